@@ -1,13 +1,205 @@
+#ifndef APPRELAY_H
+#define APPRELAY_H
+
 #include <stdarg.h>
 #include <stdbool.h>
 #include <stddef.h>
 #include <stdint.h>
 #include <stdlib.h>
+#include <sys/types.h>
+
+// Length of the SHA-256 digest returned by `request_context_fingerprint_ffi`.
+#define REQUEST_FINGERPRINT_LEN 32
+
+// The relay's response body is the encapsulated response itself.
+#define RESPONSE_FRAMING_NONE 0
+
+// The encapsulated response is prefixed by its length as a network-order 16-bit integer.
+#define RESPONSE_FRAMING_U16 1
+
+// The encapsulated response is prefixed by its length as a network-order 32-bit integer.
+#define RESPONSE_FRAMING_U32 2
+
+// Largest chunk `decapsulate_response_cb_ffi` passes to its callback at once.
+#define RESPONSE_CHUNK_LEN 16384
+
+// Default for the number of fields accepted in a single header or trailer section.
+#define BHTTP_DEFAULT_MAX_HEADERS 128
+
+// Default for the total name and value bytes accepted in a single header or trailer section.
+#define BHTTP_DEFAULT_MAX_HEADER_BYTES 65536
+
+// Flag of `build_bhttp_request_ffi` canonicalizing the header section, see [`BhttpRequest::canonical`].
+#define BHTTP_CANONICAL_HEADERS 1
+
+// DHKEM(X25519, HKDF-SHA256)
+#define KEM_X25519_SHA256 0x0020
+
+// HKDF-SHA256
+#define KDF_HKDF_SHA256 0x0001
+
+// AES-128-GCM
+#define AEAD_AES_128_GCM 0x0001
+
+// AES-256-GCM
+#define AEAD_AES_256_GCM 0x0002
+
+// ChaCha20Poly1305
+#define AEAD_CHACHA20_POLY1305 0x0003
+
+// Export-only, can not seal messages
+#define AEAD_EXPORT_ONLY 0xffff
+
+// Key configuration extension carrying the expiry of the key as a
+// big-endian, signed 64-bit Unix timestamp in seconds.
+#define KEY_CONFIG_EXTENSION_EXPIRY 0x0001
+
+// The key configuration carries an expiry that has not passed yet.
+#define KEY_CONFIG_VALID 0
+
+// The key configuration expired, fetch a fresh one before encapsulating.
+#define KEY_CONFIG_EXPIRED 1
+
+// The key configuration carries no expiry extension.
+#define KEY_CONFIG_NO_EXPIRY 2
+
+// Length of the smallest valid key configuration: the key id (1), the KEM id (2), the
+// shortest public key of a registered KEM, 32 bytes for X25519, the symmetric suites
+// length (2) and a single KDF and AEAD pair (4).
+//
+// Shorter input is rejected without parsing.
+#define MIN_KEY_CONFIG_LEN ((((1 + 2) + 32) + 2) + 4)
+
+// The configuration can not be parsed.
+#define PREFLIGHT_BAD_CONFIG 1
+
+// None of the suites of the configuration is supported by this build.
+#define PREFLIGHT_UNSUPPORTED_SUITE (1 << 1)
+
+// The message exceeds the largest plaintext the suite's AEAD can seal.
+#define PREFLIGHT_MESSAGE_TOO_LARGE (1 << 2)
+
+// Requests encapsulated in one shot, `message/ohttp-req` and `message/ohttp-res`.
+#define OHTTP_MODE_ONE_SHOT 0
+
+// Chunked requests, `message/ohttp-chunked-req` and `message/ohttp-chunked-res`.
+//
+// This library does not produce chunked requests yet, see the README.
+#define OHTTP_MODE_CHUNKED 1
+
+// [`classify_message_ffi`] found neither structure.
+#define MESSAGE_UNKNOWN 0
+
+// [`classify_message_ffi`] found the header of an encapsulated OHTTP request.
+#define MESSAGE_ENCAPSULATED_REQUEST 1
+
+// [`classify_message_ffi`] found the start of a BHTTP request or response.
+#define MESSAGE_BHTTP 2
+
+// Length of the prefix of every gRPC message, the compressed flag and the message length.
+#define GRPC_MESSAGE_PREFIX_LEN 5
+
+// Key identifier of [`DEV_KEY_CONFIG`].
+#define DEV_KEY_ID 1
+
+// Length of the private key returned by `generate_test_keypair_ffi`.
+#define TEST_PRIVATE_KEY_LEN 32
+
+// Kind of a [`ClientError`], for matching without inspecting the payload.
+//
+// The discriminants are the error codes returned by `last_error_code`, 0 means no error.
+typedef enum ClientErrorKind {
+  RequestContextInitialization = 1,
+  EncapsulationFailed = 2,
+  DecapsulationFailed = 3,
+  Ohttp = 4,
+  InvalidArgument = 5,
+  ResponseMalformed = 6,
+  MalformedConfig = 7,
+  ResponseTruncated = 8,
+  BufferTooSmall = 9,
+  SafePanic = 10,
+  JniProblem = 11,
+  ResponseEmpty = 12,
+  ResponseAuthFailed = 13,
+  UnsupportedSuite = 14,
+  ResponseFramingMismatch = 15,
+  InvalidHandle = 16,
+  ContextEmpty = 17,
+  RngUnavailable = 18,
+  SuiteMismatch = 19,
+  CallbackAborted = 20,
+  KemNotCompiled = 21,
+} ClientErrorKind;
 
 typedef struct RequestContext RequestContext;
 
 typedef struct ResponseContext ResponseContext;
 
+typedef struct BhttpResponse BhttpResponse;
+
+typedef struct DoubleRequestContext DoubleRequestContext;
+
+// Decapsulated responses returned by [`decapsulate_multiplexed_ffi`], one entry per context
+// passed, holding either the response or the reason it is missing.
+typedef struct ResponseContextArray ResponseContextArray;
+
+typedef struct ResponseDecapsulator ResponseDecapsulator;
+
+// Borrowed view of bytes owned by another object.
+//
+// The view is only valid as long as the owning object is alive.
+// Laid out as `ptr` at offset 0 followed by `len`, see [`layout::StructSizes`].
+typedef struct BytesView {
+  const uint8_t *ptr;
+  size_t len;
+} BytesView;
+
+// Everything needed to send an encapsulated request and later decapsulate its response.
+typedef struct EncapsulateResult {
+  // Context to pass to `decapsulate_response_ffi`, NULL if encapsulation failed.
+  struct RequestContext *context;
+  // The encapsulated request, owned by `context`.
+  const uint8_t *message;
+  size_t message_len;
+  // The `ClientErrorKind` of the failure, 0 on success.
+  int error_code;
+} EncapsulateResult;
+
+// Routing-relevant header of an encapsulated request.
+typedef struct OhttpHeader {
+  uint8_t key_id;
+  uint16_t kem;
+  uint16_t kdf;
+  uint16_t aead;
+  // The encapsulated key, borrowed from the context.
+  const uint8_t *enc_ptr;
+  size_t enc_len;
+} OhttpHeader;
+
+// HPKE algorithm ids of a suite as filled in by [`config_all_suites_ffi`].
+typedef struct SuiteTriple {
+  uint16_t kem;
+  uint16_t kdf;
+  uint16_t aead;
+} SuiteTriple;
+
+// Sizes, alignments and field offsets in bytes of the FFI structs of this build.
+typedef struct StructSizes {
+  // Size of `StructSizes` itself.
+  size_t struct_sizes_size;
+  size_t bytes_view_size;
+  size_t bytes_view_align;
+  size_t bytes_view_ptr_offset;
+  size_t bytes_view_len_offset;
+  size_t encapsulate_result_size;
+  size_t encapsulate_result_align;
+} StructSizes;
+
+// Receives the decapsulated response of `decapsulate_response_cb_ffi` chunk by chunk,
+// `user` being the pointer passed along. Returns `false` to abort.
+typedef bool (*ResponseChunkCallback)(const uint8_t *chunk_ptr, size_t chunk_len, void *user);
+
 #ifdef __cplusplus
 extern "C" {
 #endif // __cplusplus
@@ -30,6 +222,49 @@ uint8_t *request_context_message_ffi(struct RequestContext *context);
 // <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
 size_t request_context_message_len_ffi(struct RequestContext *context);
 
+// Returns the `Content-Length` of the POST carrying the request to the relay.
+//
+// The body of that POST is the encapsulated request and nothing else, with content type
+// `message/ohttp-req`, so this is the length of [`request_context_message_ffi`]. The BHTTP
+// message inside declares lengths of its own, which the relay never sees.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+size_t request_context_post_body_len_ffi(const struct RequestContext *context);
+
+// Writes the SHA-256 digest of the encapsulated request, [`REQUEST_FINGERPRINT_LEN`] bytes, into `out`.
+//
+// The digest is computed over the ciphertext only, so it can be logged to trace a request
+// through relays without revealing anything about its content.
+// Returns the number of bytes written, or -1 if `out` is too small.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+ssize_t request_context_fingerprint_ffi(const struct RequestContext *context,
+                                        uint8_t *out,
+                                        size_t out_cap);
+
+// Writes the plaintext message `context` was encapsulated from into `out`, to check the
+// BHTTP encoding during integration.
+//
+// **Insecure, only available in `debug-plaintext` builds which must never ship to production.**
+// Returns the number of bytes written, or -1 if `out` is too small.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+ssize_t request_context_plaintext_ffi(const struct RequestContext *context,
+                                      uint8_t *out,
+                                      size_t out_cap);
+
 // Frees up context memory. Be sure to call this in cases:
 // - after encapsulating the HTTP request was not performed
 // - the response has not been returned or is not successful
@@ -43,21 +278,77 @@ void request_context_message_drop_ffi(struct RequestContext *context);
 
 // Return a pointer to the decapsulated response.
 //
+// The pointer borrows the context and stays valid until it is dropped.
+//
 // # Safety
-// Dereferences a pointer to `RequestContext` passed by the caller.
+// Dereferences a pointer to `ResponseContext` passed by the caller.
 // Be sure that the context has not been yet freed and that you are using a valid pointer.
 //
 // <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
 uint8_t *response_context_message_ffi(struct ResponseContext *context);
 
-// Return size in bytes of the decapsulated response.
+// Return size in bytes of the decapsulated response, without consuming the context.
 //
 // # Safety
-// Dereferences a pointer to `RequestContext` passed by the caller.
+// Dereferences a pointer to `ResponseContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+size_t response_context_message_len_ffi(const struct ResponseContext *context);
+
+// Copies the decapsulated response into `out`, leaving the context untouched.
+//
+// Returns the number of bytes written, or -1 if `out` is smaller than
+// `response_context_message_len_ffi` reports.
+//
+// # Safety
+// Dereferences a pointer to `ResponseContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+ssize_t response_context_copy_ffi(const struct ResponseContext *context,
+                                  uint8_t *out,
+                                  size_t out_cap);
+
+// Frees up the decapsulated response once it has been read.
+//
+// # Safety
+// Dereferences a pointer to `ResponseContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+void response_context_drop_ffi(struct ResponseContext *context);
+
+// Consumes `context` and hands its decapsulated response over to the caller, writing
+// its length to `*out_len`, instead of copying it out with [`response_context_copy_ffi`].
+//
+// Free the bytes with [`split::apprelay_free_bytes_ffi`] and that length, the context must
+// not be used or freed afterwards. The buffer is first shrunk to the length of the
+// response, which allocators usually do in place.
+//
+// This function will return a NULL pointer, leaving `context` untouched, if `out_len` is NULL.
+//
+// **Deprecated**, read the response in place with [`response_context_message_ffi`] or copy
+// it with [`response_context_copy_ffi`]. Both borrow the context, while a context used after
+// this call reads freed memory.
+//
+// # Safety
+// Dereferences a pointer to `ResponseContext` passed by the caller.
 // Be sure that the context has not been yet freed and that you are using a valid pointer.
 //
 // <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
-size_t response_context_message_len_ffi(struct ResponseContext *context);
+uint8_t *response_context_take_bytes_ffi(struct ResponseContext *context, size_t *out_len);
+
+// Wraps an already decapsulated response in a `ResponseContext`, to exercise
+// `parse_bhttp_response_ffi` and the response accessors without a crypto round trip.
+//
+// **Test helper, only available in `testutil` builds and not meant for production.**
+// The bytes are copied, free the context with [`response_context_drop_ffi`].
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct ResponseContext *response_context_from_plaintext_ffi(const uint8_t *plaintext_ptr,
+                                                            size_t plaintext_len);
 
 // Encapsulates the provided `encoded_msg` using `encoded_config` and returns
 // a context used for decapsulating the corresponding response.
@@ -76,9 +367,119 @@ struct RequestContext *encapsulate_request_ffi(const uint8_t *encoded_config_ptr
                                                const uint8_t *encoded_msg_ptr,
                                                size_t encoded_msg_len);
 
+// Encapsulates the provided `encoded_msg` like [`encapsulate_request_ffi`], after padding
+// it with zeros to the next multiple of `bucket_size` bytes.
+//
+// Padding with zeros is the BHTTP padding mechanism, so the gateway discards it when
+// decoding the message. Requests grow by at most `bucket_size - 1` bytes, and since the
+// encapsulation overhead is constant for a given configuration, all messages from the
+// same bucket produce encapsulated requests of the same size.
+//
+// This function will return a NULL pointer if `bucket_size` is 0 or encapsulation fails.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct RequestContext *encapsulate_request_padded_ffi(const uint8_t *encoded_config_ptr,
+                                                      size_t encoded_config_len,
+                                                      const uint8_t *encoded_msg_ptr,
+                                                      size_t encoded_msg_len,
+                                                      size_t bucket_size);
+
+// Encapsulates the provided `encoded_msg` like [`encapsulate_request_ffi`], with the suite of
+// `encoded_config` using `kdf` and `aead` instead of the first supported one, so a client
+// policy, e.g. ChaCha20Poly1305 only on hardware without AES instructions, overrides the
+// order of the configuration.
+//
+// This function will return a NULL pointer with `UnsupportedSuite` if the configuration
+// does not offer the suite or this build does not support it, or if encapsulation fails.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct RequestContext *encapsulate_request_with_suite_ffi(const uint8_t *encoded_config_ptr,
+                                                          size_t encoded_config_len,
+                                                          const uint8_t *encoded_msg_ptr,
+                                                          size_t encoded_msg_len,
+                                                          uint16_t kdf,
+                                                          uint16_t aead);
+
+// Encapsulates `original_msg` again for `new_config`, to retry an exchange the gateway
+// rejected because it rotated its key after the configuration was fetched.
+//
+// Behaves like [`encapsulate_request_ffi`]. Contexts do not keep the message they were
+// encapsulated from, so the caller has to supply the same BHTTP message again; the
+// encoding does not depend on the configuration and needs no rebuilding. In
+// `debug-plaintext` builds it can be copied out of the failed context with
+// `request_context_plaintext_ffi`, before the context is decapsulated or dropped.
+//
+// This function will return a NULL pointer if the configuration is invalid or
+// encapsulation fails.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct RequestContext *reencapsulate_ffi(const uint8_t *new_config_ptr,
+                                         size_t new_config_len,
+                                         const uint8_t *original_msg_ptr,
+                                         size_t original_msg_len);
+
+// Fills `out` with the key id, the suite and the encapsulated key (`enc`) of the request
+// held by `context`.
+//
+// `enc_ptr` points into the encapsulated request owned by `context`, so it stays valid
+// until `context` is decapsulated, refilled or dropped.
+//
+// Returns 0 on success, or -1 if the context is not live or holds no request.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+int request_context_header_ffi(const struct RequestContext *context,
+                               struct OhttpHeader *out);
+
+// Encapsulates the provided `encoded_msg` like [`encapsulate_request_ffi`] and returns the
+// context together with the encapsulated request it owns.
+//
+// `message` stays valid until `context` is decapsulated or dropped. On failure `context`
+// and `message` are NULL and `error_code` holds the code also returned by `last_error_code`.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct EncapsulateResult encapsulate_request_full_ffi(const uint8_t *encoded_config_ptr,
+                                                      size_t encoded_config_len,
+                                                      const uint8_t *encoded_msg_ptr,
+                                                      size_t encoded_msg_len);
+
+// Creates an empty context to be filled by [`encapsulate_into_context_ffi`], for pools reusing
+// contexts across requests.
+//
+// Decapsulating a response empties such a context instead of freeing it, free it with
+// `request_context_message_drop_ffi` when tearing the pool down.
+struct RequestContext *request_context_new_ffi(void);
+
+// Encapsulates the provided `encoded_msg` using `encoded_config` into `context`,
+// discarding any request it held before.
+//
+// Returns 0 on success, or -1 if encapsulation fails, leaving `context` empty.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+int encapsulate_into_context_ffi(struct RequestContext *context,
+                                 const uint8_t *encoded_config_ptr,
+                                 size_t encoded_config_len,
+                                 const uint8_t *encoded_msg_ptr,
+                                 size_t encoded_msg_len);
+
 // Decapsulates the provided `encapsulated_response` using `context`.
 //
 // This function will return a NULL pointer if decapsulation fails.
+// An empty response fails with `ResponseEmpty` before `context` is consumed,
+// so the caller may retry the exchange with it or has to free it.
+// Contexts created by [`request_context_new_ffi`] are emptied instead of freed,
+// ready for [`encapsulate_into_context_ffi`].
 //
 // # Safety
 // Dereferences a pointer to `RequestContext` passed by the caller.
@@ -89,8 +490,77 @@ struct ResponseContext *decapsulate_response_ffi(struct RequestContext *context,
                                                  const uint8_t *encapsulated_response_ptr,
                                                  size_t encapsulated_response_len);
 
+// Decapsulates a response some relays send with a length prefix, see [`RESPONSE_FRAMING_U16`]
+// and [`RESPONSE_FRAMING_U32`]. [`RESPONSE_FRAMING_NONE`] behaves like [`decapsulate_response_ffi`].
+//
+// This function will return a NULL pointer if the framing is unknown or does not match the
+// response, before `context` is consumed, or if decapsulation fails.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct ResponseContext *decapsulate_response_framed_ffi(struct RequestContext *context,
+                                                        const uint8_t *framed_ptr,
+                                                        size_t framed_len,
+                                                        int framing);
+
+// Decapsulates the provided `encapsulated_response` like [`decapsulate_response_ffi`], but
+// hands the response to `callback` in chunks of at most [`RESPONSE_CHUNK_LEN`] bytes
+// instead of returning a `ResponseContext`.
+//
+// The response is authenticated as a whole before the first chunk is passed, so the
+// callback never sees unauthenticated bytes; an empty response produces no call. The
+// chunks are borrowed for the duration of the call only.
+//
+// Returns 0 once every chunk was passed, or -1 if decapsulation fails or `callback`
+// returns `false`, which stops further calls and fails with `CallbackAborted`. `context`
+// is consumed either way, except for an empty `encapsulated_response` which fails with
+// `ResponseEmpty` first. The plaintext is freed before returning.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+int decapsulate_response_cb_ffi(struct RequestContext *context,
+                                const uint8_t *encapsulated_response_ptr,
+                                size_t encapsulated_response_len,
+                                ResponseChunkCallback callback,
+                                void *user);
+
+// Initializes the crypto backend of `ohttp` once per process.
+//
+// The rust-hpke backend this library is built with needs no initialization, so calling the
+// other functions first is allowed and this only returns 0. Backends such as NSS require it
+// to run before any other function, embedders should call it at startup to stay portable.
+// Calling it again, from any thread, does nothing and returns 0.
+int apprelay_init_ffi(void);
+
+// Encapsulates an empty message to a throwaway configuration, so the first real request
+// does not pay for opening the system random number generator and faulting in the X25519
+// and AEAD code.
+//
+// Returns 0 once a warmup succeeded, later calls return 0 right away. Returns -1 if the
+// throwaway encapsulation fails, e.g. with `RngUnavailable`, and tries again on the next
+// call. Concurrent first calls may each encapsulate once, which is harmless.
+int apprelay_warmup_ffi(void);
+
+// Tears down the crypto backend at process exit, a no-op with the rust-hpke backend.
+//
+// No other function may be called afterwards on backends that require [`apprelay_init_ffi`].
+void apprelay_shutdown_ffi(void);
+
 void initialize_logging(void);
 
+// Return the [`ClientErrorKind`](crate::ClientErrorKind) code of the most recent error,
+// 0 if there is none or -1 for an error of another type passed to `update_last_error` by
+// the embedding Rust code.
+//
+// Unlike `last_error_message` this does not clear the error.
+int last_error_code(void);
+
 // Return the number of bytes in the last error message.
 // Does not include any trailing null terminators.
 int last_error_length(void);
@@ -108,6 +578,836 @@ int last_error_length(void);
 int last_error_message(char *buffer,
                        int length);
 
+// Write the `Debug` form of the most recent error, NUL terminated, into `out`.
+//
+// Unlike the message written by `last_error_message` it includes the structure of the
+// source error, e.g. the `ohttp::Error` variant behind `EncapsulationFailed`, for debugging
+// failures the message does not explain. The error is not cleared.
+//
+// Returns the number of bytes written without the NUL terminator, 0 if there is no error,
+// or -1 if `out` is a null pointer or too small, leaving the error untouched.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+ssize_t last_error_debug_ffi(char *out,
+                             size_t out_cap);
+
+// Encodes a known-length BHTTP request into `out`, to pass to `encapsulate_request_ffi`.
+//
+// `method`, `scheme`, `authority` and `path` are NUL terminated strings, `header_names` and
+// `header_values` arrays of `header_count` NUL terminated strings each. `body_ptr` may be
+// NULL if `body_len` is 0, for requests without a body, see [`BhttpRequest::encode`]. With
+// [`BHTTP_CANONICAL_HEADERS`] in `flags` the header section is lowercased and sorted, which
+// some order-sensitive origins do not accept, so it is opt-in.
+//
+// Returns the number of bytes written, or -1 if a string is NULL or not valid UTF-8 or `out`
+// is too small.
+//
+// # Safety
+// Strings must be NUL terminated, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+// The invariants of the arrays and the body are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t build_bhttp_request_ffi(const char *method,
+                                const char *scheme,
+                                const char *authority,
+                                const char *path,
+                                const char *const *header_names,
+                                const char *const *header_values,
+                                size_t header_count,
+                                const uint8_t *body_ptr,
+                                size_t body_len,
+                                int flags,
+                                uint8_t *out,
+                                size_t out_cap);
+
+// Parses the decapsulated response as a known-length BHTTP response.
+//
+// This function will return a NULL pointer if the response is malformed.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct BhttpResponse *parse_bhttp_response_ffi(const uint8_t *response_ptr,
+                                               size_t response_len);
+
+// Bounds every header and trailer section accepted by `parse_bhttp_response_ffi` to
+// `max_headers` fields and `max_total_bytes` bytes of names and values.
+//
+// Responses exceeding either limit fail with `ResponseMalformed`. The limits apply to all
+// threads and default to [`BHTTP_DEFAULT_MAX_HEADERS`] and [`BHTTP_DEFAULT_MAX_HEADER_BYTES`].
+void set_bhttp_header_limit_ffi(size_t max_headers,
+                                size_t max_total_bytes);
+
+// Bounds the content accepted by `parse_bhttp_response_ffi` to `bytes`.
+//
+// Responses declaring or carrying more content fail with `ResponseMalformed`, without
+// allocating for the declared length. The limit applies to all threads, by default the
+// content is only bounded by the size of the decapsulated response.
+void set_max_response_body_ffi(size_t bytes);
+
+// Frees up the parsed response.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+void bhttp_response_drop_ffi(struct BhttpResponse *response);
+
+// Return the status code of the final response, or 0 if `response` is NULL.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+uint16_t bhttp_response_status_ffi(const struct BhttpResponse *response);
+
+// Return the number of header fields.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+size_t bhttp_response_header_count_ffi(const struct BhttpResponse *response);
+
+// Return the name of the header field at `index`.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_header_name_ffi(const struct BhttpResponse *response,
+                                                size_t index);
+
+// Return the value of the header field at `index`.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_header_value_ffi(const struct BhttpResponse *response,
+                                                 size_t index);
+
+// Return the response content.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_body_ffi(const struct BhttpResponse *response);
+
+// Return the number of trailer fields, 0 if the response has no trailers.
+//
+// Trailers are never merged into the header fields.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+size_t bhttp_response_trailer_count_ffi(const struct BhttpResponse *response);
+
+// Return the name of the trailer field at `index`.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_trailer_name_ffi(const struct BhttpResponse *response,
+                                                 size_t index);
+
+// Return the value of the trailer field at `index`.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_trailer_value_ffi(const struct BhttpResponse *response,
+                                                  size_t index);
+
+// Return the number of informational (1xx) responses preceding the final response,
+// 0 if there were none.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+size_t bhttp_response_informational_count_ffi(const struct BhttpResponse *response);
+
+// Return the status code of the informational response at `index`, or 0 if `index` is
+// out of range.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+uint16_t bhttp_response_informational_status_ffi(const struct BhttpResponse *response,
+                                                 size_t index);
+
+// Return the number of header fields of the informational response at `index`, or 0 if
+// `index` is out of range.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+size_t bhttp_response_informational_header_count_ffi(const struct BhttpResponse *response,
+                                                     size_t index);
+
+// Return the name of the header field at `field_index` of the informational response at
+// `index`.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_informational_header_name_ffi(const struct BhttpResponse *response,
+                                                              size_t index,
+                                                              size_t field_index);
+
+// Return the value of the header field at `field_index` of the informational response at
+// `index`.
+//
+// The view borrows the response and is valid until the response is dropped.
+//
+// # Safety
+// Dereferences a pointer to `BhttpResponse` passed by the caller.
+// Be sure that the response has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView bhttp_response_informational_header_value_ffi(const struct BhttpResponse *response,
+                                                               size_t index,
+                                                               size_t field_index);
+
+// Checks the expiry extension of a key configuration against `now_unix` (seconds since the Unix epoch).
+//
+// Returns [`KEY_CONFIG_VALID`], [`KEY_CONFIG_EXPIRED`], [`KEY_CONFIG_NO_EXPIRY`],
+// or -1 if the configuration can not be parsed.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+int key_config_is_expired_ffi(const uint8_t *config_ptr,
+                              size_t config_len,
+                              int64_t now_unix);
+
+// Assembles an encoded key configuration offering a single `kdf`/`aead` suite from
+// the raw HPKE `public_key` of a gateway, and writes it into `out`.
+//
+// Returns the number of bytes written, or -1 if the public key length does not match
+// `kem`, the KEM is unknown or `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t build_key_config_ffi(uint8_t key_id,
+                             uint16_t kem,
+                             const uint8_t *public_key_ptr,
+                             size_t public_key_len,
+                             uint16_t kdf,
+                             uint16_t aead,
+                             uint8_t *out,
+                             size_t out_cap);
+
+// Checks whether encapsulating a message of `msg_len` bytes with the configuration would
+// fail, without encapsulating anything.
+//
+// Returns 0 if encapsulation is expected to succeed, otherwise a bitmask of:
+// - [`PREFLIGHT_BAD_CONFIG`] when the configuration can not be parsed, no other checks are done then.
+// - [`PREFLIGHT_UNSUPPORTED_SUITE`] when no suite of the configuration is supported by this build.
+// - [`PREFLIGHT_MESSAGE_TOO_LARGE`] when the message exceeds the AEAD plaintext limit.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+int preflight_encapsulate_ffi(const uint8_t *config_ptr,
+                              size_t config_len,
+                              size_t msg_len);
+
+// Writes a NUL terminated description of the suite such as
+// `DHKEM(X25519, HKDF-SHA256) / HKDF-SHA256 / AES-128-GCM` into `out`.
+//
+// Returns the length of the description without the NUL terminator, or -1 if any of
+// the ids is not registered or `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+ssize_t suite_description_ffi(uint16_t kem,
+                              uint16_t kdf,
+                              uint16_t aead,
+                              char *out,
+                              size_t out_cap);
+
+// Writes a NUL terminated report of every problem found in the key configuration into `out`,
+// one line per problem, see [`KeyConfig::diagnose`].
+//
+// Returns the length of the report without the NUL terminator, 0 if the configuration has
+// no problems, or -1 if `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t diagnose_key_config_ffi(const uint8_t *config_ptr,
+                                size_t config_len,
+                                char *out,
+                                size_t out_cap);
+
+// Returns [`MIN_KEY_CONFIG_LEN`], the length below which a key configuration can not be
+// valid, for a cheap check of untrusted input before parsing it.
+size_t min_key_config_len_ffi(void);
+
+// Returns the length of the encapsulated key (`enc`) that prefixes requests sealed with `kem`,
+// e.g. 32 for [`KEM_X25519_SHA256`], or -1 if the KEM is not registered.
+ssize_t kem_enc_len_ffi(uint16_t kem);
+
+// Returns the length of the request [`crate::encapsulate_request_ffi`] produces for a message
+// of `msg_len` bytes with the configuration, or -1 if the configuration can not be parsed,
+// none of its suites is supported or the message exceeds the AEAD plaintext limit.
+//
+// The length depends on the KEM and the AEAD of the selected suite, so size buffers with
+// this function rather than a fixed overhead.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t encapsulated_request_len_ffi(const uint8_t *config_ptr,
+                                     size_t config_len,
+                                     size_t msg_len);
+
+// Whether this build can encapsulate with the `kem`/`kdf`/`aead` suite, the same check
+// used to select a suite from a key configuration.
+//
+// Returns false for unknown ids.
+bool is_suite_supported_ffi(uint16_t kem,
+                            uint16_t kdf,
+                            uint16_t aead);
+
+// Merges two encoded `application/ohttp-keys` lists into `out`, see
+// [`merge_key_config_lists`] for how duplicates are handled.
+//
+// Returns the number of bytes written, or -1 if either list is malformed, they hold
+// conflicting configurations for a key id or `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t key_config_list_merge_ffi(const uint8_t *a_ptr,
+                                  size_t a_len,
+                                  const uint8_t *b_ptr,
+                                  size_t b_len,
+                                  uint8_t *out,
+                                  size_t out_cap);
+
+// Translates an encoded RFC 9230 `ObliviousDoHConfigs` list into an OHTTP key configuration
+// served by the gateway under `key_id`, and writes it into `out`.
+//
+// See [`KeyConfig::from_odoh`] for the field mapping. Returns the number of bytes written,
+// or -1 if the ODoH configurations are malformed, none has a supported version, the AEAD is
+// export-only or `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t odoh_config_to_ohttp_ffi(const uint8_t *odoh_config_ptr,
+                                 size_t odoh_config_len,
+                                 uint8_t key_id,
+                                 uint8_t *out,
+                                 size_t out_cap);
+
+// Parses the key configuration at the start of `ptr`, copies its encoding into `out_config`
+// and stores the number of bytes it occupies in `out_consumed`, so configurations
+// concatenated without length prefixes can be walked by advancing by that count.
+//
+// See [`KeyConfig::decode_prefix`], extension blocks are not consumed. Returns 0 on success,
+// or -1 if the buffer ends mid-configuration, the configuration is malformed or
+// `out_config` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+// and `out_consumed` must be valid for writes.
+int key_config_parse_one_ffi(const uint8_t *ptr,
+                             size_t len,
+                             uint8_t *out_config,
+                             size_t out_cap,
+                             size_t *out_consumed);
+
+// Extracts the key configuration from the value of an `ohttp` SvcParam of a DNS SVCB or
+// HTTPS record into `out`, see [`key_config_from_svcb_param`].
+//
+// Returns the number of bytes written, or -1 if the parameter is empty or malformed, none
+// of its configurations is supported or `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t key_config_from_svcb_param_ffi(const uint8_t *param_ptr,
+                                       size_t param_len,
+                                       uint8_t *out,
+                                       size_t out_cap);
+
+// Returns the number of symmetric suites, (KDF, AEAD) pairs, the key configuration
+// offers, or -1 if it is malformed.
+//
+// Encapsulation uses the first of them this build supports, see
+// [`KeyConfig::select_supported_suite`].
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t config_symmetric_suite_count_ffi(const uint8_t *config_ptr,
+                                         size_t config_len);
+
+// Writes the KDF and AEAD ids of the symmetric suite at `index` of the key configuration
+// into `out_kdf` and `out_aead`, in the gateway's order of preference.
+//
+// Returns 1 if this build supports the suite, 0 if it does not, or -1 if the configuration
+// is malformed or `index` is out of range, leaving the outputs untouched.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+// and `out_kdf` and `out_aead` must be valid for writes.
+int config_symmetric_suite_ffi(const uint8_t *config_ptr,
+                               size_t config_len,
+                               size_t index,
+                               uint16_t *out_kdf,
+                               uint16_t *out_aead);
+
+// Writes every (KEM, KDF, AEAD) suite the key configuration offers into `out`, in the
+// gateway's order of preference, for clients negotiating or reporting capabilities.
+//
+// A configuration has a single KEM, so there is one suite per symmetric suite, including
+// those this build does not support, see [`HpkeSuite::is_supported`].
+//
+// Returns the number of suites written, or -1 if the configuration is malformed or `out`
+// can not hold all of them, in which case `config_symmetric_suite_count_ffi` tells the size
+// to retry with.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+// and `out` must be valid for writes of `out_cap` suites.
+ssize_t config_all_suites_ffi(const uint8_t *config_ptr,
+                              size_t config_len,
+                              struct SuiteTriple *out,
+                              size_t out_cap);
+
+// Assembles an encoded key configuration offering a single `kdf`/`aead` suite from the
+// NUL terminated JSON Web Key `jwk_json` of a gateway, and writes it into `out`.
+//
+// See [`key_config_from_jwk`] for the accepted keys. Returns the number of bytes written,
+// or -1 if the JWK is malformed, its curve matches no supported KEM, it holds a private key
+// or `out` is too small.
+//
+// # Safety
+// `jwk_json` must be NUL terminated, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+// The invariants of `out` are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+ssize_t key_config_from_jwk_ffi(const char *jwk_json,
+                                uint8_t key_id,
+                                uint16_t kdf,
+                                uint16_t aead,
+                                uint8_t *out,
+                                size_t out_cap);
+
+// Writes the NUL terminated JSON log record of `context` into `out`, see [`log_record`].
+//
+// Returns the length of the record without the NUL terminator, or -1 if the context is
+// not live, holds no request or `out` is too small.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+ssize_t request_context_log_record_ffi(const struct RequestContext *context,
+                                       char *out,
+                                       size_t out_cap);
+
+// Writes the layout of the FFI structs of this build into `out`.
+//
+// # Safety
+// Dereferences a pointer to `StructSizes` passed by the caller.
+// Be sure that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+void apprelay_struct_sizes_ffi(struct StructSizes *out);
+
+// Returns the NUL terminated content type to POST requests encapsulated in `mode` with,
+// [`OHTTP_MODE_ONE_SHOT`] or [`OHTTP_MODE_CHUNKED`].
+//
+// The string is static and must not be freed. Returns a NULL pointer for an unknown mode.
+const char *expected_content_type_for_mode_ffi(int mode);
+
+// Checks that the `Content-Type` header value of the relay's response matches the response
+// media type of `mode`, before decapsulating it.
+//
+// Returns false on a mismatch, and also if `header_value` is NULL or not valid UTF-8 or the
+// mode is unknown, in which case the last error is set.
+//
+// # Safety
+// The invariants are described here [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety)
+bool check_response_content_type_ffi(const char *header_value,
+                                     int mode);
+
+// Returns 1 if the relay's response with status `outer_status` and `Content-Type` header
+// value `content_type` carries an encapsulated response, 0 if it is an error of the relay
+// or gateway to surface as such instead of decapsulating it. Pass NULL for `content_type`
+// if the header is absent.
+//
+// Returns -1 and sets the last error if `content_type` is not valid UTF-8.
+//
+// # Safety
+// The invariants are described here [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety)
+int should_decapsulate_ffi(uint16_t outer_status, const char *content_type);
+
+// Returns [`MESSAGE_ENCAPSULATED_REQUEST`], [`MESSAGE_BHTTP`] or [`MESSAGE_UNKNOWN`] for the
+// `len` bytes at `ptr`, see [`classify_message`]. The result is a best-effort guess, not a
+// security boundary.
+//
+// Returns -1 if `ptr` is NULL.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+int classify_message_ffi(const uint8_t *ptr, size_t len);
+
+// Encapsulates a unary gRPC call of the method at the NUL terminated `path` carrying the
+// serialized `message`, like [`crate::encapsulate_request_ffi`].
+//
+// The BHTTP request is a `POST` with `content-type: application/grpc` and `te: trailers`,
+// and the message gets the 5 byte gRPC prefix. The authority is left empty for the gateway
+// to resolve. The gRPC status arrives in the trailers of the decapsulated BHTTP response.
+//
+// This function will return a NULL pointer if `path` is not valid UTF-8 or does not start
+// with `/`, or if encapsulation fails.
+//
+// # Safety
+// `path` must point to a NUL terminated string, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct RequestContext *encapsulate_grpc_request_ffi(const uint8_t *encoded_config_ptr,
+                                                    size_t encoded_config_len,
+                                                    const char *path,
+                                                    const uint8_t *message_ptr,
+                                                    size_t message_len);
+
+// Delays every decapsulated response returned over FFI by a random time between `min_ms`
+// and `max_ms` milliseconds, blocking the calling thread. Both 0, the default, disables
+// the delay.
+//
+// The delay only hides the arrival time of responses from what the application does with
+// them, the network timing seen by the relay is unchanged. Builds for
+// wasm32-unknown-unknown can not block and skip it.
+//
+// Returns 0 on success, or -1 if `min_ms` exceeds `max_ms`.
+int set_response_jitter_ffi(uint32_t min_ms, uint32_t max_ms);
+
+// Writes the development key configuration into `out`.
+//
+// FOR DEVELOPMENT ONLY, the matching private key is public.
+//
+// Returns the number of bytes written, or -1 if `out` is NULL or smaller than the configuration.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+ssize_t apprelay_dev_config_ffi(uint8_t *out,
+                                size_t out_cap);
+
+// Writes a NUL terminated shell command replaying the request of `context` to the NUL
+// terminated `gateway_url` with curl into `out`, see [`curl_command`].
+//
+// FOR DEBUGGING ONLY, the command contains the complete encapsulated request.
+//
+// Returns the length of the command without the NUL terminator, or -1 if `gateway_url` is
+// not valid UTF-8, the context is not live or `out` is too small.
+//
+// # Safety
+// Dereferences a pointer to `RequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+ssize_t request_context_as_curl_ffi(const struct RequestContext *context,
+                                    const char *gateway_url,
+                                    char *out,
+                                    size_t out_cap);
+
+// Decodes the NUL terminated hex dump `hex` into `out`, see [`from_hex`] for the accepted
+// format.
+//
+// Returns the number of bytes written, or -1 if `hex` has an odd number of digits or a
+// character other than a hex digit or whitespace, whose position the last error names,
+// or if `out` is too small.
+//
+// # Safety
+// `hex` must be NUL terminated, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+// The invariants of `out` are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+ssize_t hex_to_bytes_ffi(const char *hex,
+                         uint8_t *out,
+                         size_t out_cap);
+
+// Writes the NUL terminated lowercase hex encoding of `ptr` into `out`, which needs
+// `2 * len + 1` bytes.
+//
+// Returns the length of the encoding without the NUL terminator, or -1 if `out` is too small.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+ssize_t bytes_to_hex_ffi(const uint8_t *ptr,
+                         size_t len,
+                         char *out,
+                         size_t out_cap);
+
+// Returns the heap bytes held by the `RequestContext` and `ResponseContext` objects that
+// have not been consumed or freed yet, the struct itself plus its buffers.
+//
+// A value that keeps growing while the number of requests in flight is stable points to
+// contexts that are never freed. Only available in `diagnostics` builds.
+size_t apprelay_live_context_bytes_ffi(void);
+
+// Returns the number of `RequestContext` handles that have not been consumed or freed yet,
+// pooled contexts included. Only available in `diagnostics` builds.
+size_t apprelay_debug_live_handle_count_ffi(void);
+
+// Writes the live `RequestContext` handles, as addresses in ascending order, into `out`
+// to spot the ones a host application never frees. Only available in `diagnostics` builds.
+//
+// The list is a snapshot taken under the lock contexts are registered and freed under, so
+// it is consistent even while other threads create or free contexts, but may be outdated
+// as soon as it is returned.
+//
+// Returns the number of handles written, or -1 if `out` can not hold all of them, in which
+// case `apprelay_debug_live_handle_count_ffi` tells the size to retry with.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+ssize_t apprelay_debug_live_handles_ffi(uint64_t *out,
+                                        size_t out_cap);
+
+// Encapsulates the provided `encoded_msg` for the gateway using `inner_config`, and the
+// resulting encapsulated request for the first relay using `outer_config`.
+//
+// This function will return a NULL pointer if either configuration is invalid or
+// encapsulation fails.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+struct DoubleRequestContext *encapsulate_double_ffi(const uint8_t *inner_config_ptr,
+                                                    size_t inner_config_len,
+                                                    const uint8_t *outer_config_ptr,
+                                                    size_t outer_config_len,
+                                                    const uint8_t *encoded_msg_ptr,
+                                                    size_t encoded_msg_len);
+
+// Return the doubly encapsulated request to send to the first relay.
+//
+// The view borrows the context and is valid until the context is consumed or dropped.
+//
+// # Safety
+// Dereferences a pointer to `DoubleRequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct BytesView double_request_context_message_ffi(const struct DoubleRequestContext *context);
+
+// Decapsulates both layers of the first relay's response, consuming `context`.
+//
+// This function will return a NULL pointer if decapsulating either layer fails.
+// An empty or NULL response fails before `context` is consumed, the former with `ResponseEmpty`.
+//
+// # Safety
+// Dereferences a pointer to `DoubleRequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct ResponseContext *decapsulate_double_response_ffi(struct DoubleRequestContext *context,
+                                                        const uint8_t *encapsulated_response_ptr,
+                                                        size_t encapsulated_response_len);
+
+// Frees up a double request context that is not going to be decapsulated.
+//
+// # Safety
+// Dereferences a pointer to `DoubleRequestContext` passed by the caller.
+// Be sure that the context has not been yet freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+void double_request_context_drop_ffi(struct DoubleRequestContext *context);
+
+// Decapsulates the responses a relay multiplexed into `body`, framed as described in the
+// [module documentation](self), opening response `i` with `contexts[i]`.
+//
+// Contexts are consumed like by [`decapsulate_response_ffi`](crate::decapsulate_response_ffi)
+// when their response is present, whether it decapsulates or not. A context whose response
+// is missing, because the body holds fewer responses or its final one is truncated, is left
+// untouched for the caller to decapsulate once the rest arrives or to free. The array
+// records the outcome per context, see [`response_context_array_error_ffi`].
+//
+// This function will return a NULL pointer, before any context is consumed, if `body` holds
+// more responses than `contexts_len`.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety),
+// `contexts` must point to `contexts_len` contexts that have not been freed yet.
+struct ResponseContextArray *decapsulate_multiplexed_ffi(struct RequestContext *const *contexts,
+                                                         size_t contexts_len,
+                                                         const uint8_t *body_ptr,
+                                                         size_t body_len);
+
+// Returns the number of entries of `array`, the number of contexts passed to
+// [`decapsulate_multiplexed_ffi`].
+//
+// # Safety
+// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+// Be sure that the array has not been yet freed and that you are using a valid pointer.
+size_t response_context_array_len_ffi(const struct ResponseContextArray *array);
+
+// Returns the error code of entry `index`, 0 if its response was decapsulated.
+//
+// An entry whose response is missing from the body reports `ResponseTruncated` if the final
+// response was cut short and `InvalidArgument` otherwise, its context was not consumed.
+// Returns -1 if `index` is out of range.
+//
+// # Safety
+// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+// Be sure that the array has not been yet freed and that you are using a valid pointer.
+int response_context_array_error_ffi(const struct ResponseContextArray *array, size_t index);
+
+// Returns the response of entry `index`, borrowed from `array`: read it with the
+// `response_context_*` accessors but never free it with `response_context_drop_ffi`.
+//
+// This function will return a NULL pointer if `index` is out of range or the entry holds
+// no response, see [`response_context_array_error_ffi`].
+//
+// # Safety
+// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+// Be sure that the array has not been yet freed and that you are using a valid pointer.
+struct ResponseContext *response_context_array_get_ffi(struct ResponseContextArray *array,
+                                                       size_t index);
+
+// Frees `array` along with the responses it holds.
+//
+// # Safety
+// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+// Be sure that the array has not been yet freed and that you are using a valid pointer.
+void response_context_array_drop_ffi(struct ResponseContextArray *array);
+
+// Encapsulates the provided `encoded_msg` using `encoded_config`, handing the request bytes
+// and the state decapsulating its response to the caller separately.
+//
+// On success `*out_req_bytes` and `*out_req_len` describe the encapsulated request, to be
+// freed with [`apprelay_free_bytes_ffi`], and `*out_decapsulator` the state to pass to
+// [`response_decapsulator_decapsulate_ffi`] or free with [`response_decapsulator_drop_ffi`].
+//
+// Returns 0 on success, or -1 if encapsulation fails, leaving the outputs untouched.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+// and the output pointers must be valid for writes.
+int encapsulate_split_ffi(const uint8_t *encoded_config_ptr,
+                          size_t encoded_config_len,
+                          const uint8_t *encoded_msg_ptr,
+                          size_t encoded_msg_len,
+                          uint8_t **out_req_bytes,
+                          size_t *out_req_len,
+                          struct ResponseDecapsulator **out_decapsulator);
+
+// Frees request bytes returned by [`encapsulate_split_ffi`] or response bytes returned by
+// [`response_context_take_bytes_ffi`](crate::response_context_take_bytes_ffi), `len` being
+// the length returned along with them.
+//
+// # Safety
+// `bytes` and `len` must come from a single successful call of either function,
+// and `bytes` must not be used or freed again afterwards.
+void apprelay_free_bytes_ffi(uint8_t *bytes,
+                             size_t len);
+
+// Decapsulates the provided `encapsulated_response` with the state returned by
+// [`encapsulate_split_ffi`], consuming it.
+//
+// This function will return a NULL pointer if decapsulation fails. An empty response fails
+// with `ResponseEmpty` before `decapsulator` is consumed, so the caller may retry the
+// exchange with it or has to free it.
+//
+// # Safety
+// Dereferences a pointer to `ResponseDecapsulator` passed by the caller.
+// Be sure that it has not been yet consumed or freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+struct ResponseContext *response_decapsulator_decapsulate_ffi(struct ResponseDecapsulator *decapsulator,
+                                                              const uint8_t *encapsulated_response_ptr,
+                                                              size_t encapsulated_response_len);
+
+// Frees up decapsulation state returned by [`encapsulate_split_ffi`] whose response is
+// not going to be decapsulated.
+//
+// # Safety
+// Dereferences a pointer to `ResponseDecapsulator` passed by the caller.
+// Be sure that it has not been yet consumed or freed and that you are using a valid pointer.
+//
+// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+void response_decapsulator_drop_ffi(struct ResponseDecapsulator *decapsulator);
+
+// Reassembles a `RequestContext` from an encapsulated request delivered as two fields,
+// `header` holding the key id, the suite and `enc` and `ciphertext` the rest, and the
+// `decapsulator` returned along with the request by [`encapsulate_split_ffi`].
+//
+// The HPKE state needed for the response can not be derived from the request bytes, so the
+// context takes over `decapsulator` and is used like any other, e.g. with
+// `decapsulate_response_ffi`.
+//
+// This function will return a NULL pointer if `encoded_config` is invalid, if `header` is
+// not as long as the KEM's `enc` requires or names another key id or suite than
+// `encoded_config` and `decapsulator`. `decapsulator` is only consumed on success.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety).
+// `decapsulator` must not have been consumed or freed yet.
+struct RequestContext *request_context_from_parts_ffi(const uint8_t *encoded_config_ptr,
+                                                      size_t encoded_config_len,
+                                                      const uint8_t *header_ptr,
+                                                      size_t header_len,
+                                                      const uint8_t *ciphertext_ptr,
+                                                      size_t ciphertext_len,
+                                                      struct ResponseDecapsulator *decapsulator);
+
+// Does nothing but warn like a deprecated function, to check where the warnings of a build
+// end up.
+//
+// **Test helper, only available in `testutil` builds and not meant for production.**
+void apprelay_deprecation_probe_ffi(void);
+
+// Generates a fresh key pair for `key_id` and the suite of `kem`, `kdf` and `aead`, writing
+// the encoded key configuration for the client into `out_config` and the private key for
+// a test gateway, [`TEST_PRIVATE_KEY_LEN`] bytes, into `out_private`.
+//
+// **Test helper, only available in builds with both `server` and `testutil`. The private
+// key must never ship.** It is the seed the key pair is derived from, which `ohttp` accepts
+// instead of the raw key, so load it with [`TestServer::from_seed`] and the same
+// parameters.
+//
+// Returns the length of the configuration, or -1 if the suite is not supported or either
+// buffer is too small, in which case neither is written.
+//
+// # Safety
+// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+int generate_test_keypair_ffi(uint16_t kem,
+                              uint16_t kdf,
+                              uint16_t aead,
+                              uint8_t key_id,
+                              uint8_t *out_config,
+                              size_t config_cap,
+                              uint8_t *out_private,
+                              size_t private_cap);
+
 #ifdef __cplusplus
 } // extern "C"
 #endif // __cplusplus
+
+#endif /* APPRELAY_H */
@@ -0,0 +1,97 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+use apprelay::config::KeyConfig;
+use apprelay::dev::DEV_KEY_CONFIG;
+use apprelay::server::TestServer;
+use apprelay::{
+    encapsulate_into_context_ffi, encapsulate_request_ffi, request_context_message_drop_ffi,
+    request_context_new_ffi, RequestContext,
+};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+
+const MESSAGE_SIZES: [usize; 4] = [0, 1024, 64 * 1024, 1024 * 1024];
+
+fn config_parsing(c: &mut Criterion) {
+    c.bench_function("config/decode", |b| {
+        b.iter(|| KeyConfig::decode(black_box(&DEV_KEY_CONFIG)).unwrap())
+    });
+}
+
+fn encapsulation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encapsulate");
+    for size in MESSAGE_SIZES {
+        let msg = vec![0x2a; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &msg, |b, msg| {
+            b.iter(|| RequestContext::new(&DEV_KEY_CONFIG, black_box(msg)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn decapsulation(c: &mut Criterion) {
+    let mut server = TestServer::dev().unwrap();
+    let mut group = c.benchmark_group("decapsulate");
+    for size in MESSAGE_SIZES {
+        let response = vec![0x2a; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_batched(
+                || {
+                    let (request, decapsulator) = RequestContext::new(&DEV_KEY_CONFIG, b"ping")
+                        .unwrap()
+                        .into_parts()
+                        .unwrap();
+                    let encapsulated_response =
+                        server.handle(&request, |_| response.clone()).unwrap();
+                    (decapsulator, encapsulated_response)
+                },
+                |(decapsulator, encapsulated_response)| {
+                    decapsulator.decapsulate(&encapsulated_response).unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// A fresh context per request against one pooled context refilled for every request.
+fn reuse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reuse");
+    for size in MESSAGE_SIZES {
+        let msg = vec![0x2a; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("cold", size), &msg, |b, msg| {
+            b.iter(|| unsafe {
+                let context = encapsulate_request_ffi(
+                    DEV_KEY_CONFIG.as_ptr(),
+                    DEV_KEY_CONFIG.len(),
+                    black_box(msg).as_ptr(),
+                    msg.len(),
+                );
+                request_context_message_drop_ffi(context);
+            })
+        });
+        let context = request_context_new_ffi();
+        group.bench_with_input(BenchmarkId::new("pooled", size), &msg, |b, msg| {
+            b.iter(|| unsafe {
+                encapsulate_into_context_ffi(
+                    context,
+                    DEV_KEY_CONFIG.as_ptr(),
+                    DEV_KEY_CONFIG.len(),
+                    black_box(msg).as_ptr(),
+                    msg.len(),
+                )
+            })
+        });
+        unsafe { request_context_message_drop_ffi(context) };
+    }
+    group.finish();
+}
+
+criterion_group!(benches, config_parsing, encapsulation, decapsulation, reuse);
+criterion_main!(benches);
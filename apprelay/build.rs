@@ -3,25 +3,50 @@
 
 use cbindgen::Config;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() {
     // cbindgen crashes on stable release due to macro expansion
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let config_path = Path::new(&crate_dir).join("cbindgen.toml");
 
-    let config = Config {
-        language: cbindgen::Language::C,
-        documentation_style: cbindgen::DocumentationStyle::C99,
-        cpp_compat: true,
-        usize_is_size_t: true,
-        parse: cbindgen::ParseConfig {
-            parse_deps: false,
-            include: Some(vec!["apprelay".to_owned()]),
-            ..Default::default()
-        },
-        ..Default::default()
-    };
+    println!("cargo:rerun-if-changed={}", config_path.display());
+    println!("cargo:rerun-if-changed=src");
+
+    let config = Config::from_file(&config_path).expect("Could not read cbindgen.toml");
 
     cbindgen::generate_with_config(&crate_dir, config)
         .expect("Could not generate header")
         .write_to_file("apprelay.h");
+
+    write_pkg_config(Path::new(&crate_dir));
+}
+
+/// Writes `apprelay.pc` next to the built library so that C build systems can
+/// locate it with `PKG_CONFIG_PATH=target/<profile> pkg-config --cflags --libs apprelay`.
+fn write_pkg_config(crate_dir: &Path) {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    // OUT_DIR is `<target>/<profile>/build/apprelay-<hash>/out`
+    let lib_dir = match out_dir.ancestors().nth(3) {
+        Some(dir) => dir.to_path_buf(),
+        None => return,
+    };
+
+    let contents = format!(
+        "libdir={libdir}\n\
+         includedir={includedir}\n\
+         \n\
+         Name: apprelay\n\
+         Description: {description}\n\
+         Version: {version}\n\
+         Libs: -L${{libdir}} -lapprelay\n\
+         Cflags: -I${{includedir}}\n",
+        libdir = lib_dir.display(),
+        includedir = crate_dir.display(),
+        description = "OHTTP client library for app relays",
+        version = env::var("CARGO_PKG_VERSION").unwrap(),
+    );
+
+    fs::write(lib_dir.join("apprelay.pc"), contents).expect("Could not write apprelay.pc");
 }
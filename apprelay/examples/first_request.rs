@@ -0,0 +1,35 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Times the first encapsulation of a fresh process, optionally after `apprelay_warmup_ffi`.
+//!
+//! Criterion warms up before measuring, so the cold start can only be observed by running
+//! this once per process:
+//!
+//! ```sh
+//! cargo run --release --example first_request --features dev-config
+//! cargo run --release --example first_request --features dev-config -- --warmup
+//! ```
+
+use std::time::Instant;
+
+use apprelay::apprelay_warmup_ffi;
+use apprelay::dev::DEV_KEY_CONFIG;
+use apprelay::RequestContext;
+
+fn main() {
+    let warmup = std::env::args().any(|arg| arg == "--warmup");
+    if warmup {
+        let start = Instant::now();
+        assert_eq!(apprelay_warmup_ffi(), 0, "warmup failed");
+        println!("warmup:        {:?}", start.elapsed());
+    }
+
+    let start = Instant::now();
+    RequestContext::new(&DEV_KEY_CONFIG, &[0x2a; 1024]).expect("encapsulation failed");
+    println!("first request: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    RequestContext::new(&DEV_KEY_CONFIG, &[0x2a; 1024]).expect("encapsulation failed");
+    println!("next request:  {:?}", start.elapsed());
+}
@@ -0,0 +1,89 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Sends a GET request through an OHTTP relay and prints the status of the response.
+//!
+//! ```sh
+//! cargo run --example relay_roundtrip --features example-http -- \
+//!     <relay url> <gateway config url> <target url>
+//! ```
+
+use std::error::Error;
+use std::io::Read;
+use std::process::exit;
+
+use apprelay::bhttp::{BhttpRequest, BhttpResponse};
+use apprelay::media_type::{MediaTypes, OHTTP_MODE_ONE_SHOT};
+use apprelay::RequestContext;
+
+/// Splits `https://authority/path` into its scheme, authority and path.
+fn split_url(url: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    match rest.find('/') {
+        Some(index) => Some((scheme, &rest[..index], &rest[index..])),
+        None => Some((scheme, rest, "/")),
+    }
+}
+
+fn fetch_config(config_url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut config = Vec::new();
+    ureq::get(config_url)
+        .call()?
+        .into_reader()
+        .read_to_end(&mut config)?;
+    Ok(config)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    let (relay_url, config_url, target_url) = match &args[..] {
+        [_, relay_url, config_url, target_url] => (relay_url, config_url, target_url),
+        _ => {
+            eprintln!("usage: relay_roundtrip <relay url> <gateway config url> <target url>");
+            exit(2);
+        }
+    };
+    let (scheme, authority, path) = split_url(target_url).ok_or("target url has no scheme")?;
+    let media_types = MediaTypes::for_mode(OHTTP_MODE_ONE_SHOT)?;
+
+    let config = fetch_config(config_url)?;
+    let request = BhttpRequest::new("GET", scheme, authority, path).encode(&[]);
+    let (encapsulated_request, decapsulator) =
+        RequestContext::new(&config, &request)?.into_parts()?;
+
+    let response = match ureq::post(relay_url)
+        .set("Content-Type", media_types.request)
+        .send_bytes(&encapsulated_request)
+    {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, response)) => {
+            // The relay or the gateway rejected the request, the body is not encapsulated.
+            let status_text = response.status_text().to_owned();
+            let body = response.into_string().unwrap_or_default();
+            eprintln!("relay answered {status} {status_text}: {body}");
+            exit(1);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let content_type = response.content_type().to_owned();
+    if !media_types.is_response(&content_type) {
+        return Err(format!("unexpected response content type {content_type:?}").into());
+    }
+
+    let mut encapsulated_response = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut encapsulated_response)?;
+    let response = BhttpResponse::decode(&decapsulator.decapsulate(&encapsulated_response)?)?;
+
+    println!("{} {}", response.status(), target_url);
+    for field in response.headers() {
+        println!(
+            "{}: {}",
+            String::from_utf8_lossy(field.name()),
+            String::from_utf8_lossy(field.value())
+        );
+    }
+    println!("\n{} bytes of content", response.body().len());
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+// Forwards every incoming request through an OHTTP relay with the `workers` build of apprelay.
+// Build `pkg/` as described in the README, then deploy with the `wrangler.toml` next to this file.
+
+import init, { OhttpRequest } from "./pkg/apprelay.js";
+import wasm from "./pkg/apprelay_bg.wasm";
+
+const ready = init(wasm);
+
+// Known-length BHTTP request without header fields, RFC 9292, for bodies under 16 KiB.
+function encodeBhttpRequest(method, url, body) {
+  const { protocol, host, pathname, search } = new URL(url);
+  const encoder = new TextEncoder();
+  const bytes = [0];
+  const push = (field) => {
+    if (field.length >= 64) {
+      bytes.push(0x40 | (field.length >> 8), field.length & 0xff);
+    } else {
+      bytes.push(field.length);
+    }
+    bytes.push(...field);
+  };
+  for (const control of [method, protocol.slice(0, -1), host, pathname + search]) {
+    push(encoder.encode(control));
+  }
+  push([]);
+  push(body);
+  bytes.push(0);
+  return new Uint8Array(bytes);
+}
+
+export default {
+  async fetch(request, env) {
+    await ready;
+    const config = new Uint8Array(await (await fetch(env.GATEWAY_CONFIG_URL)).arrayBuffer());
+    const body = new Uint8Array(await request.arrayBuffer());
+    const ohttp = new OhttpRequest(config, encodeBhttpRequest(request.method, env.TARGET_URL, body));
+
+    const reply = await fetch(env.RELAY_URL, {
+      method: "POST",
+      headers: { "content-type": "message/ohttp-req" },
+      body: ohttp.encapsulated,
+    });
+    const bhttp = ohttp.decapsulate(new Uint8Array(await reply.arrayBuffer()));
+    return new Response(bhttp, { headers: { "content-type": "message/bhttp" } });
+  },
+};
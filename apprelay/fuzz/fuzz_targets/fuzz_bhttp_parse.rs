@@ -0,0 +1,46 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+#![no_main]
+
+use apprelay::bhttp::*;
+use apprelay::error_ffi::last_error_code;
+use apprelay::{BytesView, ClientErrorKind};
+use libfuzzer_sys::fuzz_target;
+
+fn view_len(view: BytesView) -> usize {
+    assert!(!view.ptr.is_null(), "accessor failed for an index in range");
+    view.len
+}
+
+fuzz_target!(|data: &[u8]| {
+    unsafe {
+        let response = parse_bhttp_response_ffi(data.as_ptr(), data.len());
+        if response.is_null() {
+            // Arbitrary input may only be rejected as malformed, never by a caught panic.
+            assert_eq!(
+                last_error_code(),
+                ClientErrorKind::ResponseMalformed as std::os::raw::c_int
+            );
+            return;
+        }
+
+        let status = bhttp_response_status_ffi(response);
+        assert!((200..=599).contains(&status));
+
+        // Everything the parser holds was copied out of the input, so no length field
+        // may have made it allocate more than the input itself.
+        let mut retained = view_len(bhttp_response_body_ffi(response));
+        for i in 0..bhttp_response_header_count_ffi(response) {
+            retained += view_len(bhttp_response_header_name_ffi(response, i));
+            retained += view_len(bhttp_response_header_value_ffi(response, i));
+        }
+        for i in 0..bhttp_response_trailer_count_ffi(response) {
+            retained += view_len(bhttp_response_trailer_name_ffi(response, i));
+            retained += view_len(bhttp_response_trailer_value_ffi(response, i));
+        }
+        assert!(retained <= data.len());
+
+        bhttp_response_drop_ffi(response);
+    }
+});
@@ -1,13 +1,14 @@
 use std::ptr::null_mut;
+use std::slice;
 
 use jni::JNIEnv;
 
-use jni::objects::JClass;
+use jni::objects::{JByteBuffer, JClass, JObject, JValue};
 
-use jni::sys::{jbyteArray, jlong, jstring};
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jobject, jstring};
 
 use crate::error_ffi::update_last_error;
-use crate::{null_safe_ptr, safe_unwrap, ClientError, RequestContext};
+use crate::{handles, null_safe_ptr, safe_unwrap, ClientError, ClientErrorKind, RequestContext};
 
 /// Return most recent error as a Java `String`.
 ///
@@ -75,12 +76,14 @@ pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_getEncapsulat
     _class: JClass,
     context_ptr: jlong,
 ) -> jbyteArray {
-    let context = &mut *(context_ptr as *mut RequestContext);
-    safe_unwrap!(
-        env.byte_array_from_slice(&context.encapsulated_request[..]),
+    let array = safe_unwrap!(
+        handles::with(context_ptr as *const RequestContext, |context| {
+            env.byte_array_from_slice(&context.encapsulated_request[..])
+        }),
         null_mut(),
-        ClientError::JniProblem
-    )
+        std::convert::identity
+    );
+    safe_unwrap!(array, null_mut(), ClientError::JniProblem)
 }
 
 /// Frees up context memory. Be sure to call this in cases:
@@ -98,7 +101,9 @@ pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_drop(
     _class: JClass,
     context_ptr: jlong,
 ) {
-    let _context = Box::from_raw(context_ptr as *mut RequestContext);
+    if let Err(err) = handles::take(context_ptr as *mut RequestContext) {
+        update_last_error(err);
+    }
 }
 
 /// Decapsulates the provided response `encapsulated_response` using
@@ -107,6 +112,7 @@ pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_drop(
 /// Returns an array containing the decapsulated response.
 ///
 /// If this function fails due JNI problems or decapsulation it returns a NULL pointer.
+/// An empty `encapsulated_response` fails before the context is consumed.
 ///
 /// # Safety
 /// Dereferences a pointer to `RequestContext` passed by the caller.
@@ -120,16 +126,15 @@ pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_decapsulateRe
     context_ptr: jlong,
     encapsulated_response: jbyteArray,
 ) -> jbyteArray {
-    let context = Box::from_raw(context_ptr as *mut RequestContext);
     let encapsulated_response = crate::safe_unwrap!(
         env.convert_byte_array(encapsulated_response),
         null_mut(),
         ClientError::JniProblem
     );
     let response = safe_unwrap!(
-        context.response_context.decapsulate(&encapsulated_response),
+        decapsulate_context(context_ptr, &encapsulated_response),
         null_mut(),
-        ClientError::DecapsulationFailed
+        std::convert::identity
     );
     safe_unwrap!(
         env.byte_array_from_slice(&response[..]),
@@ -137,3 +142,300 @@ pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_decapsulateRe
         ClientError::JniProblem
     )
 }
+
+/// Decapsulates `encapsulated_response` with the context behind `context_ptr` and applies the
+/// response jitter, the part of `decapsulateResponse` and `decapsulateResponseDirect` that does
+/// not touch the JVM.
+///
+/// An empty `encapsulated_response` fails with `ResponseEmpty` before the context is consumed.
+pub fn decapsulate_context(
+    context_ptr: jlong,
+    encapsulated_response: &[u8],
+) -> Result<Vec<u8>, ClientError> {
+    if encapsulated_response.is_empty() {
+        return Err(ClientError::ResponseEmpty);
+    }
+    let decapsulator = handles::take_decapsulator(context_ptr as *mut RequestContext)?;
+    let response = decapsulator.decapsulate(encapsulated_response)?;
+    crate::jitter::delay_response();
+    Ok(response)
+}
+
+/// Address and capacity of the direct `buffer`.
+///
+/// Fails for non-direct buffers, which have no native address. The memory is shared with the
+/// JVM, so it is handed on as a pointer rather than a slice borrowing `env`.
+fn direct_region(env: &JNIEnv, buffer: JByteBuffer) -> Result<(*mut u8, usize), ClientError> {
+    let memory = env
+        .get_direct_buffer_address(buffer)
+        .map_err(ClientError::JniProblem)?;
+    Ok((memory.as_mut_ptr(), memory.len()))
+}
+
+/// Borrows the first `len` bytes of the direct `buffer` for reading.
+///
+/// Fails for non-direct buffers and for `len` beyond the capacity.
+///
+/// # Safety
+/// The buffer must not be written to or collected while the slice is in use.
+unsafe fn direct_input<'a>(
+    env: &JNIEnv,
+    buffer: JByteBuffer,
+    len: jint,
+) -> Result<&'a [u8], ClientError> {
+    let (ptr, capacity) = direct_region(env, buffer)?;
+    if len < 0 || len as usize > capacity {
+        return Err(ClientError::InvalidArgument(format!(
+            "length {} does not fit the direct buffer of {} bytes",
+            len, capacity
+        )));
+    }
+    Ok(slice::from_raw_parts(ptr, len as usize))
+}
+
+/// Like `encapsulateRequest`, reading the first `config_len` bytes of the direct buffer `config`
+/// and `msg_len` bytes of `msg` in place instead of copying Java arrays.
+///
+/// Returns a pointer to encapsulation context, and returns -1 upon failure or if either buffer
+/// is not direct.
+///
+/// # Safety
+/// The buffers must not be written to by other threads during the call.
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_encapsulateRequestDirect(
+    env: JNIEnv,
+    _class: JClass,
+    config: JByteBuffer,
+    config_len: jint,
+    msg: JByteBuffer,
+    msg_len: jint,
+) -> jlong {
+    let config = safe_unwrap!(
+        direct_input(&env, config, config_len),
+        -1,
+        std::convert::identity
+    );
+    let msg = safe_unwrap!(direct_input(&env, msg, msg_len), -1, std::convert::identity);
+
+    let encapsulated =
+        crate::encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+    if encapsulated.is_null() {
+        -1
+    } else {
+        encapsulated as jlong
+    }
+}
+
+/// Address and length of the encapsulated request owned by the context behind `context_ptr`,
+/// the memory `getEncapsulatedRequestDirect` wraps into a direct buffer.
+///
+/// The address is taken through exclusive access, a direct buffer lets Java write to it. It
+/// is only valid until the context is decapsulated or dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+pub unsafe fn encapsulated_request_region(
+    context_ptr: jlong,
+) -> Result<(*mut u8, usize), ClientError> {
+    handles::with_mut(context_ptr as *mut RequestContext, |context| {
+        let request = &mut context.encapsulated_request;
+        (request.as_mut_ptr(), request.len())
+    })
+}
+
+/// Returns a direct buffer over the encapsulated request owned by the context, without a copy.
+///
+/// The buffer is only valid until the context is decapsulated or dropped, reading it afterwards
+/// reads freed memory. Returns a NULL pointer if the context is not live.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_getEncapsulatedRequestDirect(
+    env: JNIEnv,
+    _class: JClass,
+    context_ptr: jlong,
+) -> jobject {
+    let (ptr, len) = safe_unwrap!(
+        encapsulated_request_region(context_ptr),
+        null_mut(),
+        std::convert::identity
+    );
+    let buffer = safe_unwrap!(
+        env.new_direct_byte_buffer(slice::from_raw_parts_mut(ptr, len)),
+        null_mut(),
+        ClientError::JniProblem
+    );
+    buffer.into_inner()
+}
+
+/// Like `decapsulateResponse`, reading the first `response_len` bytes of the direct buffer
+/// `encapsulated_response` in place and writing the decapsulated response into the direct
+/// buffer `out`.
+///
+/// Returns the number of bytes written, or -1 upon failure, if a buffer is not direct or
+/// `out` is too small. As with `decapsulateResponse` the context is consumed unless the
+/// response is empty.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_decapsulateResponseDirect(
+    env: JNIEnv,
+    _class: JClass,
+    context_ptr: jlong,
+    encapsulated_response: JByteBuffer,
+    response_len: jint,
+    out: JByteBuffer,
+) -> jint {
+    let encapsulated_response = safe_unwrap!(
+        direct_input(&env, encapsulated_response, response_len),
+        -1,
+        std::convert::identity
+    );
+    let (out, out_len) = safe_unwrap!(direct_region(&env, out), -1, std::convert::identity);
+    let response = safe_unwrap!(
+        decapsulate_context(context_ptr, encapsulated_response),
+        -1,
+        std::convert::identity
+    );
+    crate::write_out_buffer(&response, out, out_len) as jint
+}
+
+/// Class returned by the `*Result` variants, see the README for its definition.
+const NATIVE_RESULT_CLASS: &str = "org/platform/NativeResult";
+/// `NativeResult(boolean isOk, long handle, byte[] bytes, int errorCode, String errorMessage)`
+const NATIVE_RESULT_CONSTRUCTOR: &str = "(ZJ[BILjava/lang/String;)V";
+
+fn new_native_result(
+    env: &JNIEnv,
+    handle: jlong,
+    bytes: jbyteArray,
+    error_code: jint,
+    error_message: JObject,
+) -> jobject {
+    let is_ok = error_code == 0;
+    let result = env.new_object(
+        NATIVE_RESULT_CLASS,
+        NATIVE_RESULT_CONSTRUCTOR,
+        &[
+            JValue::Bool(is_ok as jboolean),
+            JValue::Long(handle),
+            JValue::Object(JObject::from(bytes)),
+            JValue::Int(error_code),
+            JValue::Object(error_message),
+        ],
+    );
+    safe_unwrap!(result, null_mut(), ClientError::JniProblem).into_inner()
+}
+
+fn ok_result(env: &JNIEnv, handle: jlong, bytes: jbyteArray) -> jobject {
+    new_native_result(env, handle, bytes, 0, JObject::null())
+}
+
+/// Wraps the last error, which is cleared, into a failed `NativeResult`.
+fn err_result(env: &JNIEnv) -> jobject {
+    let (error_code, error_message) = match crate::error_ffi::take_last_error() {
+        Some(err) => (crate::error_ffi::error_code(&*err), err.to_string()),
+        None => (
+            ClientErrorKind::JniProblem as jint,
+            "Unknown error".to_owned(),
+        ),
+    };
+    let error_message = safe_unwrap!(
+        env.new_string(error_message),
+        null_mut(),
+        ClientError::JniProblem
+    );
+    new_native_result(env, 0, null_mut(), error_code, error_message.into())
+}
+
+/// Like `encapsulateRequest`, returning a `NativeResult` holding the context handle and the
+/// encapsulated request, or the error code and message.
+#[no_mangle]
+pub extern "system" fn Java_org_platform_OHttpNativeWrapper_encapsulateRequestResult(
+    env: JNIEnv,
+    class: JClass,
+    config: jbyteArray,
+    msg: jbyteArray,
+) -> jobject {
+    let handle =
+        Java_org_platform_OHttpNativeWrapper_encapsulateRequest(env.clone(), class, config, msg);
+    if handle == -1 {
+        return err_result(&env);
+    }
+    let request = unsafe {
+        Java_org_platform_OHttpNativeWrapper_getEncapsulatedRequest(env.clone(), class, handle)
+    };
+    if request.is_null() {
+        discard_context(handle);
+        return err_result(&env);
+    }
+    ok_result(&env, handle, request)
+}
+
+/// Frees the context behind `handle` after a later step of the call creating it failed,
+/// keeping the error of that step for the `NativeResult`.
+pub fn discard_context(handle: jlong) {
+    // The handle was just registered by the failed call, no other caller knows it.
+    let _context = unsafe { handles::take(handle as *mut RequestContext) };
+}
+
+/// Like `getEncapsulatedRequest`, returning a `NativeResult`.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_getEncapsulatedRequestResult(
+    env: JNIEnv,
+    class: JClass,
+    context_ptr: jlong,
+) -> jobject {
+    let request = Java_org_platform_OHttpNativeWrapper_getEncapsulatedRequest(
+        env.clone(),
+        class,
+        context_ptr,
+    );
+    if request.is_null() {
+        return err_result(&env);
+    }
+    ok_result(&env, context_ptr, request)
+}
+
+/// Like `decapsulateResponse`, returning a `NativeResult` holding the decapsulated response.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_platform_OHttpNativeWrapper_decapsulateResponseResult(
+    env: JNIEnv,
+    class: JClass,
+    context_ptr: jlong,
+    encapsulated_response: jbyteArray,
+) -> jobject {
+    let response = Java_org_platform_OHttpNativeWrapper_decapsulateResponse(
+        env.clone(),
+        class,
+        context_ptr,
+        encapsulated_response,
+    );
+    if response.is_null() {
+        return err_result(&env);
+    }
+    ok_result(&env, 0, response)
+}
@@ -0,0 +1,840 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Encoding of [Binary HTTP](https://www.rfc-editor.org/rfc/rfc9292.html) requests and decoding of responses.
+
+use std::borrow::Cow;
+use std::panic::catch_unwind;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{ptr, slice};
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{
+    catch_panics, cstr_to_str, null_safe_ptr, safe_unwrap, write_out_buffer, BytesView, ClientError,
+};
+
+/// Framing indicator of a known-length request.
+const KNOWN_LENGTH_REQUEST: u64 = 0;
+/// Framing indicator of a known-length response.
+const KNOWN_LENGTH_RESPONSE: u64 = 1;
+
+/// Default for the number of fields accepted in a single header or trailer section.
+pub const BHTTP_DEFAULT_MAX_HEADERS: usize = 128;
+/// Default for the total name and value bytes accepted in a single header or trailer section.
+pub const BHTTP_DEFAULT_MAX_HEADER_BYTES: usize = 65536;
+
+static MAX_HEADERS: AtomicUsize = AtomicUsize::new(BHTTP_DEFAULT_MAX_HEADERS);
+static MAX_HEADER_BYTES: AtomicUsize = AtomicUsize::new(BHTTP_DEFAULT_MAX_HEADER_BYTES);
+static MAX_BODY_BYTES: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Bounds on the field sections and the content of a response, see
+/// [`set_bhttp_header_limit_ffi`] and [`set_max_response_body_ffi`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldLimits {
+    pub max_fields: usize,
+    pub max_total_bytes: usize,
+    /// Largest content accepted, unbounded by default.
+    pub max_body_bytes: usize,
+}
+
+impl FieldLimits {
+    /// Limits currently configured for `parse_bhttp_response_ffi`.
+    pub fn current() -> Self {
+        FieldLimits {
+            max_fields: MAX_HEADERS.load(Ordering::Relaxed),
+            max_total_bytes: MAX_HEADER_BYTES.load(Ordering::Relaxed),
+            max_body_bytes: MAX_BODY_BYTES.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for FieldLimits {
+    fn default() -> Self {
+        FieldLimits {
+            max_fields: BHTTP_DEFAULT_MAX_HEADERS,
+            max_total_bytes: BHTTP_DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: usize::MAX,
+        }
+    }
+}
+
+/// A single field line of a header or trailer section.
+pub struct Field {
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Field {
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+/// Known-length BHTTP request under construction.
+pub struct BhttpRequest<'a> {
+    method: &'a str,
+    scheme: &'a str,
+    authority: &'a str,
+    path: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+    canonical: bool,
+}
+
+impl<'a> BhttpRequest<'a> {
+    /// Request for `path`, an empty `authority` leaves the choice of the target to the gateway.
+    pub fn new(method: &'a str, scheme: &'a str, authority: &'a str, path: &'a str) -> Self {
+        BhttpRequest {
+            method,
+            scheme,
+            authority,
+            path,
+            headers: Vec::new(),
+            canonical: false,
+        }
+    }
+
+    pub fn header(mut self, name: &'a str, value: &'a str) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Lowercases the header names and sorts the header section by name and value when
+    /// encoding, so the same logical request always encodes to the same bytes.
+    ///
+    /// The control data (method, scheme, authority and path) always precedes the header
+    /// section. Sorting reorders repeated fields of the same name, so leave this off for
+    /// origins that depend on the order of field lines.
+    pub fn canonical(mut self) -> Self {
+        self.canonical = true;
+        self
+    }
+
+    /// Encodes the request with `body` as its content and an empty trailer section.
+    ///
+    /// A request without a body, such as most GETs, is encoded with a content length of
+    /// zero followed by the empty trailer section rather than relying on the truncation
+    /// RFC 9292 allows, so the message ends with two zero bytes:
+    ///
+    /// ```text
+    /// 00 03 "GET" 05 "https" .. authority .. path .. headers 00 00
+    /// ```
+    pub fn encode(&self, body: &[u8]) -> Vec<u8> {
+        self.encode_with_headers(&[], body)
+    }
+
+    /// Encodes the request like [`BhttpRequest::encode`] with the gzip compressed `body`,
+    /// adding `content-encoding: gzip` for the origin to decompress it.
+    ///
+    /// Fails with [`ClientError::InvalidArgument`] if the request already has a
+    /// `content-encoding` header, since the body would then be compressed twice.
+    #[cfg(feature = "gzip")]
+    pub fn encode_gzip(&self, body: &[u8]) -> Result<Vec<u8>, ClientError> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        if let Some((_, encoding)) = self
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-encoding"))
+        {
+            return Err(ClientError::InvalidArgument(format!(
+                "body already has content-encoding {encoding}"
+            )));
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(body)
+            .expect("writing to a Vec does not fail");
+        let compressed = encoder.finish().expect("writing to a Vec does not fail");
+        Ok(self.encode_with_headers(&[("content-encoding", "gzip")], &compressed))
+    }
+
+    fn encode_with_headers(&self, extra: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+        let mut fields: Vec<(Cow<str>, &str)> = self
+            .headers
+            .iter()
+            .chain(extra)
+            .map(|(name, value)| (Cow::Borrowed(*name), *value))
+            .collect();
+        if self.canonical {
+            for (name, _) in &mut fields {
+                *name = Cow::Owned(name.to_ascii_lowercase());
+            }
+            fields.sort();
+        }
+
+        let mut headers = Vec::new();
+        for (name, value) in &fields {
+            write_length_prefixed(&mut headers, name.as_bytes());
+            write_length_prefixed(&mut headers, value.as_bytes());
+        }
+
+        let mut out = Vec::with_capacity(headers.len() + body.len() + 64);
+        write_varint(&mut out, KNOWN_LENGTH_REQUEST);
+        for control in [self.method, self.scheme, self.authority, self.path] {
+            write_length_prefixed(&mut out, control.as_bytes());
+        }
+        write_length_prefixed(&mut out, &headers);
+        write_length_prefixed(&mut out, body);
+        write_varint(&mut out, 0);
+        out
+    }
+}
+
+/// Writes a QUIC variable-length integer, `value` must be below 2^62.
+fn write_varint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0x3f => out.push(value as u8),
+        0x40..=0x3fff => out.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes()),
+        0x4000..=0x3fff_ffff => out.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => out.extend_from_slice(&(value | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+fn write_length_prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Informational (1xx) response preceding the final response, e.g. 103 Early Hints.
+pub struct InformationalResponse {
+    status: u16,
+    headers: Vec<Field>,
+}
+
+impl InformationalResponse {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &[Field] {
+        &self.headers
+    }
+}
+
+/// Decoded known-length BHTTP response.
+pub struct BhttpResponse {
+    informational: Vec<InformationalResponse>,
+    status: u16,
+    headers: Vec<Field>,
+    body: Vec<u8>,
+    trailers: Vec<Field>,
+}
+
+impl BhttpResponse {
+    /// Decodes a known-length BHTTP response.
+    ///
+    /// Informational (1xx) responses preceding the final response are kept apart from it,
+    /// see [`BhttpResponse::informational`].
+    /// A message truncated after the header section or the content is accepted and
+    /// yields an empty body and/or an empty trailer section.
+    /// Field sections are bounded by [`FieldLimits::current`].
+    pub fn decode(data: &[u8]) -> Result<Self, ClientError> {
+        Self::decode_with_limits(data, FieldLimits::current())
+    }
+
+    /// Decodes like [`BhttpResponse::decode`], rejecting any header or trailer section
+    /// or content that exceeds `limits`.
+    ///
+    /// The declared content length is checked against the limit before the content is read,
+    /// and the body is only allocated for content actually present in `data`.
+    pub fn decode_with_limits(data: &[u8], limits: FieldLimits) -> Result<Self, ClientError> {
+        let mut reader = Reader { data };
+
+        let framing = reader.read_varint()?;
+        if framing != KNOWN_LENGTH_RESPONSE {
+            return Err(malformed(format!(
+                "unsupported framing indicator {framing}"
+            )));
+        }
+
+        let mut informational = Vec::new();
+        let (status, headers) = loop {
+            let status = reader.read_varint()?;
+            let fields = reader.read_field_section(limits)?;
+            match status {
+                100..=199 => informational.push(InformationalResponse {
+                    status: status as u16,
+                    headers: fields,
+                }),
+                200..=599 => break (status as u16, fields),
+                _ => return Err(malformed(format!("invalid status code {status}"))),
+            }
+        };
+
+        let body = if reader.is_empty() {
+            Vec::new()
+        } else {
+            let len = reader.read_varint()?;
+            if len > limits.max_body_bytes as u64 {
+                return Err(malformed(format!(
+                    "content length {len} exceeds {} bytes",
+                    limits.max_body_bytes
+                )));
+            }
+            reader.read_bytes(len)?.to_vec()
+        };
+
+        let trailers = if reader.is_empty() {
+            Vec::new()
+        } else {
+            reader.read_field_section(limits)?
+        };
+
+        if reader.data.iter().any(|b| *b != 0) {
+            return Err(malformed("non-zero padding".to_owned()));
+        }
+
+        Ok(BhttpResponse {
+            informational,
+            status,
+            headers,
+            body,
+            trailers,
+        })
+    }
+
+    /// Informational responses in the order received, empty if the final response came first.
+    pub fn informational(&self) -> &[InformationalResponse] {
+        &self.informational
+    }
+
+    /// Status code of the final response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &[Field] {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Trailer fields, empty if the response carries no trailer section.
+    pub fn trailers(&self) -> &[Field] {
+        &self.trailers
+    }
+}
+
+fn malformed(reason: String) -> ClientError {
+    ClientError::ResponseMalformed(reason)
+}
+
+/// Whether `data` starts like a BHTTP message of any framing: a request method made of
+/// token characters, or a response status code, after the framing indicator.
+pub(crate) fn looks_like_bhttp(data: &[u8]) -> bool {
+    let mut reader = Reader { data };
+    match reader.read_varint() {
+        // Known-length and indeterminate-length requests.
+        Ok(0) | Ok(2) => matches!(
+            reader.read_length_prefixed(),
+            Ok(method) if !method.is_empty() && method.iter().all(|b| is_tchar(*b))
+        ),
+        // Known-length and indeterminate-length responses.
+        Ok(1) | Ok(3) => matches!(reader.read_varint(), Ok(100..=599)),
+        _ => false,
+    }
+}
+
+/// Whether `b` may appear in an HTTP token, such as a method.
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Cursor over untrusted input.
+///
+/// Every length read from the message is checked against the remaining input before
+/// anything is sliced or copied, so the decoded response never holds more bytes than
+/// the input and no allocation is sized by a length field alone.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn read_bytes(&mut self, len: u64) -> Result<&'a [u8], ClientError> {
+        if len > self.data.len() as u64 {
+            return Err(malformed(format!(
+                "length {len} exceeds the remaining {} bytes",
+                self.data.len()
+            )));
+        }
+        let (bytes, rest) = self.data.split_at(len as usize);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a QUIC variable-length integer.
+    fn read_varint(&mut self) -> Result<u64, ClientError> {
+        let first = match self.data.first() {
+            Some(first) => *first,
+            None => return Err(malformed("unexpected end of message".to_owned())),
+        };
+        let len = 1 << (first >> 6);
+        let bytes = self.read_bytes(len)?;
+        Ok(bytes[1..]
+            .iter()
+            .fold(u64::from(first & 0x3f), |acc, b| (acc << 8) | u64::from(*b)))
+    }
+
+    fn read_length_prefixed(&mut self) -> Result<&'a [u8], ClientError> {
+        let len = self.read_varint()?;
+        self.read_bytes(len)
+    }
+
+    fn read_field_section(&mut self, limits: FieldLimits) -> Result<Vec<Field>, ClientError> {
+        let mut section = Reader {
+            data: self.read_length_prefixed()?,
+        };
+        let mut fields = Vec::new();
+        let mut total_bytes = 0;
+        while !section.is_empty() {
+            if fields.len() == limits.max_fields {
+                return Err(malformed(format!(
+                    "field section exceeds {} fields",
+                    limits.max_fields
+                )));
+            }
+            let name = section.read_length_prefixed()?;
+            let value = section.read_length_prefixed()?;
+            total_bytes += name.len() + value.len();
+            if total_bytes > limits.max_total_bytes {
+                return Err(malformed(format!(
+                    "field section exceeds {} bytes",
+                    limits.max_total_bytes
+                )));
+            }
+            fields.push(Field {
+                name: name.to_vec(),
+                value: value.to_vec(),
+            });
+        }
+        Ok(fields)
+    }
+}
+
+fn field_view(fields: &[Field], index: libc::size_t, select: fn(&Field) -> &[u8]) -> BytesView {
+    match fields.get(index) {
+        Some(field) => BytesView::from_slice(select(field)),
+        None => {
+            update_last_error(ClientError::InvalidArgument(format!(
+                "Field index {index} out of range"
+            )));
+            BytesView::empty()
+        }
+    }
+}
+
+fn informational_at(
+    response: &BhttpResponse,
+    index: libc::size_t,
+) -> Option<&InformationalResponse> {
+    let informational = response.informational().get(index);
+    if informational.is_none() {
+        update_last_error(ClientError::InvalidArgument(format!(
+            "Informational response index {index} out of range"
+        )));
+    }
+    informational
+}
+
+/// Flag of `build_bhttp_request_ffi` canonicalizing the header section, see [`BhttpRequest::canonical`].
+pub const BHTTP_CANONICAL_HEADERS: libc::c_int = 1;
+
+/// Encodes a known-length BHTTP request into `out`, to pass to `encapsulate_request_ffi`.
+///
+/// `method`, `scheme`, `authority` and `path` are NUL terminated strings, `header_names` and
+/// `header_values` arrays of `header_count` NUL terminated strings each. `body_ptr` may be
+/// NULL if `body_len` is 0, for requests without a body, see [`BhttpRequest::encode`]. With
+/// [`BHTTP_CANONICAL_HEADERS`] in `flags` the header section is lowercased and sorted, which
+/// some order-sensitive origins do not accept, so it is opt-in.
+///
+/// Returns the number of bytes written, or -1 if a string is NULL or not valid UTF-8 or `out`
+/// is too small.
+///
+/// # Safety
+/// Strings must be NUL terminated, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+/// The invariants of the arrays and the body are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[allow(clippy::too_many_arguments)]
+#[no_mangle]
+pub unsafe extern "C" fn build_bhttp_request_ffi(
+    method: *const libc::c_char,
+    scheme: *const libc::c_char,
+    authority: *const libc::c_char,
+    path: *const libc::c_char,
+    header_names: *const *const libc::c_char,
+    header_values: *const *const libc::c_char,
+    header_count: libc::size_t,
+    body_ptr: *const u8,
+    body_len: libc::size_t,
+    flags: libc::c_int,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let method = safe_unwrap!(cstr_to_str(method, "method"), -1, std::convert::identity);
+    let scheme = safe_unwrap!(cstr_to_str(scheme, "scheme"), -1, std::convert::identity);
+    let authority = safe_unwrap!(
+        cstr_to_str(authority, "authority"),
+        -1,
+        std::convert::identity
+    );
+    let path = safe_unwrap!(cstr_to_str(path, "path"), -1, std::convert::identity);
+    let body = if body_len == 0 {
+        &[]
+    } else {
+        let body_ptr = null_safe_ptr!(body_ptr, -1, body_ptr);
+        slice::from_raw_parts(body_ptr, body_len)
+    };
+
+    let mut request = BhttpRequest::new(method, scheme, authority, path);
+    if header_count > 0 {
+        let header_names = null_safe_ptr!(header_names, -1, header_names);
+        let header_values = null_safe_ptr!(header_values, -1, header_values);
+        let names = slice::from_raw_parts(header_names, header_count);
+        let values = slice::from_raw_parts(header_values, header_count);
+        for (name, value) in names.iter().zip(values) {
+            let name = safe_unwrap!(
+                cstr_to_str(*name, "header_names"),
+                -1,
+                std::convert::identity
+            );
+            let value = safe_unwrap!(
+                cstr_to_str(*value, "header_values"),
+                -1,
+                std::convert::identity
+            );
+            request = request.header(name, value);
+        }
+    }
+    if flags & BHTTP_CANONICAL_HEADERS != 0 {
+        request = request.canonical();
+    }
+    write_out_buffer(&request.encode(body), out, out_cap)
+}
+
+/// Parses the decapsulated response as a known-length BHTTP response.
+///
+/// This function will return a NULL pointer if the response is malformed.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn parse_bhttp_response_ffi(
+    response_ptr: *const u8,
+    response_len: libc::size_t,
+) -> *mut BhttpResponse {
+    let response_ptr = null_safe_ptr!(response_ptr, ptr::null_mut(), response_ptr);
+    let response = slice::from_raw_parts(response_ptr, response_len as usize);
+
+    catch_panics!(
+        {
+            let response = safe_unwrap!(
+                BhttpResponse::decode(response),
+                ptr::null_mut(),
+                std::convert::identity
+            );
+            Box::into_raw(Box::new(response))
+        },
+        ptr::null_mut()
+    )
+}
+
+/// Bounds every header and trailer section accepted by `parse_bhttp_response_ffi` to
+/// `max_headers` fields and `max_total_bytes` bytes of names and values.
+///
+/// Responses exceeding either limit fail with `ResponseMalformed`. The limits apply to all
+/// threads and default to [`BHTTP_DEFAULT_MAX_HEADERS`] and [`BHTTP_DEFAULT_MAX_HEADER_BYTES`].
+#[no_mangle]
+pub extern "C" fn set_bhttp_header_limit_ffi(
+    max_headers: libc::size_t,
+    max_total_bytes: libc::size_t,
+) {
+    MAX_HEADERS.store(max_headers, Ordering::Relaxed);
+    MAX_HEADER_BYTES.store(max_total_bytes, Ordering::Relaxed);
+}
+
+/// Bounds the content accepted by `parse_bhttp_response_ffi` to `bytes`.
+///
+/// Responses declaring or carrying more content fail with `ResponseMalformed`, without
+/// allocating for the declared length. The limit applies to all threads, by default the
+/// content is only bounded by the size of the decapsulated response.
+#[no_mangle]
+pub extern "C" fn set_max_response_body_ffi(bytes: libc::size_t) {
+    MAX_BODY_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+/// Frees up the parsed response.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_drop_ffi(response: *mut BhttpResponse) {
+    null_safe_ptr!(response, (), {
+        let _response = Box::from_raw(response);
+    })
+}
+
+/// Return the status code of the final response, or 0 if `response` is NULL.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_status_ffi(response: *const BhttpResponse) -> u16 {
+    null_safe_ptr!(response, 0, (*response).status())
+}
+
+/// Return the number of header fields.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_header_count_ffi(
+    response: *const BhttpResponse,
+) -> libc::size_t {
+    null_safe_ptr!(response, 0, (*response).headers().len())
+}
+
+/// Return the name of the header field at `index`.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_header_name_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        field_view((*response).headers(), index, Field::name)
+    )
+}
+
+/// Return the value of the header field at `index`.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_header_value_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        field_view((*response).headers(), index, Field::value)
+    )
+}
+
+/// Return the response content.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_body_ffi(response: *const BhttpResponse) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        BytesView::from_slice((*response).body())
+    )
+}
+
+/// Return the number of trailer fields, 0 if the response has no trailers.
+///
+/// Trailers are never merged into the header fields.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_trailer_count_ffi(
+    response: *const BhttpResponse,
+) -> libc::size_t {
+    null_safe_ptr!(response, 0, (*response).trailers().len())
+}
+
+/// Return the name of the trailer field at `index`.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_trailer_name_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        field_view((*response).trailers(), index, Field::name)
+    )
+}
+
+/// Return the value of the trailer field at `index`.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_trailer_value_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        field_view((*response).trailers(), index, Field::value)
+    )
+}
+
+/// Return the number of informational (1xx) responses preceding the final response,
+/// 0 if there were none.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_informational_count_ffi(
+    response: *const BhttpResponse,
+) -> libc::size_t {
+    null_safe_ptr!(response, 0, (*response).informational().len())
+}
+
+/// Return the status code of the informational response at `index`, or 0 if `index` is
+/// out of range.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_informational_status_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+) -> u16 {
+    null_safe_ptr!(
+        response,
+        0,
+        informational_at(&*response, index).map_or(0, InformationalResponse::status)
+    )
+}
+
+/// Return the number of header fields of the informational response at `index`, or 0 if
+/// `index` is out of range.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_informational_header_count_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+) -> libc::size_t {
+    null_safe_ptr!(
+        response,
+        0,
+        informational_at(&*response, index)
+            .map_or(0, |informational| informational.headers().len())
+    )
+}
+
+/// Return the name of the header field at `field_index` of the informational response at
+/// `index`.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_informational_header_name_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+    field_index: libc::size_t,
+) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        informational_at(&*response, index).map_or(BytesView::empty(), |informational| {
+            field_view(informational.headers(), field_index, Field::name)
+        })
+    )
+}
+
+/// Return the value of the header field at `field_index` of the informational response at
+/// `index`.
+///
+/// The view borrows the response and is valid until the response is dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `BhttpResponse` passed by the caller.
+/// Be sure that the response has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn bhttp_response_informational_header_value_ffi(
+    response: *const BhttpResponse,
+    index: libc::size_t,
+    field_index: libc::size_t,
+) -> BytesView {
+    null_safe_ptr!(
+        response,
+        BytesView::empty(),
+        informational_at(&*response, index).map_or(BytesView::empty(), |informational| {
+            field_view(informational.headers(), field_index, Field::value)
+        })
+    )
+}
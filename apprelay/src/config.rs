@@ -0,0 +1,1292 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Parsing of OHTTP key configurations and the HPKE parameters they reference.
+
+use std::slice;
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{null_safe_ptr, write_out_buffer, ClientError};
+
+/// DHKEM(X25519, HKDF-SHA256)
+pub const KEM_X25519_SHA256: u16 = 0x0020;
+/// HKDF-SHA256
+pub const KDF_HKDF_SHA256: u16 = 0x0001;
+/// AES-128-GCM
+pub const AEAD_AES_128_GCM: u16 = 0x0001;
+/// AES-256-GCM
+pub const AEAD_AES_256_GCM: u16 = 0x0002;
+/// ChaCha20Poly1305
+pub const AEAD_CHACHA20_POLY1305: u16 = 0x0003;
+/// Export-only, can not seal messages
+pub const AEAD_EXPORT_ONLY: u16 = 0xffff;
+
+/// Version of the RFC 9230 `ObliviousDoHConfig` structure understood by [`KeyConfig::from_odoh`].
+const ODOH_CONFIG_VERSION: u16 = 0x0001;
+
+/// Key configuration extension carrying the expiry of the key as a
+/// big-endian, signed 64-bit Unix timestamp in seconds.
+pub const KEY_CONFIG_EXTENSION_EXPIRY: u16 = 0x0001;
+
+/// The key configuration carries an expiry that has not passed yet.
+pub const KEY_CONFIG_VALID: libc::c_int = 0;
+/// The key configuration expired, fetch a fresh one before encapsulating.
+pub const KEY_CONFIG_EXPIRED: libc::c_int = 1;
+/// The key configuration carries no expiry extension.
+pub const KEY_CONFIG_NO_EXPIRY: libc::c_int = 2;
+
+/// Length of the smallest valid key configuration: the key id (1), the KEM id (2), the
+/// shortest public key of a registered KEM, 32 bytes for X25519, the symmetric suites
+/// length (2) and a single KDF and AEAD pair (4).
+///
+/// Shorter input is rejected without parsing.
+pub const MIN_KEY_CONFIG_LEN: usize = 1 + 2 + 32 + 2 + 4;
+
+/// Key id, KEM, KDF and AEAD ids prefixing every encapsulated request.
+pub(crate) const REQUEST_HEADER_LEN: usize = 7;
+
+/// Length of the `Npk` public key of `kem`, if the KEM is registered.
+pub(crate) fn kem_public_key_len(kem: u16) -> Option<usize> {
+    match kem {
+        0x0010 => Some(65),  // DHKEM(P-256, HKDF-SHA256)
+        0x0011 => Some(97),  // DHKEM(P-384, HKDF-SHA384)
+        0x0012 => Some(133), // DHKEM(P-521, HKDF-SHA512)
+        0x0020 => Some(32),  // DHKEM(X25519, HKDF-SHA256)
+        0x0021 => Some(56),  // DHKEM(X448, HKDF-SHA512)
+        _ => None,
+    }
+}
+
+/// Name of a KEM registered in the IANA HPKE registry.
+pub(crate) fn kem_name(kem: u16) -> Option<&'static str> {
+    match kem {
+        0x0010 => Some("DHKEM(P-256, HKDF-SHA256)"),
+        0x0011 => Some("DHKEM(P-384, HKDF-SHA384)"),
+        0x0012 => Some("DHKEM(P-521, HKDF-SHA512)"),
+        0x0020 => Some("DHKEM(X25519, HKDF-SHA256)"),
+        0x0021 => Some("DHKEM(X448, HKDF-SHA512)"),
+        _ => None,
+    }
+}
+
+/// Length of the `Nenc` encapsulated key of `kem`, if the KEM is registered.
+///
+/// The encapsulated key of every registered DHKEM is a serialized public key.
+pub(crate) fn kem_enc_len(kem: u16) -> Option<usize> {
+    kem_public_key_len(kem)
+}
+
+/// Whether the `ohttp` rust-hpke backend this library is built with implements `kem`.
+fn kem_compiled(kem: u16) -> bool {
+    kem == KEM_X25519_SHA256
+}
+
+/// Name of a KDF registered in the IANA HPKE registry.
+pub(crate) fn kdf_name(kdf: u16) -> Option<&'static str> {
+    match kdf {
+        0x0001 => Some("HKDF-SHA256"),
+        0x0002 => Some("HKDF-SHA384"),
+        0x0003 => Some("HKDF-SHA512"),
+        _ => None,
+    }
+}
+
+/// Name of an AEAD registered in the IANA HPKE registry.
+pub(crate) fn aead_name(aead: u16) -> Option<&'static str> {
+    match aead {
+        AEAD_AES_128_GCM => Some("AES-128-GCM"),
+        AEAD_AES_256_GCM => Some("AES-256-GCM"),
+        AEAD_CHACHA20_POLY1305 => Some("ChaCha20Poly1305"),
+        AEAD_EXPORT_ONLY => Some("Export-only"),
+        _ => None,
+    }
+}
+
+/// Key, nonce and tag lengths of an HPKE AEAD and the largest plaintext it can seal.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AeadParams {
+    pub(crate) key_len: usize,
+    pub(crate) nonce_len: usize,
+    pub(crate) tag_len: usize,
+    pub(crate) max_plaintext_len: u64,
+}
+
+pub(crate) fn aead_params(aead: u16) -> Option<AeadParams> {
+    match aead {
+        AEAD_AES_128_GCM => Some(AeadParams {
+            key_len: 16,
+            nonce_len: 12,
+            tag_len: 16,
+            max_plaintext_len: (1 << 36) - 32,
+        }),
+        AEAD_AES_256_GCM => Some(AeadParams {
+            key_len: 32,
+            nonce_len: 12,
+            tag_len: 16,
+            max_plaintext_len: (1 << 36) - 32,
+        }),
+        AEAD_CHACHA20_POLY1305 => Some(AeadParams {
+            key_len: 32,
+            nonce_len: 12,
+            tag_len: 16,
+            max_plaintext_len: (1 << 38) - 64,
+        }),
+        _ => None,
+    }
+}
+
+/// The configuration can not be parsed.
+pub const PREFLIGHT_BAD_CONFIG: libc::c_int = 1;
+/// None of the suites of the configuration is supported by this build.
+pub const PREFLIGHT_UNSUPPORTED_SUITE: libc::c_int = 1 << 1;
+/// The message exceeds the largest plaintext the suite's AEAD can seal.
+pub const PREFLIGHT_MESSAGE_TOO_LARGE: libc::c_int = 1 << 2;
+
+/// HPKE algorithms used for a single request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HpkeSuite {
+    pub kem: u16,
+    pub kdf: u16,
+    pub aead: u16,
+}
+
+impl HpkeSuite {
+    /// Whether the `ohttp` rust-hpke backend this library is built with implements the suite.
+    ///
+    /// AES-256-GCM is registered and its sizes are known, but the backend only implements
+    /// AES-128-GCM and ChaCha20Poly1305, so suites using it are skipped when selecting one
+    /// rather than failing inside `ohttp`.
+    pub fn is_supported(&self) -> bool {
+        kem_compiled(self.kem)
+            && self.kdf == KDF_HKDF_SHA256
+            && matches!(self.aead, AEAD_AES_128_GCM | AEAD_CHACHA20_POLY1305)
+    }
+
+    /// Human-readable `KEM / KDF / AEAD` names of the suite, fails for ids not in the IANA registry.
+    pub fn description(&self) -> Result<String, ClientError> {
+        let unknown = |what: &str, id: u16| {
+            ClientError::InvalidArgument(format!("unknown HPKE {} id {:#06x}", what, id))
+        };
+        let kem = kem_name(self.kem).ok_or_else(|| unknown("KEM", self.kem))?;
+        let kdf = kdf_name(self.kdf).ok_or_else(|| unknown("KDF", self.kdf))?;
+        let aead = aead_name(self.aead).ok_or_else(|| unknown("AEAD", self.aead))?;
+        Ok(format!("{} / {} / {}", kem, kdf, aead))
+    }
+
+    /// Smallest valid encapsulated response: the response nonce followed by the AEAD tag.
+    pub fn min_response_len(&self) -> Option<usize> {
+        self.encapsulated_response_len(0)
+    }
+
+    /// Length of a request encapsulating `msg_len` bytes: the 7 byte header, the
+    /// encapsulated key and the sealed message with its AEAD tag.
+    pub fn encapsulated_request_len(&self, msg_len: usize) -> Option<usize> {
+        let params = aead_params(self.aead)?;
+        Some(REQUEST_HEADER_LEN + kem_enc_len(self.kem)? + msg_len + params.tag_len)
+    }
+
+    /// Length of a response encapsulating `plaintext_len` bytes: the response nonce, as long
+    /// as the longer of the AEAD key and nonce, and the sealed response with its AEAD tag.
+    pub fn encapsulated_response_len(&self, plaintext_len: usize) -> Option<usize> {
+        aead_params(self.aead)
+            .map(|params| params.key_len.max(params.nonce_len) + plaintext_len + params.tag_len)
+    }
+
+    /// Largest message the suite's AEAD can seal.
+    pub fn max_plaintext_len(&self) -> Option<u64> {
+        aead_params(self.aead).map(|params| params.max_plaintext_len)
+    }
+}
+
+/// KDF and AEAD pair offered by a key configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymmetricSuite {
+    pub kdf: u16,
+    pub aead: u16,
+}
+
+/// HPKE algorithm ids of a suite as filled in by [`config_all_suites_ffi`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SuiteTriple {
+    pub kem: u16,
+    pub kdf: u16,
+    pub aead: u16,
+}
+
+impl From<HpkeSuite> for SuiteTriple {
+    fn from(suite: HpkeSuite) -> Self {
+        SuiteTriple {
+            kem: suite.kem,
+            kdf: suite.kdf,
+            aead: suite.aead,
+        }
+    }
+}
+
+/// A decoded OHTTP key configuration.
+///
+/// Deployments may append an extension block to the RFC 9458 encoding:
+///
+/// ```text
+/// Extensions Length (16),
+/// Extension (..) {
+///   Extension Type (16),
+///   Extension Length (16),
+///   Extension Value (..),
+/// } ...
+/// ```
+///
+/// [`KEY_CONFIG_EXTENSION_EXPIRY`] is the only extension understood, others are skipped.
+/// The block is stripped before the configuration is handed to `ohttp`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyConfig {
+    pub key_id: u8,
+    pub kem: u16,
+    pub public_key: Vec<u8>,
+    pub symmetric: Vec<SymmetricSuite>,
+    /// Unix timestamp in seconds after which the key must not be used.
+    pub expiry: Option<i64>,
+}
+
+impl KeyConfig {
+    /// Assembles a key configuration from its parts, checking that `public_key` has the
+    /// length the KEM requires.
+    pub fn new(
+        key_id: u8,
+        kem: u16,
+        public_key: Vec<u8>,
+        symmetric: Vec<SymmetricSuite>,
+    ) -> Result<Self, ClientError> {
+        let expected =
+            kem_public_key_len(kem).ok_or_else(|| malformed(format!("unknown KEM {kem:#06x}")))?;
+        if public_key.len() != expected {
+            return Err(malformed(format!(
+                "public key of KEM {kem:#06x} must be {expected} bytes but got {}",
+                public_key.len()
+            )));
+        }
+        if symmetric.is_empty() {
+            return Err(malformed("no symmetric suites".to_owned()));
+        }
+        Ok(KeyConfig {
+            key_id,
+            kem,
+            public_key,
+            symmetric,
+            expiry: None,
+        })
+    }
+
+    /// Encodes the configuration, appending the extension block if it carries an expiry.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.encoded_len() + 14);
+        encoded.push(self.key_id);
+        encoded.extend_from_slice(&self.kem.to_be_bytes());
+        encoded.extend_from_slice(&self.public_key);
+        encoded.extend_from_slice(&((4 * self.symmetric.len()) as u16).to_be_bytes());
+        for suite in &self.symmetric {
+            encoded.extend_from_slice(&suite.kdf.to_be_bytes());
+            encoded.extend_from_slice(&suite.aead.to_be_bytes());
+        }
+        if let Some(expiry) = self.expiry {
+            encoded.extend_from_slice(&12u16.to_be_bytes());
+            encoded.extend_from_slice(&KEY_CONFIG_EXTENSION_EXPIRY.to_be_bytes());
+            encoded.extend_from_slice(&8u16.to_be_bytes());
+            encoded.extend_from_slice(&expiry.to_be_bytes());
+        }
+        encoded
+    }
+
+    /// Decodes a single key configuration followed by an optional extension block,
+    /// rejecting trailing bytes.
+    ///
+    /// A public key of the wrong length for the declared KEM, e.g. a 65 byte P-256 key
+    /// declared as X25519, is reported with both lengths instead of the parse error it
+    /// leads to further on.
+    pub fn decode(encoded_config: &[u8]) -> Result<Self, ClientError> {
+        check_min_len(encoded_config)?;
+        Self::decode_fields(encoded_config)
+            .map_err(|err| public_key_len_mismatch(encoded_config).unwrap_or(err))
+    }
+
+    /// Decodes the key configuration at the start of `data`, returning it along with the
+    /// number of bytes it occupies so configurations concatenated without length prefixes
+    /// can be walked.
+    ///
+    /// Only the RFC 9458 encoding is consumed, an extension block can not be told apart from
+    /// the next configuration and is left in place. Fails if `data` ends mid-configuration.
+    pub fn decode_prefix(data: &[u8]) -> Result<(Self, usize), ClientError> {
+        check_min_len(data)?;
+        let mut reader = Reader { data };
+        let config = Self::read_fields(&mut reader)?;
+        Ok((config, data.len() - reader.data.len()))
+    }
+
+    fn decode_fields(encoded_config: &[u8]) -> Result<Self, ClientError> {
+        let mut reader = Reader {
+            data: encoded_config,
+        };
+        let mut config = Self::read_fields(&mut reader)?;
+
+        if !reader.data.is_empty() {
+            let extensions_len = reader.read_u16()? as usize;
+            let mut extensions = Reader {
+                data: reader.read_declared(extensions_len, "extensions")?,
+            };
+            while !extensions.data.is_empty() {
+                let extension_type = extensions.read_u16()?;
+                let extension_len = extensions.read_u16()? as usize;
+                let value = extensions.read_declared(extension_len, "extension value")?;
+                if extension_type == KEY_CONFIG_EXTENSION_EXPIRY {
+                    let value: [u8; 8] = value.try_into().map_err(|_| {
+                        malformed(format!("invalid expiry extension length {extension_len}"))
+                    })?;
+                    config.expiry = Some(i64::from_be_bytes(value));
+                }
+            }
+        }
+
+        if !reader.data.is_empty() {
+            return Err(malformed(format!(
+                "{} trailing bytes after the key configuration",
+                reader.data.len()
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Reads the RFC 9458 fields of a configuration, leaving any extension block in `reader`.
+    fn read_fields(reader: &mut Reader) -> Result<Self, ClientError> {
+        let key_id = reader.read_u8()?;
+        let kem = reader.read_u16()?;
+        let public_key_len =
+            kem_public_key_len(kem).ok_or_else(|| malformed(format!("unknown KEM {kem:#06x}")))?;
+        let public_key = reader.read_declared(public_key_len, "public key")?.to_vec();
+
+        let symmetric_len = reader.read_u16()? as usize;
+        if symmetric_len == 0 || !symmetric_len.is_multiple_of(4) {
+            return Err(malformed(format!(
+                "invalid symmetric suites length {symmetric_len}"
+            )));
+        }
+        let mut suites = Reader {
+            data: reader.read_declared(symmetric_len, "symmetric suites")?,
+        };
+        let mut symmetric = Vec::with_capacity(symmetric_len / 4);
+        while !suites.data.is_empty() {
+            symmetric.push(SymmetricSuite {
+                kdf: suites.read_u16()?,
+                aead: suites.read_u16()?,
+            });
+        }
+
+        Ok(KeyConfig {
+            key_id,
+            kem,
+            public_key,
+            symmetric,
+            expiry: None,
+        })
+    }
+
+    /// Decodes an `application/ohttp-keys` list, key configurations each prefixed with
+    /// their 2 byte length.
+    pub fn decode_list(encoded_list: &[u8]) -> Result<Vec<Self>, ClientError> {
+        split_list(encoded_list)?
+            .into_iter()
+            .map(KeyConfig::decode)
+            .collect()
+    }
+
+    /// Translates the first supported configuration of an encoded RFC 9230
+    /// `ObliviousDoHConfigs` list into an OHTTP key configuration.
+    ///
+    /// The fields map as follows:
+    ///
+    /// ```text
+    /// ObliviousDoHConfigContents    KeyConfig
+    ///   (none)                        Key Identifier = key_id
+    ///   kem_id                        HPKE KEM ID
+    ///   public_key                    HPKE Public Key
+    ///   kdf_id, aead_id               the only HPKE Symmetric Algorithms entry
+    /// ```
+    ///
+    /// ODoH identifies keys by a digest of the configuration instead of an 8-bit identifier,
+    /// so `key_id` has to be the identifier the OHTTP gateway serves the same key under.
+    /// Configurations of versions other than 0x0001 are skipped as RFC 9230 requires.
+    pub fn from_odoh(odoh_configs: &[u8], key_id: u8) -> Result<Self, ClientError> {
+        let mut reader = Reader { data: odoh_configs };
+        let configs_len = reader.read_u16()? as usize;
+        let mut configs = Reader {
+            data: reader.read_declared(configs_len, "ODoH configurations")?,
+        };
+        if !reader.data.is_empty() {
+            return Err(malformed(format!(
+                "{} trailing bytes after the ODoH configurations",
+                reader.data.len()
+            )));
+        }
+
+        while !configs.data.is_empty() {
+            let version = configs.read_u16()?;
+            let contents_len = configs.read_u16()? as usize;
+            let mut contents = Reader {
+                data: configs.read_declared(contents_len, "ODoH configuration contents")?,
+            };
+            if version != ODOH_CONFIG_VERSION {
+                continue;
+            }
+
+            let kem = contents.read_u16()?;
+            let kdf = contents.read_u16()?;
+            let aead = contents.read_u16()?;
+            let public_key_len = contents.read_u16()? as usize;
+            let public_key = contents
+                .read_declared(public_key_len, "public key")?
+                .to_vec();
+            if aead == AEAD_EXPORT_ONLY {
+                return Err(malformed(
+                    "export-only ODoH configurations can not encrypt OHTTP messages".to_owned(),
+                ));
+            }
+            return KeyConfig::new(key_id, kem, public_key, vec![SymmetricSuite { kdf, aead }]);
+        }
+        Err(malformed(format!(
+            "no ODoH configuration of version {ODOH_CONFIG_VERSION:#06x}"
+        )))
+    }
+
+    /// Lists every problem found in `encoded_config`, one human-readable line each.
+    ///
+    /// Unlike [`KeyConfig::decode`] this keeps going after a problem wherever the rest of the
+    /// encoding can still be located, so an empty list means `decode` succeeds and a suite is
+    /// supported by this build.
+    pub fn diagnose(encoded_config: &[u8]) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut reader = Reader {
+            data: encoded_config,
+        };
+        let truncated = |what: &str, reader: &Reader| {
+            format!("truncated {what}, {} bytes remain", reader.data.len())
+        };
+
+        if reader.read_u8().is_err() {
+            problems.push("empty configuration".to_owned());
+            return problems;
+        }
+        let kem = match reader.read_u16() {
+            Ok(kem) => kem,
+            Err(_) => {
+                problems.push(truncated("KEM id", &reader));
+                return problems;
+            }
+        };
+        if let Some(ClientError::MalformedConfig(reason)) = public_key_len_mismatch(encoded_config)
+        {
+            problems.push(reason);
+            return problems;
+        }
+        let public_key_len = match kem_public_key_len(kem) {
+            Some(len) => len,
+            None => {
+                problems.push(format!(
+                    "unknown KEM {kem:#06x}, the rest of the configuration can not be located"
+                ));
+                return problems;
+            }
+        };
+        if reader.read_bytes(public_key_len).is_err() {
+            problems.push(format!(
+                "truncated public key, KEM {kem:#06x} requires {public_key_len} bytes but {} remain",
+                reader.data.len()
+            ));
+            return problems;
+        }
+
+        let symmetric_len = match reader.read_u16() {
+            Ok(len) => len as usize,
+            Err(_) => {
+                problems.push(truncated("symmetric suites length", &reader));
+                return problems;
+            }
+        };
+        if symmetric_len == 0 {
+            problems.push("no symmetric suites".to_owned());
+        } else if !symmetric_len.is_multiple_of(4) {
+            problems.push(format!(
+                "symmetric suites length {symmetric_len} is not a multiple of 4"
+            ));
+        }
+        let available = symmetric_len.min(reader.data.len());
+        if available < symmetric_len {
+            problems.push(format!(
+                "truncated symmetric suites, expected {symmetric_len} bytes but {available} remain"
+            ));
+        }
+        let mut suites = Reader {
+            data: reader.read_bytes(available).unwrap_or_default(),
+        };
+        let mut seen = Vec::new();
+        let mut any_supported = false;
+        while let (Ok(kdf), Ok(aead)) = (suites.read_u16(), suites.read_u16()) {
+            let suite = HpkeSuite { kem, kdf, aead };
+            if kdf_name(kdf).is_none() {
+                problems.push(format!("unknown KDF {kdf:#06x}"));
+            }
+            if aead_name(aead).is_none() {
+                problems.push(format!("unknown AEAD {aead:#06x}"));
+            }
+            if seen.contains(&suite) {
+                problems.push(format!("duplicate suite KDF {kdf:#06x} / AEAD {aead:#06x}"));
+            }
+            any_supported |= suite.is_supported();
+            seen.push(suite);
+        }
+        if !seen.is_empty() && !any_supported {
+            problems.push("no suite is supported by this build".to_owned());
+        }
+
+        if !reader.data.is_empty() {
+            match reader.read_u16() {
+                Ok(extensions_len) => {
+                    let extensions_len = extensions_len as usize;
+                    if extensions_len > reader.data.len() {
+                        problems.push(format!(
+                            "truncated extensions, expected {extensions_len} bytes but {} remain",
+                            reader.data.len()
+                        ));
+                    }
+                    let mut extensions = Reader {
+                        data: reader
+                            .read_bytes(extensions_len.min(reader.data.len()))
+                            .unwrap_or_default(),
+                    };
+                    while !extensions.data.is_empty() {
+                        let (extension_type, extension_len) =
+                            match (extensions.read_u16(), extensions.read_u16()) {
+                                (Ok(extension_type), Ok(len)) => (extension_type, len as usize),
+                                _ => {
+                                    problems.push(truncated("extension header", &extensions));
+                                    break;
+                                }
+                            };
+                        if extensions.read_bytes(extension_len).is_err() {
+                            problems.push(format!(
+                                "truncated extension {extension_type:#06x}, expected {extension_len} bytes but {} remain",
+                                extensions.data.len()
+                            ));
+                            break;
+                        }
+                        if extension_type == KEY_CONFIG_EXTENSION_EXPIRY && extension_len != 8 {
+                            problems
+                                .push(format!("invalid expiry extension length {extension_len}"));
+                        }
+                    }
+                }
+                Err(_) => problems.push(truncated("extensions length", &reader)),
+            }
+        }
+
+        if !reader.data.is_empty() {
+            problems.push(format!(
+                "{} trailing bytes after the key configuration",
+                reader.data.len()
+            ));
+        }
+        problems
+    }
+
+    /// Length of the RFC 9458 encoding, excluding any extension block.
+    pub fn encoded_len(&self) -> usize {
+        1 + 2 + self.public_key.len() + 2 + 4 * self.symmetric.len()
+    }
+
+    /// Whether the key expired at `now_unix`, `None` if it carries no expiry.
+    pub fn is_expired(&self, now_unix: i64) -> Option<bool> {
+        self.expiry.map(|expiry| now_unix >= expiry)
+    }
+
+    /// The first suite listed, which `ohttp` encapsulates with when given this configuration.
+    pub fn default_suite(&self) -> HpkeSuite {
+        self.suites()
+            .next()
+            .expect("key configurations list at least one suite")
+    }
+
+    /// All suites of the configuration, in the gateway's order of preference.
+    pub fn suites(&self) -> impl Iterator<Item = HpkeSuite> + '_ {
+        self.symmetric.iter().map(move |symmetric| HpkeSuite {
+            kem: self.kem,
+            kdf: symmetric.kdf,
+            aead: symmetric.aead,
+        })
+    }
+
+    /// The first suite of the configuration this build supports, so a gateway listing
+    /// suites of other backends first stays usable.
+    ///
+    /// Fails with [`ClientError::KemNotCompiled`] if the backend does not implement the KEM of
+    /// the configuration, otherwise with [`ClientError::UnsupportedSuite`] if no suite is
+    /// supported.
+    pub fn select_supported_suite(&self) -> Result<HpkeSuite, ClientError> {
+        if !kem_compiled(self.kem) {
+            return Err(ClientError::KemNotCompiled(self.kem));
+        }
+        self.suites()
+            .find(HpkeSuite::is_supported)
+            .ok_or(ClientError::UnsupportedSuite)
+    }
+
+    /// The suite of the configuration with `kdf` and `aead`, regardless of where the
+    /// configuration lists it, for clients whose policy mandates an AEAD.
+    ///
+    /// Fails with [`ClientError::KemNotCompiled`] like [`KeyConfig::select_supported_suite`],
+    /// and with [`ClientError::UnsupportedSuite`] if the configuration does not offer the
+    /// suite or this build does not support it.
+    pub fn select_suite(&self, kdf: u16, aead: u16) -> Result<HpkeSuite, ClientError> {
+        if !kem_compiled(self.kem) {
+            return Err(ClientError::KemNotCompiled(self.kem));
+        }
+        self.suites()
+            .find(|suite| suite.kdf == kdf && suite.aead == aead)
+            .filter(HpkeSuite::is_supported)
+            .ok_or(ClientError::UnsupportedSuite)
+    }
+
+    /// The configuration offering only `suite` and no extensions, for handing to `ohttp`.
+    pub(crate) fn restricted_to(&self, suite: HpkeSuite) -> KeyConfig {
+        KeyConfig {
+            key_id: self.key_id,
+            kem: self.kem,
+            public_key: self.public_key.clone(),
+            symmetric: vec![SymmetricSuite {
+                kdf: suite.kdf,
+                aead: suite.aead,
+            }],
+            expiry: None,
+        }
+    }
+}
+
+fn malformed(reason: String) -> ClientError {
+    ClientError::MalformedConfig(reason)
+}
+
+/// Rejects input shorter than [`MIN_KEY_CONFIG_LEN`] before any parsing.
+fn check_min_len(data: &[u8]) -> Result<(), ClientError> {
+    if data.len() < MIN_KEY_CONFIG_LEN {
+        return Err(malformed(format!(
+            "configuration of {} bytes is shorter than the minimum of {MIN_KEY_CONFIG_LEN}",
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Splits an `application/ohttp-keys` list into its encoded configurations.
+fn split_list(encoded_list: &[u8]) -> Result<Vec<&[u8]>, ClientError> {
+    let mut reader = Reader { data: encoded_list };
+    let mut configs = Vec::new();
+    while !reader.data.is_empty() {
+        let len = reader.read_u16()? as usize;
+        configs.push(reader.read_declared(len, "key configuration")?);
+    }
+    Ok(configs)
+}
+
+/// Concatenates two `application/ohttp-keys` lists, dropping duplicates.
+///
+/// A configuration with the key id, KEM and public key of an earlier one is a duplicate
+/// and only the earlier one is kept, along with its suites and extensions. The same key
+/// id with a different KEM or public key is a conflict and fails with
+/// [`ClientError::MalformedConfig`], since the gateway could only hold one of them.
+pub fn merge_key_config_lists(first: &[u8], second: &[u8]) -> Result<Vec<u8>, ClientError> {
+    let mut merged = Vec::with_capacity(first.len() + second.len());
+    let mut kept: Vec<KeyConfig> = Vec::new();
+    for encoded in split_list(first)?.into_iter().chain(split_list(second)?) {
+        let config = KeyConfig::decode(encoded)?;
+        match kept.iter().find(|kept| kept.key_id == config.key_id) {
+            Some(kept) if kept.kem == config.kem && kept.public_key == config.public_key => {}
+            Some(_) => {
+                return Err(malformed(format!(
+                    "conflicting configurations for key id {}",
+                    config.key_id
+                )))
+            }
+            None => {
+                merged.extend_from_slice(&(encoded.len() as u16).to_be_bytes());
+                merged.extend_from_slice(encoded);
+                kept.push(config);
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// Extracts the key configuration from the value of an `ohttp` SvcParam of a DNS SVCB or
+/// HTTPS record, as handed over by a resolver.
+///
+/// The value is an `application/ohttp-keys` list. The first configuration with a suite this
+/// build supports is returned as encoded in the record, unsupported ones are skipped, as are
+/// ones this crate can not decode, e.g. with a KEM registered after it was written. An
+/// empty value only announces that the target is an OHTTP gateway, the configuration then
+/// has to be fetched from the gateway itself.
+pub fn key_config_from_svcb_param(param: &[u8]) -> Result<&[u8], ClientError> {
+    if param.is_empty() {
+        return Err(malformed(
+            "the SvcParam carries no key configuration, fetch it from the gateway".to_owned(),
+        ));
+    }
+    let configs = split_list(param)
+        .map_err(|err| malformed(format!("SvcParam is not a key configuration list: {err}")))?;
+    for encoded in configs {
+        match KeyConfig::decode(encoded) {
+            Ok(config) if config.select_supported_suite().is_ok() => return Ok(encoded),
+            _ => continue,
+        }
+    }
+    Err(ClientError::UnsupportedSuite)
+}
+
+/// Public key lengths of the registered KEMs, see [`kem_public_key_len`].
+const PUBLIC_KEY_LENS: [usize; 5] = [32, 56, 65, 97, 133];
+
+/// The error for a configuration whose public key has the length of another KEM than the
+/// declared one, detected by the rest of the configuration only lining up with that length.
+fn public_key_len_mismatch(encoded_config: &[u8]) -> Option<ClientError> {
+    let kem = u16::from_be_bytes([*encoded_config.get(1)?, *encoded_config.get(2)?]);
+    let expected = kem_public_key_len(kem)?;
+    let rest = &encoded_config[3..];
+    let actual = PUBLIC_KEY_LENS
+        .into_iter()
+        .filter(|len| *len != expected && *len <= rest.len())
+        .find(|len| suites_and_extensions_fit(&rest[*len..]))?;
+    Some(malformed(format!(
+        "public key of KEM {kem:#06x} must be {expected} bytes but the configuration carries {actual}"
+    )))
+}
+
+/// Whether `data` is exactly a symmetric suites block followed by an optional extension block.
+fn suites_and_extensions_fit(data: &[u8]) -> bool {
+    let mut reader = Reader { data };
+    let symmetric_len = match reader.read_u16() {
+        Ok(len) => len as usize,
+        Err(_) => return false,
+    };
+    if symmetric_len == 0
+        || !symmetric_len.is_multiple_of(4)
+        || reader.read_bytes(symmetric_len).is_err()
+    {
+        return false;
+    }
+    if reader.data.is_empty() {
+        return true;
+    }
+    match reader.read_u16() {
+        Ok(extensions_len) => reader.data.len() == extensions_len as usize,
+        Err(_) => false,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ClientError> {
+        if len > self.data.len() {
+            return Err(malformed(format!(
+                "expected {len} more bytes but only {} remain",
+                self.data.len()
+            )));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a field whose length was declared by the input, so a length running past the
+    /// end is reported as such and not as a truncated field.
+    fn read_declared(&mut self, len: usize, field: &str) -> Result<&'a [u8], ClientError> {
+        if len > self.data.len() {
+            return Err(malformed(format!(
+                "declared length exceeds buffer, {field} of {len} bytes but only {} remain",
+                self.data.len()
+            )));
+        }
+        self.read_bytes(len)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ClientError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, ClientError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+/// Checks the expiry extension of a key configuration against `now_unix` (seconds since the Unix epoch).
+///
+/// Returns [`KEY_CONFIG_VALID`], [`KEY_CONFIG_EXPIRED`], [`KEY_CONFIG_NO_EXPIRY`],
+/// or -1 if the configuration can not be parsed.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn key_config_is_expired_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+    now_unix: i64,
+) -> libc::c_int {
+    let config_ptr = null_safe_ptr!(config_ptr, -1, config_ptr);
+    let config = match KeyConfig::decode(slice::from_raw_parts(config_ptr, config_len)) {
+        Ok(config) => config,
+        Err(err) => {
+            update_last_error(err);
+            return -1;
+        }
+    };
+    match config.is_expired(now_unix) {
+        Some(true) => KEY_CONFIG_EXPIRED,
+        Some(false) => KEY_CONFIG_VALID,
+        None => KEY_CONFIG_NO_EXPIRY,
+    }
+}
+
+/// Assembles an encoded key configuration offering a single `kdf`/`aead` suite from
+/// the raw HPKE `public_key` of a gateway, and writes it into `out`.
+///
+/// Returns the number of bytes written, or -1 if the public key length does not match
+/// `kem`, the KEM is unknown or `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn build_key_config_ffi(
+    key_id: u8,
+    kem: u16,
+    public_key_ptr: *const u8,
+    public_key_len: libc::size_t,
+    kdf: u16,
+    aead: u16,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let public_key_ptr = null_safe_ptr!(public_key_ptr, -1, public_key_ptr);
+    let public_key = slice::from_raw_parts(public_key_ptr, public_key_len).to_vec();
+
+    match KeyConfig::new(key_id, kem, public_key, vec![SymmetricSuite { kdf, aead }]) {
+        Ok(config) => write_out_buffer(&config.encode(), out, out_cap),
+        Err(err) => {
+            update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Checks whether encapsulating a message of `msg_len` bytes with the configuration would
+/// fail, without encapsulating anything.
+///
+/// Returns 0 if encapsulation is expected to succeed, otherwise a bitmask of:
+/// - [`PREFLIGHT_BAD_CONFIG`] when the configuration can not be parsed, no other checks are done then.
+/// - [`PREFLIGHT_UNSUPPORTED_SUITE`] when no suite of the configuration is supported by this build.
+/// - [`PREFLIGHT_MESSAGE_TOO_LARGE`] when the message exceeds the AEAD plaintext limit.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn preflight_encapsulate_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+    msg_len: libc::size_t,
+) -> libc::c_int {
+    if config_ptr.is_null() {
+        return PREFLIGHT_BAD_CONFIG;
+    }
+    let config = match KeyConfig::decode(slice::from_raw_parts(config_ptr, config_len)) {
+        Ok(config) => config,
+        Err(_) => return PREFLIGHT_BAD_CONFIG,
+    };
+
+    let mut problems = 0;
+    let suite = config.select_supported_suite().unwrap_or_else(|_| {
+        problems |= PREFLIGHT_UNSUPPORTED_SUITE;
+        config.default_suite()
+    });
+    if let Some(max_plaintext_len) = suite.max_plaintext_len() {
+        if msg_len as u64 > max_plaintext_len {
+            problems |= PREFLIGHT_MESSAGE_TOO_LARGE;
+        }
+    }
+    problems
+}
+
+/// Writes a NUL terminated description of the suite such as
+/// `DHKEM(X25519, HKDF-SHA256) / HKDF-SHA256 / AES-128-GCM` into `out`.
+///
+/// Returns the length of the description without the NUL terminator, or -1 if any of
+/// the ids is not registered or `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[no_mangle]
+pub unsafe extern "C" fn suite_description_ffi(
+    kem: u16,
+    kdf: u16,
+    aead: u16,
+    out: *mut libc::c_char,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let description = match (HpkeSuite { kem, kdf, aead }).description() {
+        Ok(description) => description,
+        Err(err) => {
+            update_last_error(err);
+            return -1;
+        }
+    };
+    let mut bytes = description.into_bytes();
+    bytes.push(0);
+    match write_out_buffer(&bytes, out as *mut u8, out_cap) {
+        -1 => -1,
+        written => written - 1,
+    }
+}
+
+/// Writes a NUL terminated report of every problem found in the key configuration into `out`,
+/// one line per problem, see [`KeyConfig::diagnose`].
+///
+/// Returns the length of the report without the NUL terminator, 0 if the configuration has
+/// no problems, or -1 if `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn diagnose_key_config_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+    out: *mut libc::c_char,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let config_ptr = null_safe_ptr!(config_ptr, -1, config_ptr);
+    let config = slice::from_raw_parts(config_ptr, config_len);
+
+    let mut bytes = KeyConfig::diagnose(config).join("\n").into_bytes();
+    bytes.push(0);
+    match write_out_buffer(&bytes, out as *mut u8, out_cap) {
+        -1 => -1,
+        written => written - 1,
+    }
+}
+
+/// Returns [`MIN_KEY_CONFIG_LEN`], the length below which a key configuration can not be
+/// valid, for a cheap check of untrusted input before parsing it.
+#[no_mangle]
+pub extern "C" fn min_key_config_len_ffi() -> libc::size_t {
+    MIN_KEY_CONFIG_LEN
+}
+
+/// Returns the length of the encapsulated key (`enc`) that prefixes requests sealed with `kem`,
+/// e.g. 32 for [`KEM_X25519_SHA256`], or -1 if the KEM is not registered.
+#[no_mangle]
+pub extern "C" fn kem_enc_len_ffi(kem: u16) -> libc::ssize_t {
+    match kem_enc_len(kem) {
+        Some(len) => len as libc::ssize_t,
+        None => {
+            update_last_error(ClientError::InvalidArgument(format!(
+                "unknown HPKE KEM id {:#06x}",
+                kem
+            )));
+            -1
+        }
+    }
+}
+
+/// Returns the length of the request [`crate::encapsulate_request_ffi`] produces for a message
+/// of `msg_len` bytes with the configuration, or -1 if the configuration can not be parsed,
+/// none of its suites is supported or the message exceeds the AEAD plaintext limit.
+///
+/// The length depends on the KEM and the AEAD of the selected suite, so size buffers with
+/// this function rather than a fixed overhead.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn encapsulated_request_len_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+    msg_len: libc::size_t,
+) -> libc::ssize_t {
+    let config_ptr = null_safe_ptr!(config_ptr, -1, config_ptr);
+    let config = slice::from_raw_parts(config_ptr, config_len);
+
+    let suite = match KeyConfig::decode(config).and_then(|config| config.select_supported_suite()) {
+        Ok(suite) => suite,
+        Err(err) => {
+            update_last_error(err);
+            return -1;
+        }
+    };
+    match suite.max_plaintext_len() {
+        Some(max_plaintext_len) if msg_len as u64 > max_plaintext_len => {
+            update_last_error(ClientError::InvalidArgument(format!(
+                "message of {} bytes exceeds the AEAD plaintext limit of {} bytes",
+                msg_len, max_plaintext_len
+            )));
+            -1
+        }
+        _ => match suite.encapsulated_request_len(msg_len) {
+            Some(len) => len as libc::ssize_t,
+            None => {
+                update_last_error(ClientError::UnsupportedSuite);
+                -1
+            }
+        },
+    }
+}
+
+/// Whether this build can encapsulate with the `kem`/`kdf`/`aead` suite, the same check
+/// used to select a suite from a key configuration.
+///
+/// Returns false for unknown ids.
+#[no_mangle]
+pub extern "C" fn is_suite_supported_ffi(kem: u16, kdf: u16, aead: u16) -> bool {
+    HpkeSuite { kem, kdf, aead }.is_supported()
+}
+
+/// Merges two encoded `application/ohttp-keys` lists into `out`, see
+/// [`merge_key_config_lists`] for how duplicates are handled.
+///
+/// Returns the number of bytes written, or -1 if either list is malformed, they hold
+/// conflicting configurations for a key id or `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn key_config_list_merge_ffi(
+    a_ptr: *const u8,
+    a_len: libc::size_t,
+    b_ptr: *const u8,
+    b_len: libc::size_t,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let a_ptr = null_safe_ptr!(a_ptr, -1, a_ptr);
+    let b_ptr = null_safe_ptr!(b_ptr, -1, b_ptr);
+    let a = slice::from_raw_parts(a_ptr, a_len);
+    let b = slice::from_raw_parts(b_ptr, b_len);
+
+    match merge_key_config_lists(a, b) {
+        Ok(merged) => write_out_buffer(&merged, out, out_cap),
+        Err(err) => {
+            update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Translates an encoded RFC 9230 `ObliviousDoHConfigs` list into an OHTTP key configuration
+/// served by the gateway under `key_id`, and writes it into `out`.
+///
+/// See [`KeyConfig::from_odoh`] for the field mapping. Returns the number of bytes written,
+/// or -1 if the ODoH configurations are malformed, none has a supported version, the AEAD is
+/// export-only or `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn odoh_config_to_ohttp_ffi(
+    odoh_config_ptr: *const u8,
+    odoh_config_len: libc::size_t,
+    key_id: u8,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let odoh_config_ptr = null_safe_ptr!(odoh_config_ptr, -1, odoh_config_ptr);
+    let odoh_config = slice::from_raw_parts(odoh_config_ptr, odoh_config_len);
+
+    match KeyConfig::from_odoh(odoh_config, key_id) {
+        Ok(config) => write_out_buffer(&config.encode(), out, out_cap),
+        Err(err) => {
+            update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Parses the key configuration at the start of `ptr`, copies its encoding into `out_config`
+/// and stores the number of bytes it occupies in `out_consumed`, so configurations
+/// concatenated without length prefixes can be walked by advancing by that count.
+///
+/// See [`KeyConfig::decode_prefix`], extension blocks are not consumed. Returns 0 on success,
+/// or -1 if the buffer ends mid-configuration, the configuration is malformed or
+/// `out_config` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+/// and `out_consumed` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn key_config_parse_one_ffi(
+    ptr: *const u8,
+    len: libc::size_t,
+    out_config: *mut u8,
+    out_cap: libc::size_t,
+    out_consumed: *mut libc::size_t,
+) -> libc::c_int {
+    let ptr = null_safe_ptr!(ptr, -1, ptr);
+    let out_consumed = null_safe_ptr!(out_consumed, -1, out_consumed);
+    let data = slice::from_raw_parts(ptr, len);
+
+    let consumed = match KeyConfig::decode_prefix(data) {
+        Ok((_, consumed)) => consumed,
+        Err(err) => {
+            update_last_error(err);
+            return -1;
+        }
+    };
+    if write_out_buffer(&data[..consumed], out_config, out_cap) == -1 {
+        return -1;
+    }
+    *out_consumed = consumed;
+    0
+}
+
+/// Extracts the key configuration from the value of an `ohttp` SvcParam of a DNS SVCB or
+/// HTTPS record into `out`, see [`key_config_from_svcb_param`].
+///
+/// Returns the number of bytes written, or -1 if the parameter is empty or malformed, none
+/// of its configurations is supported or `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn key_config_from_svcb_param_ffi(
+    param_ptr: *const u8,
+    param_len: libc::size_t,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let param_ptr = null_safe_ptr!(param_ptr, -1, param_ptr);
+    let param = slice::from_raw_parts(param_ptr, param_len);
+
+    match key_config_from_svcb_param(param) {
+        Ok(config) => write_out_buffer(config, out, out_cap),
+        Err(err) => {
+            update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Returns the number of symmetric suites, (KDF, AEAD) pairs, the key configuration
+/// offers, or -1 if it is malformed.
+///
+/// Encapsulation uses the first of them this build supports, see
+/// [`KeyConfig::select_supported_suite`].
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn config_symmetric_suite_count_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+) -> libc::ssize_t {
+    let config_ptr = null_safe_ptr!(config_ptr, -1, config_ptr);
+    match KeyConfig::decode(slice::from_raw_parts(config_ptr, config_len)) {
+        Ok(config) => config.symmetric.len() as libc::ssize_t,
+        Err(err) => {
+            update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Writes the KDF and AEAD ids of the symmetric suite at `index` of the key configuration
+/// into `out_kdf` and `out_aead`, in the gateway's order of preference.
+///
+/// Returns 1 if this build supports the suite, 0 if it does not, or -1 if the configuration
+/// is malformed or `index` is out of range, leaving the outputs untouched.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+/// and `out_kdf` and `out_aead` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn config_symmetric_suite_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+    index: libc::size_t,
+    out_kdf: *mut u16,
+    out_aead: *mut u16,
+) -> libc::c_int {
+    let config_ptr = null_safe_ptr!(config_ptr, -1, config_ptr);
+    let out_kdf = null_safe_ptr!(out_kdf, -1, out_kdf);
+    let out_aead = null_safe_ptr!(out_aead, -1, out_aead);
+    let config = match KeyConfig::decode(slice::from_raw_parts(config_ptr, config_len)) {
+        Ok(config) => config,
+        Err(err) => {
+            update_last_error(err);
+            return -1;
+        }
+    };
+    let suite = match config.suites().nth(index) {
+        Some(suite) => suite,
+        None => {
+            update_last_error(ClientError::InvalidArgument(format!(
+                "Symmetric suite index {index} out of range, the configuration offers {}",
+                config.symmetric.len()
+            )));
+            return -1;
+        }
+    };
+    *out_kdf = suite.kdf;
+    *out_aead = suite.aead;
+    suite.is_supported() as libc::c_int
+}
+
+/// Writes every (KEM, KDF, AEAD) suite the key configuration offers into `out`, in the
+/// gateway's order of preference, for clients negotiating or reporting capabilities.
+///
+/// A configuration has a single KEM, so there is one suite per symmetric suite, including
+/// those this build does not support, see [`HpkeSuite::is_supported`].
+///
+/// Returns the number of suites written, or -1 if the configuration is malformed or `out`
+/// can not hold all of them, in which case `config_symmetric_suite_count_ffi` tells the size
+/// to retry with.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+/// and `out` must be valid for writes of `out_cap` suites.
+#[no_mangle]
+pub unsafe extern "C" fn config_all_suites_ffi(
+    config_ptr: *const u8,
+    config_len: libc::size_t,
+    out: *mut SuiteTriple,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let config_ptr = null_safe_ptr!(config_ptr, -1, config_ptr);
+    let out = null_safe_ptr!(out, -1, out);
+    let config = match KeyConfig::decode(slice::from_raw_parts(config_ptr, config_len)) {
+        Ok(config) => config,
+        Err(err) => {
+            update_last_error(err);
+            return -1;
+        }
+    };
+    if config.symmetric.len() > out_cap {
+        update_last_error(ClientError::BufferTooSmall {
+            needed: config.symmetric.len(),
+            available: out_cap,
+        });
+        return -1;
+    }
+    let out = slice::from_raw_parts_mut(out, config.symmetric.len());
+    for (slot, suite) in out.iter_mut().zip(config.suites()) {
+        *slot = suite.into();
+    }
+    config.symmetric.len() as libc::ssize_t
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The HPKE operations behind [`crate::RequestContext`], behind a trait so the context
+//! lifecycle and error mapping can be exercised without real crypto.
+
+use std::panic::UnwindSafe;
+
+use ohttp::{ClientRequest, ClientResponse};
+
+#[cfg(feature = "mock-crypto")]
+use crate::config;
+use crate::config::KeyConfig;
+use crate::{check_system_rng, ClientError};
+
+/// Encapsulates requests, [`OhttpProvider`] in production.
+pub trait CryptoProvider {
+    /// Encapsulates `msg` to `config`, which offers only the suite selected for the request.
+    ///
+    /// Returns the encapsulated request and the state opening its response.
+    fn encapsulate(
+        &self,
+        config: &KeyConfig,
+        msg: &[u8],
+    ) -> Result<(Vec<u8>, Box<dyn ResponseOpener>), ClientError>;
+}
+
+/// Opens the encapsulated response of a single request, consuming the request's state.
+pub trait ResponseOpener: UnwindSafe {
+    fn open(self: Box<Self>, encapsulated_response: &[u8]) -> Result<Vec<u8>, ClientError>;
+}
+
+/// The `ohttp` rust-hpke backend, used by [`crate::RequestContext::new`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OhttpProvider;
+
+impl CryptoProvider for OhttpProvider {
+    fn encapsulate(
+        &self,
+        config: &KeyConfig,
+        msg: &[u8],
+    ) -> Result<(Vec<u8>, Box<dyn ResponseOpener>), ClientError> {
+        check_system_rng()?;
+        let client = ClientRequest::new(&config.encode())
+            .map_err(ClientError::RequestContextInitialization)?;
+        let (encapsulated_request, response_context) = client
+            .encapsulate(msg)
+            .map_err(ClientError::EncapsulationFailed)?;
+        Ok((encapsulated_request, Box::new(response_context)))
+    }
+}
+
+impl ResponseOpener for ClientResponse {
+    fn open(self: Box<Self>, encapsulated_response: &[u8]) -> Result<Vec<u8>, ClientError> {
+        self.decapsulate(encapsulated_response).map_err(open_error)
+    }
+}
+
+/// Only a failed AEAD check means the response was not sealed for this request or was
+/// altered, anything else went wrong before the ciphertext was authenticated.
+fn open_error(err: ohttp::Error) -> ClientError {
+    match err {
+        ohttp::Error::Aead(_) => ClientError::ResponseAuthFailed(err),
+        _ => ClientError::DecapsulationFailed(err),
+    }
+}
+
+/// Predictable stand-in for [`OhttpProvider`], **never use it outside of tests**.
+///
+/// The encapsulated request is the 7 byte header of the configuration, an all-zero `enc`
+/// of the KEM's length and `msg` in the clear, and responses open to themselves. The
+/// `fail_*` switches fail the matching step with the error `ohttp` would cause there.
+#[cfg(feature = "mock-crypto")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MockCryptoProvider {
+    /// Fails encapsulation with [`ClientError::EncapsulationFailed`].
+    pub fail_encapsulate: bool,
+    /// Fails opening the response with [`ClientError::ResponseAuthFailed`].
+    pub fail_open: bool,
+    /// Fails opening the response with [`ClientError::DecapsulationFailed`], as a response
+    /// `ohttp` can not parse does.
+    pub fail_open_malformed: bool,
+    /// Fails encapsulation with [`ClientError::RngUnavailable`], as a sandbox blocking
+    /// `getrandom` does. The mock draws no randomness otherwise.
+    pub fail_rng: bool,
+}
+
+#[cfg(feature = "mock-crypto")]
+impl CryptoProvider for MockCryptoProvider {
+    fn encapsulate(
+        &self,
+        config: &KeyConfig,
+        msg: &[u8],
+    ) -> Result<(Vec<u8>, Box<dyn ResponseOpener>), ClientError> {
+        if self.fail_rng {
+            return Err(ClientError::RngUnavailable(getrandom::Error::UNSUPPORTED));
+        }
+        if self.fail_encapsulate {
+            return Err(ClientError::EncapsulationFailed(ohttp::Error::Unsupported));
+        }
+        let suite = config.default_suite();
+        let enc_len = config::kem_enc_len(suite.kem).ok_or(ClientError::UnsupportedSuite)?;
+        let mut request = Vec::with_capacity(config::REQUEST_HEADER_LEN + enc_len + msg.len());
+        request.push(config.key_id);
+        request.extend_from_slice(&suite.kem.to_be_bytes());
+        request.extend_from_slice(&suite.kdf.to_be_bytes());
+        request.extend_from_slice(&suite.aead.to_be_bytes());
+        request.resize(request.len() + enc_len, 0);
+        request.extend_from_slice(msg);
+        Ok((
+            request,
+            Box::new(MockOpener {
+                fail_auth: self.fail_open,
+                fail_malformed: self.fail_open_malformed,
+            }),
+        ))
+    }
+}
+
+#[cfg(feature = "mock-crypto")]
+struct MockOpener {
+    fail_auth: bool,
+    fail_malformed: bool,
+}
+
+#[cfg(feature = "mock-crypto")]
+impl ResponseOpener for MockOpener {
+    fn open(self: Box<Self>, encapsulated_response: &[u8]) -> Result<Vec<u8>, ClientError> {
+        if self.fail_auth {
+            // The `aead::Error` behind `ohttp::Error::Aead` can not be built outside of
+            // `ohttp`, any source stands in for it.
+            return Err(ClientError::ResponseAuthFailed(ohttp::Error::Truncated));
+        }
+        if self.fail_malformed {
+            return Err(open_error(ohttp::Error::Format));
+        }
+        Ok(encapsulated_response.to_vec())
+    }
+}
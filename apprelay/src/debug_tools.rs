@@ -0,0 +1,177 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Helpers for replaying requests by hand while debugging a relay integration.
+//!
+//! Only built with the `debug-tools` feature, the output contains the complete
+//! encapsulated request.
+
+use std::convert::identity;
+use std::slice;
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{
+    cstr_to_str, handles, null_safe_ptr, safe_unwrap, write_out_buffer, ClientError, RequestContext,
+};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with padding, as accepted by `base64 -d`.
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Lowercase hex encoding of `bytes`, without separators.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decodes a hex dump as pasted from logs or bug reports.
+///
+/// Digits may be upper or lower case and ASCII whitespace between them is ignored, so
+/// dumps wrapped over several lines or grouped by byte decode as well. Fails with
+/// [`ClientError::InvalidArgument`] naming the offending character position for anything
+/// else, and for an odd number of digits.
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, ClientError> {
+    let mut bytes = Vec::with_capacity(hex.len() / 2);
+    let mut high = None;
+    for (position, c) in hex.char_indices() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        let digit = c.to_digit(16).ok_or_else(|| {
+            ClientError::InvalidArgument(format!(
+                "invalid hex character {c:?} at position {position}"
+            ))
+        })? as u8;
+        match high.take() {
+            Some(high) => bytes.push((high << 4) | digit),
+            None => high = Some(digit),
+        }
+    }
+    if high.is_some() {
+        return Err(ClientError::InvalidArgument(format!(
+            "odd number of hex digits, the last byte is missing a digit ({} bytes decoded)",
+            bytes.len()
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Quotes `arg` for a POSIX shell.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Shell command POSTing `encapsulated_request` to `gateway_url` with curl.
+///
+/// The request is embedded base64 encoded and decoded into curl's standard input, so the
+/// command can be pasted as is. The decapsulated response can only be read by the context
+/// the request was encapsulated with, so save the output with `-o` to decapsulate it.
+pub fn curl_command(encapsulated_request: &[u8], gateway_url: &str) -> String {
+    format!(
+        "echo '{}' | base64 -d | curl -X POST --data-binary @- -H 'Content-Type: message/ohttp-req' {}",
+        base64(encapsulated_request),
+        shell_quote(gateway_url)
+    )
+}
+
+/// Writes a NUL terminated shell command replaying the request of `context` to the NUL
+/// terminated `gateway_url` with curl into `out`, see [`curl_command`].
+///
+/// FOR DEBUGGING ONLY, the command contains the complete encapsulated request.
+///
+/// Returns the length of the command without the NUL terminator, or -1 if `gateway_url` is
+/// not valid UTF-8, the context is not live or `out` is too small.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn request_context_as_curl_ffi(
+    context: *const RequestContext,
+    gateway_url: *const libc::c_char,
+    out: *mut libc::c_char,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let gateway_url = safe_unwrap!(cstr_to_str(gateway_url, "gateway_url"), -1, identity);
+    let command = safe_unwrap!(
+        handles::with(context, |context| curl_command(
+            context.encapsulated_request(),
+            gateway_url
+        )),
+        -1,
+        identity
+    );
+    let mut bytes = command.into_bytes();
+    bytes.push(0);
+    match write_out_buffer(&bytes, out as *mut u8, out_cap) {
+        -1 => -1,
+        written => written - 1,
+    }
+}
+
+/// Decodes the NUL terminated hex dump `hex` into `out`, see [`from_hex`] for the accepted
+/// format.
+///
+/// Returns the number of bytes written, or -1 if `hex` has an odd number of digits or a
+/// character other than a hex digit or whitespace, whose position the last error names,
+/// or if `out` is too small.
+///
+/// # Safety
+/// `hex` must be NUL terminated, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+/// The invariants of `out` are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[no_mangle]
+pub unsafe extern "C" fn hex_to_bytes_ffi(
+    hex: *const libc::c_char,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let hex = safe_unwrap!(cstr_to_str(hex, "hex"), -1, identity);
+    let bytes = safe_unwrap!(from_hex(hex), -1, identity);
+    write_out_buffer(&bytes, out, out_cap)
+}
+
+/// Writes the NUL terminated lowercase hex encoding of `ptr` into `out`, which needs
+/// `2 * len + 1` bytes.
+///
+/// Returns the length of the encoding without the NUL terminator, or -1 if `out` is too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn bytes_to_hex_ffi(
+    ptr: *const u8,
+    len: libc::size_t,
+    out: *mut libc::c_char,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let ptr = null_safe_ptr!(ptr, -1, ptr);
+    let mut hex = to_hex(slice::from_raw_parts(ptr, len)).into_bytes();
+    hex.push(0);
+    match write_out_buffer(&hex, out as *mut u8, out_cap) {
+        -1 => -1,
+        written => written - 1,
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! One-time warnings for deprecated FFI functions, so integrators notice what to migrate to
+//! before the function is removed.
+//!
+//! A deprecated function starts with `warn_deprecated!("name", "replacement")`, which
+//! warns on its first call in the process only. The warning goes to the `log` logger set up
+//! by `initialize_logging` or the embedder, and to stderr if no logger takes warnings,
+//! unless built with `no-deprecation-stderr`.
+
+use log::{log_enabled, warn, Level};
+
+/// Warns once per process that the calling FFI function is deprecated in favor of
+/// `$replacement`.
+macro_rules! warn_deprecated {
+    ($function:literal, $replacement:literal) => {{
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        WARNED.call_once(|| $crate::deprecation::emit($function, $replacement));
+    }};
+}
+
+pub(crate) use warn_deprecated;
+
+#[doc(hidden)]
+pub fn emit(function: &str, replacement: &str) {
+    let message =
+        format!("{function} is deprecated and will be removed, use {replacement} instead");
+    if log_enabled!(Level::Warn) {
+        warn!("{message}");
+    } else if cfg!(not(feature = "no-deprecation-stderr")) {
+        eprintln!("apprelay: {message}");
+    }
+}
+
+/// Does nothing but warn like a deprecated function, to check where the warnings of a build
+/// end up.
+///
+/// **Test helper, only available in `testutil` builds and not meant for production.**
+#[cfg(feature = "testutil")]
+#[no_mangle]
+pub extern "C" fn apprelay_deprecation_probe_ffi() {
+    warn_deprecated!("apprelay_deprecation_probe_ffi", "nothing");
+}
@@ -0,0 +1,40 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Well-known key configuration for development against a local test gateway.
+//!
+//! **Never use this in production.** The private half of this key is part of the
+//! `server` test helper, so anyone can decapsulate requests sealed to it.
+//! The key does not expire and is never rotated by a gateway, but it may change
+//! between releases of this library, so do not persist it.
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::write_out_buffer;
+
+/// Key identifier of [`DEV_KEY_CONFIG`].
+pub const DEV_KEY_ID: u8 = 1;
+
+/// Encoded key configuration of the development gateway: key id 1,
+/// DHKEM(X25519, HKDF-SHA256) with HKDF-SHA256/AES-128-GCM and HKDF-SHA256/ChaCha20Poly1305.
+pub const DEV_KEY_CONFIG: [u8; 45] = [
+    0x01, 0x00, 0x20, 0xdf, 0x22, 0x42, 0xa9, 0x47, 0x3f, 0xfe, 0xf2, 0x89, 0x82, 0x25, 0x29, 0x26,
+    0x45, 0xc6, 0x23, 0x33, 0x96, 0x17, 0x2e, 0xd6, 0x78, 0x30, 0xf2, 0xdf, 0xfa, 0xf9, 0xe7, 0x82,
+    0x05, 0x8b, 0x19, 0x00, 0x08, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x03,
+];
+
+/// Writes the development key configuration into `out`.
+///
+/// FOR DEVELOPMENT ONLY, the matching private key is public.
+///
+/// Returns the number of bytes written, or -1 if `out` is NULL or smaller than the configuration.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[no_mangle]
+pub unsafe extern "C" fn apprelay_dev_config_ffi(
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    write_out_buffer(&DEV_KEY_CONFIG, out, out_cap)
+}
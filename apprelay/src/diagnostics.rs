@@ -0,0 +1,84 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Approximate accounting of the heap bytes held by live contexts and the list of live
+//! request context handles, for spotting callers that never free them.
+
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{handles, null_safe_ptr, ClientError};
+
+static LIVE_CONTEXT_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes of a context counted as live from its creation until it is dropped.
+///
+/// The size is taken once at creation, so buffers growing or shrinking afterwards, such as
+/// a pooled context emptied by decapsulation, are not reflected.
+pub(crate) struct LiveBytes(usize);
+
+impl LiveBytes {
+    pub(crate) fn track(bytes: usize) -> Self {
+        LIVE_CONTEXT_BYTES.fetch_add(bytes, Ordering::Relaxed);
+        LiveBytes(bytes)
+    }
+}
+
+impl Drop for LiveBytes {
+    fn drop(&mut self) {
+        LIVE_CONTEXT_BYTES.fetch_sub(self.0, Ordering::Relaxed);
+    }
+}
+
+/// Returns the heap bytes held by the `RequestContext` and `ResponseContext` objects that
+/// have not been consumed or freed yet, the struct itself plus its buffers.
+///
+/// A value that keeps growing while the number of requests in flight is stable points to
+/// contexts that are never freed. Only available in `diagnostics` builds.
+#[no_mangle]
+pub extern "C" fn apprelay_live_context_bytes_ffi() -> libc::size_t {
+    LIVE_CONTEXT_BYTES.load(Ordering::Relaxed)
+}
+
+/// Returns the number of `RequestContext` handles that have not been consumed or freed yet,
+/// pooled contexts included. Only available in `diagnostics` builds.
+#[no_mangle]
+pub extern "C" fn apprelay_debug_live_handle_count_ffi() -> libc::size_t {
+    handles::live_handles().len()
+}
+
+/// Writes the live `RequestContext` handles, as addresses in ascending order, into `out`
+/// to spot the ones a host application never frees. Only available in `diagnostics` builds.
+///
+/// The list is a snapshot taken under the lock contexts are registered and freed under, so
+/// it is consistent even while other threads create or free contexts, but may be outdated
+/// as soon as it is returned.
+///
+/// Returns the number of handles written, or -1 if `out` can not hold all of them, in which
+/// case `apprelay_debug_live_handle_count_ffi` tells the size to retry with.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[no_mangle]
+pub unsafe extern "C" fn apprelay_debug_live_handles_ffi(
+    out: *mut u64,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let out = null_safe_ptr!(out, -1, out);
+    let live = handles::live_handles();
+    if live.len() > out_cap {
+        update_last_error(ClientError::BufferTooSmall {
+            needed: live.len(),
+            available: out_cap,
+        });
+        return -1;
+    }
+    let out = slice::from_raw_parts_mut(out, live.len());
+    for (slot, handle) in out.iter_mut().zip(&live) {
+        *slot = *handle as u64;
+    }
+    live.len() as libc::ssize_t
+}
@@ -1,14 +1,55 @@
-use std::{cell::RefCell, error::Error, slice};
+#[cfg(feature = "no-error-global")]
+use std::cell::Cell;
+use std::error::Error;
+#[cfg(not(feature = "no-error-global"))]
+use std::{cell::RefCell, slice};
 
-use libc::{c_char, c_int};
-use log::{debug, error};
+use log::debug;
+#[cfg(not(feature = "no-error-global"))]
+use log::error;
+#[cfg(not(feature = "no-error-global"))]
+use std::ffi::c_char;
+use std::ffi::c_int;
 
 use env_logger::{Builder, Target};
 
+use crate::ClientError;
+
+#[cfg(not(feature = "no-error-global"))]
 thread_local! {
     static LAST_ERROR: RefCell<Option<Box<dyn Error>>> = RefCell::new(None);
 }
 
+// Still per thread, as errors of concurrent calls must not overwrite each other, but const
+// initialized and without a destructor, so no lazy setup or exit hook per thread.
+#[cfg(feature = "no-error-global")]
+thread_local! {
+    static LAST_ERROR_CODE: Cell<c_int> = const { Cell::new(0) };
+}
+
+/// The code `last_error_code` reports for `err`, -1 for errors other than [`ClientError`].
+pub(crate) fn error_code(err: &(dyn Error + 'static)) -> c_int {
+    match err.downcast_ref::<ClientError>() {
+        Some(err) => err.kind() as c_int,
+        None => -1,
+    }
+}
+
+/// The error `take_last_error` returns in `no-error-global` builds, which only keep its code.
+#[cfg(feature = "no-error-global")]
+#[derive(Debug)]
+pub struct ErrorCode(pub c_int);
+
+#[cfg(feature = "no-error-global")]
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error code {}", self.0)
+    }
+}
+
+#[cfg(feature = "no-error-global")]
+impl Error for ErrorCode {}
+
 #[no_mangle]
 pub extern "C" fn initialize_logging() {
     let mut builder = Builder::from_default_env();
@@ -19,6 +60,7 @@ pub extern "C" fn initialize_logging() {
 }
 
 /// Update the last error, clearing the old one.
+#[cfg(not(feature = "no-error-global"))]
 pub fn update_last_error<E: Error + 'static>(err: E) {
     error!("Setting last error {err}");
     {
@@ -33,13 +75,50 @@ pub fn update_last_error<E: Error + 'static>(err: E) {
     });
 }
 
+/// Update the last error code, dropping the error itself.
+#[cfg(feature = "no-error-global")]
+pub fn update_last_error<E: Error + 'static>(err: E) {
+    LAST_ERROR_CODE.with(|prev| prev.set(error_code(&err)));
+}
+
 /// Retrieve the most recent error, clearing it in the process.
+#[cfg(not(feature = "no-error-global"))]
 pub fn take_last_error() -> Option<Box<dyn Error>> {
     LAST_ERROR.with(|prev| prev.borrow_mut().take())
 }
 
+/// Retrieve the code of the most recent error as an [`ErrorCode`], clearing it in the process.
+#[cfg(feature = "no-error-global")]
+pub fn take_last_error() -> Option<Box<dyn Error>> {
+    match LAST_ERROR_CODE.with(|prev| prev.replace(0)) {
+        0 => None,
+        code => Some(Box::new(ErrorCode(code))),
+    }
+}
+
+/// Return the [`ClientErrorKind`](crate::ClientErrorKind) code of the most recent error,
+/// 0 if there is none or -1 for an error of another type passed to `update_last_error` by
+/// the embedding Rust code.
+///
+/// Unlike `last_error_message` this does not clear the error.
+#[no_mangle]
+pub extern "C" fn last_error_code() -> c_int {
+    #[cfg(not(feature = "no-error-global"))]
+    {
+        LAST_ERROR.with(|prev| match *prev.borrow() {
+            Some(ref err) => error_code(&**err),
+            None => 0,
+        })
+    }
+    #[cfg(feature = "no-error-global")]
+    {
+        LAST_ERROR_CODE.with(|prev| prev.get())
+    }
+}
+
 /// Return the number of bytes in the last error message.
 /// Does not include any trailing null terminators.
+#[cfg(not(feature = "no-error-global"))]
 #[no_mangle]
 pub extern "C" fn last_error_length() -> libc::c_int {
     LAST_ERROR.with(|prev| match *prev.borrow() {
@@ -58,6 +137,7 @@ pub extern "C" fn last_error_length() -> libc::c_int {
 ///
 /// # Safety
 /// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[cfg(not(feature = "no-error-global"))]
 #[no_mangle]
 pub unsafe extern "C" fn last_error_message(buffer: *mut c_char, length: c_int) -> c_int {
     if buffer.is_null() {
@@ -94,3 +174,45 @@ pub unsafe extern "C" fn last_error_message(buffer: *mut c_char, length: c_int)
     buffer[error_message.len()] = 0;
     error_message.len() as c_int
 }
+
+/// Write the `Debug` form of the most recent error, NUL terminated, into `out`.
+///
+/// Unlike the message written by `last_error_message` it includes the structure of the
+/// source error, e.g. the `ohttp::Error` variant behind `EncapsulationFailed`, for debugging
+/// failures the message does not explain. The error is not cleared.
+///
+/// Returns the number of bytes written without the NUL terminator, 0 if there is no error,
+/// or -1 if `out` is a null pointer or too small, leaving the error untouched.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[cfg(not(feature = "no-error-global"))]
+#[no_mangle]
+pub unsafe extern "C" fn last_error_debug_ffi(
+    out: *mut c_char,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    if out.is_null() {
+        error!("Null pointer passed into last_error_debug_ffi() as the buffer");
+        return -1;
+    }
+
+    let debug = LAST_ERROR.with(|prev| prev.borrow().as_ref().map(|err| format!("{err:?}")));
+    let debug = match debug {
+        Some(debug) => debug,
+        None => return 0,
+    };
+    if debug.len() >= out_cap {
+        error!("Buffer provided for the last error debug form is too small");
+        error!(
+            "Expected at least {} bytes but got {out_cap}",
+            debug.len() + 1
+        );
+        return -1;
+    }
+
+    let out = slice::from_raw_parts_mut(out as *mut u8, out_cap);
+    out[..debug.len()].copy_from_slice(debug.as_bytes());
+    out[debug.len()] = 0;
+    debug.len() as libc::ssize_t
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Tunneling gRPC calls through OHTTP.
+//!
+//! A unary gRPC call is a BHTTP `POST` whose content is the request message prefixed as
+//! described by the [gRPC over HTTP/2](https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md)
+//! protocol.
+
+use std::convert::identity;
+use std::ptr;
+
+use crate::bhttp::BhttpRequest;
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{cstr_to_str, encapsulate_request_with, safe_unwrap, ClientError, RequestContext};
+
+/// Length of the prefix of every gRPC message, the compressed flag and the message length.
+pub const GRPC_MESSAGE_PREFIX_LEN: usize = 5;
+
+/// Prefixes `message` with the gRPC compressed flag and its length as a big-endian `u32`.
+pub fn frame_grpc_message(message: &[u8], compressed: bool) -> Result<Vec<u8>, ClientError> {
+    let len = u32::try_from(message.len()).map_err(|_| {
+        ClientError::InvalidArgument(format!(
+            "gRPC message of {} bytes exceeds the 4 GiB limit",
+            message.len()
+        ))
+    })?;
+    let mut framed = Vec::with_capacity(GRPC_MESSAGE_PREFIX_LEN + message.len());
+    framed.push(compressed as u8);
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(message);
+    Ok(framed)
+}
+
+/// Encodes a unary gRPC call of the method at `path`, e.g. `/package.Service/Method`,
+/// as a BHTTP request carrying the uncompressed `message`.
+pub fn encode_grpc_request(path: &str, message: &[u8]) -> Result<Vec<u8>, ClientError> {
+    if !path.starts_with('/') {
+        return Err(ClientError::InvalidArgument(format!(
+            "gRPC path {:?} does not start with '/'",
+            path
+        )));
+    }
+    let body = frame_grpc_message(message, false)?;
+    Ok(BhttpRequest::new("POST", "https", "", path)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .encode(&body))
+}
+
+/// Encapsulates a unary gRPC call of the method at the NUL terminated `path` carrying the
+/// serialized `message`, like [`crate::encapsulate_request_ffi`].
+///
+/// The BHTTP request is a `POST` with `content-type: application/grpc` and `te: trailers`,
+/// and the message gets the 5 byte gRPC prefix. The authority is left empty for the gateway
+/// to resolve. The gRPC status arrives in the trailers of the decapsulated BHTTP response.
+///
+/// This function will return a NULL pointer if `path` is not valid UTF-8 or does not start
+/// with `/`, or if encapsulation fails.
+///
+/// # Safety
+/// `path` must point to a NUL terminated string, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_grpc_request_ffi(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    path: *const libc::c_char,
+    message_ptr: *const u8,
+    message_len: libc::size_t,
+) -> *mut RequestContext {
+    let path = safe_unwrap!(cstr_to_str(path, "path"), ptr::null_mut(), identity);
+    encapsulate_request_with(
+        encoded_config_ptr,
+        encoded_config_len,
+        message_ptr,
+        message_len,
+        |config, message| {
+            let request = encode_grpc_request(path, message)?;
+            RequestContext::new(config, &request)
+        },
+    )
+}
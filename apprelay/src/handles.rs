@@ -0,0 +1,161 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Registry of the `RequestContext` handles handed out over FFI.
+//!
+//! Every context has a guard of its own, held shared by readers and exclusively by writers
+//! and by whoever frees it, so the registry lock is only held to look handles up and a long
+//! access like encapsulation does not block other contexts. Consuming or freeing a context
+//! removes it from the registry before waiting for its guard, so an accessor racing with
+//! `decapsulate_response_ffi` or `request_context_message_drop_ffi` fails with
+//! `InvalidHandle` instead of reading freed memory. Once freed, the address of a context may
+//! be handed out again, so a handle must still not be used after the call consuming it
+//! returned.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+use crate::{ClientError, RequestContext, ResponseDecapsulator};
+
+/// What decapsulating the response of a context does with its handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Ownership {
+    /// The context is freed.
+    Consumed,
+    /// The context was created by `request_context_new_ffi` and is only emptied, for reuse.
+    Pooled,
+}
+
+struct Entry {
+    ownership: Ownership,
+    /// Guards no data of its own, the context is behind the handle.
+    guard: Arc<RwLock<()>>,
+}
+
+static LIVE_REQUEST_CONTEXTS: Mutex<BTreeMap<usize, Entry>> = Mutex::new(BTreeMap::new());
+
+fn live() -> MutexGuard<'static, BTreeMap<usize, Entry>> {
+    // Every update is a single insert or remove, so the map is consistent even after a
+    // panic while it was locked.
+    LIVE_REQUEST_CONTEXTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn register_as(context: RequestContext, ownership: Ownership) -> *mut RequestContext {
+    let handle = Box::into_raw(Box::new(context));
+    let guard = Arc::new(RwLock::new(()));
+    live().insert(handle as usize, Entry { ownership, guard });
+    handle
+}
+
+/// The guard of the context behind `handle`, if it is live.
+fn guard_of(handle: usize) -> Result<Arc<RwLock<()>>, ClientError> {
+    live()
+        .get(&handle)
+        .map(|entry| entry.guard.clone())
+        .ok_or(ClientError::InvalidHandle)
+}
+
+/// Whether `handle` still refers to the context `guard` was looked up for, checked once the
+/// guard is held: the context may have been freed, and its address reused, meanwhile.
+fn still_live(handle: usize, guard: &Arc<RwLock<()>>) -> Result<(), ClientError> {
+    match live().get(&handle) {
+        Some(entry) if Arc::ptr_eq(&entry.guard, guard) => Ok(()),
+        _ => Err(ClientError::InvalidHandle),
+    }
+}
+
+/// Handles of the live contexts in ascending order, a snapshot taken under the registry lock.
+#[cfg(feature = "diagnostics")]
+pub(crate) fn live_handles() -> Vec<usize> {
+    live().keys().copied().collect()
+}
+
+/// Moves `context` to the heap and registers the handle returned to the caller.
+pub(crate) fn register(context: RequestContext) -> *mut RequestContext {
+    register_as(context, Ownership::Consumed)
+}
+
+/// Registers an empty context that survives decapsulation, see `request_context_new_ffi`.
+pub(crate) fn register_pooled() -> *mut RequestContext {
+    register_as(RequestContext::empty(), Ownership::Pooled)
+}
+
+/// Runs `access` on the context behind `handle`, holding its guard shared so the context
+/// can not be freed or written meanwhile.
+///
+/// # Safety
+/// `handle` must have been returned by [`register`] or [`register_pooled`].
+pub(crate) unsafe fn with<R>(
+    handle: *const RequestContext,
+    access: impl FnOnce(&RequestContext) -> R,
+) -> Result<R, ClientError> {
+    let guard = guard_of(handle as usize)?;
+    // The guard protects no data, a panic while it was held leaves nothing inconsistent.
+    let _shared = guard.read().unwrap_or_else(PoisonError::into_inner);
+    still_live(handle as usize, &guard)?;
+    Ok(access(&*handle))
+}
+
+/// Like [`with`], with exclusive access to the context.
+///
+/// Only the context's own guard is held during `access`, other contexts stay usable.
+///
+/// # Safety
+/// `handle` must have been returned by [`register`] or [`register_pooled`].
+pub(crate) unsafe fn with_mut<R>(
+    handle: *mut RequestContext,
+    access: impl FnOnce(&mut RequestContext) -> R,
+) -> Result<R, ClientError> {
+    let guard = guard_of(handle as usize)?;
+    let _exclusive = guard.write().unwrap_or_else(PoisonError::into_inner);
+    still_live(handle as usize, &guard)?;
+    Ok(access(&mut *handle))
+}
+
+/// Unregisters `handle` and takes back ownership of its context, only once per handle.
+///
+/// Waits for accesses that looked the handle up before it was unregistered.
+///
+/// # Safety
+/// `handle` must have been returned by [`register`] or [`register_pooled`].
+pub(crate) unsafe fn take(handle: *mut RequestContext) -> Result<Box<RequestContext>, ClientError> {
+    let entry = live()
+        .remove(&(handle as usize))
+        .ok_or(ClientError::InvalidHandle)?;
+    let _exclusive = entry.guard.write().unwrap_or_else(PoisonError::into_inner);
+    Ok(Box::from_raw(handle))
+}
+
+/// Takes the decapsulation state of the context behind `handle` to decapsulate its response,
+/// freeing the context unless it is pooled.
+///
+/// # Safety
+/// `handle` must have been returned by [`register`] or [`register_pooled`].
+pub(crate) unsafe fn take_decapsulator(
+    handle: *mut RequestContext,
+) -> Result<ResponseDecapsulator, ClientError> {
+    let (ownership, guard) = {
+        let mut live = live();
+        let entry = live
+            .get(&(handle as usize))
+            .ok_or(ClientError::InvalidHandle)?;
+        let found = (entry.ownership, entry.guard.clone());
+        if found.0 == Ownership::Consumed {
+            live.remove(&(handle as usize));
+        }
+        found
+    };
+    let _exclusive = guard.write().unwrap_or_else(PoisonError::into_inner);
+    match ownership {
+        Ownership::Pooled => {
+            still_live(handle as usize, &guard)?;
+            (*handle).take_decapsulator()
+        }
+        Ownership::Consumed => {
+            let (_, decapsulator) = Box::from_raw(handle).into_parts()?;
+            Ok(decapsulator)
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Optional random delay before the FFI hands a decapsulated response to the application.
+//!
+//! The delay blurs the relation between when a response arrives and when the application
+//! acts on it, for observers of the application's behaviour. It does not change network
+//! timing: the relay still sees the request and response when they are sent.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::ClientError;
+
+/// Bounds in milliseconds, the minimum in the upper and the maximum in the lower 32 bits so
+/// both are updated at once. 0 disables the delay.
+static JITTER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the bounds in milliseconds of the delay drawn uniformly for every response.
+///
+/// `min_ms` and `max_ms` of 0, the default, disable the delay.
+pub fn set_response_jitter(min_ms: u32, max_ms: u32) -> Result<(), ClientError> {
+    if min_ms > max_ms {
+        return Err(ClientError::InvalidArgument(format!(
+            "jitter minimum {min_ms} ms exceeds the maximum {max_ms} ms"
+        )));
+    }
+    JITTER_MS.store(
+        (u64::from(min_ms) << 32) | u64::from(max_ms),
+        Ordering::Relaxed,
+    );
+    Ok(())
+}
+
+/// Draws the delay of the next response from the configured bounds.
+///
+/// Only the FFI sleeps, Rust callers decapsulating with an async runtime await its timer for
+/// this long instead of blocking a thread.
+pub fn next_response_jitter() -> Duration {
+    let bounds = JITTER_MS.load(Ordering::Relaxed);
+    let (min_ms, max_ms) = (bounds >> 32, bounds & u64::from(u32::MAX));
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    let mut random = [0; 8];
+    // Without randomness the delay falls back to the minimum rather than failing the response.
+    let offset = match getrandom::getrandom(&mut random) {
+        Ok(()) => u64::from_le_bytes(random) % (max_ms - min_ms + 1),
+        Err(_) => 0,
+    };
+    Duration::from_millis(min_ms + offset)
+}
+
+/// Blocks for [`next_response_jitter`] before a decapsulated response is returned over FFI.
+///
+/// wasm32-unknown-unknown can not block, the delay is skipped there.
+pub(crate) fn delay_response() {
+    let delay = next_response_jitter();
+    if !delay.is_zero() {
+        sleep(delay);
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn sleep(_delay: Duration) {}
+
+/// Delays every decapsulated response returned over FFI by a random time between `min_ms`
+/// and `max_ms` milliseconds, blocking the calling thread. Both 0, the default, disables
+/// the delay.
+///
+/// The delay only hides the arrival time of responses from what the application does with
+/// them, the network timing seen by the relay is unchanged. Builds for
+/// wasm32-unknown-unknown can not block and skip it.
+///
+/// Returns 0 on success, or -1 if `min_ms` exceeds `max_ms`.
+#[no_mangle]
+pub extern "C" fn set_response_jitter_ffi(min_ms: u32, max_ms: u32) -> libc::c_int {
+    match set_response_jitter(min_ms, max_ms) {
+        Ok(()) => 0,
+        Err(err) => {
+            update_last_error(err);
+            -1
+        }
+    }
+}
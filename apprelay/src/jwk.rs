@@ -0,0 +1,145 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Key configurations from gateway public keys distributed as a JSON Web Key (RFC 7517).
+//!
+//! Only built with the `jwk` feature, which pulls in `serde_json`.
+
+use serde_json::Value;
+
+use crate::config::{KeyConfig, SymmetricSuite, KEM_X25519_SHA256};
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{cstr_to_str, safe_unwrap, write_out_buffer, ClientError};
+
+/// DHKEM(P-256, HKDF-SHA256)
+const KEM_P256_SHA256: u16 = 0x0010;
+
+fn invalid(reason: String) -> ClientError {
+    ClientError::InvalidArgument(format!("JWK {reason}"))
+}
+
+/// Decodes unpadded base64url (RFC 4648 section 5), as used by JWK members.
+fn base64url_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((acc >> bits) as u8);
+        }
+    }
+    // A single leftover character can not be produced by any encoding.
+    if bits >= 6 {
+        return None;
+    }
+    Some(decoded)
+}
+
+/// The base64url member `name` of `jwk`, which must decode to `len` bytes.
+fn coordinate(jwk: &Value, name: &str, len: usize) -> Result<Vec<u8>, ClientError> {
+    let encoded = jwk[name]
+        .as_str()
+        .ok_or_else(|| invalid(format!("has no `{name}` member")))?;
+    let decoded =
+        base64url_decode(encoded).ok_or_else(|| invalid(format!("`{name}` is not base64url")))?;
+    if decoded.len() != len {
+        return Err(invalid(format!(
+            "`{name}` must be {len} bytes but is {}",
+            decoded.len()
+        )));
+    }
+    Ok(decoded)
+}
+
+/// Assembles a key configuration offering the single `kdf`/`aead` suite from the public key
+/// in `jwk`.
+///
+/// The KEM follows from the key type and curve, which must be one of:
+///
+/// ```text
+/// kty  crv     KEM                           public key
+/// OKP  X25519  DHKEM(X25519, HKDF-SHA256)    x
+/// EC   P-256   DHKEM(P-256, HKDF-SHA256)     0x04 || x || y
+/// ```
+///
+/// A JWK carrying the private key `d` is rejected, it must never leave the gateway.
+pub fn key_config_from_jwk(
+    jwk: &str,
+    key_id: u8,
+    kdf: u16,
+    aead: u16,
+) -> Result<KeyConfig, ClientError> {
+    let jwk: Value =
+        serde_json::from_str(jwk).map_err(|err| invalid(format!("is not valid JSON: {err}")))?;
+    if !jwk.is_object() {
+        return Err(invalid("is not a JSON object".to_owned()));
+    }
+    if !jwk["d"].is_null() {
+        return Err(invalid(
+            "holds a private key, only distribute the public key".to_owned(),
+        ));
+    }
+
+    let kty = jwk["kty"].as_str().unwrap_or_default();
+    let crv = jwk["crv"].as_str().unwrap_or_default();
+    let (kem, public_key) = match (kty, crv) {
+        ("OKP", "X25519") => (KEM_X25519_SHA256, coordinate(&jwk, "x", 32)?),
+        ("EC", "P-256") => {
+            let mut public_key = vec![0x04];
+            public_key.extend_from_slice(&coordinate(&jwk, "x", 32)?);
+            public_key.extend_from_slice(&coordinate(&jwk, "y", 32)?);
+            (KEM_P256_SHA256, public_key)
+        }
+        _ => {
+            return Err(invalid(format!(
+                "key type `{kty}` with curve `{crv}` matches no supported KEM"
+            )))
+        }
+    };
+    KeyConfig::new(key_id, kem, public_key, vec![SymmetricSuite { kdf, aead }])
+}
+
+/// Assembles an encoded key configuration offering a single `kdf`/`aead` suite from the
+/// NUL terminated JSON Web Key `jwk_json` of a gateway, and writes it into `out`.
+///
+/// See [`key_config_from_jwk`] for the accepted keys. Returns the number of bytes written,
+/// or -1 if the JWK is malformed, its curve matches no supported KEM, it holds a private key
+/// or `out` is too small.
+///
+/// # Safety
+/// `jwk_json` must be NUL terminated, see [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety).
+/// The invariants of `out` are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[no_mangle]
+pub unsafe extern "C" fn key_config_from_jwk_ffi(
+    jwk_json: *const libc::c_char,
+    key_id: u8,
+    kdf: u16,
+    aead: u16,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let jwk = safe_unwrap!(
+        cstr_to_str(jwk_json, "jwk_json"),
+        -1,
+        std::convert::identity
+    );
+    let config = safe_unwrap!(
+        key_config_from_jwk(jwk, key_id, kdf, aead),
+        -1,
+        std::convert::identity
+    );
+    write_out_buffer(&config.encode(), out, out_cap)
+}
@@ -0,0 +1,62 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Layout of the structs passed by value across the FFI boundary.
+//!
+//! Bindings declaring these structs themselves should compare their layout against
+//! `apprelay_struct_sizes_ffi` when loading the library.
+
+use std::mem::{align_of, offset_of, size_of};
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{null_safe_ptr, BytesView, ClientError, EncapsulateResult};
+
+// `BytesView` is a pointer followed by a `size_t`, with no padding on any supported target.
+const _: () = assert!(size_of::<BytesView>() == 2 * size_of::<usize>());
+const _: () = assert!(align_of::<BytesView>() == align_of::<usize>());
+const _: () = assert!(offset_of!(BytesView, ptr) == 0);
+const _: () = assert!(offset_of!(BytesView, len) == size_of::<usize>());
+
+/// Sizes, alignments and field offsets in bytes of the FFI structs of this build.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StructSizes {
+    /// Size of `StructSizes` itself.
+    pub struct_sizes_size: libc::size_t,
+    pub bytes_view_size: libc::size_t,
+    pub bytes_view_align: libc::size_t,
+    pub bytes_view_ptr_offset: libc::size_t,
+    pub bytes_view_len_offset: libc::size_t,
+    pub encapsulate_result_size: libc::size_t,
+    pub encapsulate_result_align: libc::size_t,
+}
+
+impl StructSizes {
+    pub fn current() -> Self {
+        StructSizes {
+            struct_sizes_size: size_of::<StructSizes>(),
+            bytes_view_size: size_of::<BytesView>(),
+            bytes_view_align: align_of::<BytesView>(),
+            bytes_view_ptr_offset: offset_of!(BytesView, ptr),
+            bytes_view_len_offset: offset_of!(BytesView, len),
+            encapsulate_result_size: size_of::<EncapsulateResult>(),
+            encapsulate_result_align: align_of::<EncapsulateResult>(),
+        }
+    }
+}
+
+/// Writes the layout of the FFI structs of this build into `out`.
+///
+/// # Safety
+/// Dereferences a pointer to `StructSizes` passed by the caller.
+/// Be sure that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn apprelay_struct_sizes_ffi(out: *mut StructSizes) {
+    null_safe_ptr!(out, (), {
+        *out = StructSizes::current();
+    })
+}
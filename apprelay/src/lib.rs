@@ -3,13 +3,19 @@
 
 #![allow(clippy::unused_unit)]
 
+use config::{HpkeSuite, KeyConfig};
+use crypto::{CryptoProvider, OhttpProvider, ResponseOpener};
 use error_ffi::update_last_error;
-use ohttp::{ClientRequest, ClientResponse};
+use sha2::{Digest, Sha256};
 use std::any::Any;
+use std::convert::identity;
+use std::ffi::{c_void, CStr};
 use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
 use std::{ptr, slice};
 
-use std::panic::catch_unwind;
+use std::panic::{catch_unwind, AssertUnwindSafe, UnwindSafe};
 
 use thiserror::Error;
 
@@ -21,26 +27,623 @@ pub enum ClientError {
     EncapsulationFailed(#[source] ohttp::Error),
     #[error("Failed to decapsulate request")]
     DecapsulationFailed(#[source] ohttp::Error),
+    #[error("OHTTP operation failed")]
+    Ohttp(#[source] ohttp::Error),
 
     #[error("Invalid argument `{0}` passed")]
     InvalidArgument(String),
 
+    #[error("Malformed BHTTP response: {0}")]
+    ResponseMalformed(String),
+
+    #[error("Malformed key configuration: {0}")]
+    MalformedConfig(String),
+
+    #[error(
+        "Encapsulated response is truncated, expected at least {expected} bytes but got {actual}"
+    )]
+    ResponseTruncated { expected: usize, actual: usize },
+
+    #[error("Output buffer too small, expected at least {needed} bytes but got {available}")]
+    BufferTooSmall { needed: usize, available: usize },
+
     #[error("Panic unwinded at {0:?}")]
     SafePanic(Box<dyn Any + Send>),
 
     #[cfg(feature = "java")]
     #[error("Unexpected JNI issue")]
     JniProblem(#[source] jni::errors::Error),
+
+    #[error("Encapsulated response is empty, the relay returned no body")]
+    ResponseEmpty,
+
+    #[error("Encapsulated response failed authentication, it was not produced for this request")]
+    ResponseAuthFailed(#[source] ohttp::Error),
+
+    #[error("No HPKE suite of the key configuration is supported by this build")]
+    UnsupportedSuite,
+
+    #[error("Response length prefix declares {declared} bytes but {actual} follow")]
+    ResponseFramingMismatch { declared: usize, actual: usize },
+
+    #[error("Handle does not refer to a live context, it was already consumed or freed")]
+    InvalidHandle,
+
+    #[error("Context holds no encapsulated request")]
+    ContextEmpty,
+
+    #[error("System random number generator is unavailable, check the sandbox or seccomp policy")]
+    RngUnavailable(#[source] getrandom::Error),
+
+    #[error(
+        "Encapsulated request header {actual:?} does not match the selected suite {expected:?}"
+    )]
+    SuiteMismatch {
+        expected: HpkeSuite,
+        actual: [u8; config::REQUEST_HEADER_LEN],
+    },
+
+    #[error("Response callback aborted decapsulation")]
+    CallbackAborted,
+
+    #[error(
+        "KEM {0:#06x} of the key configuration is not implemented by the HPKE backend of this build"
+    )]
+    KemNotCompiled(u16),
+}
+
+/// Kind of a [`ClientError`], for matching without inspecting the payload.
+///
+/// The discriminants are the error codes returned by `last_error_code`, 0 means no error.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ClientErrorKind {
+    RequestContextInitialization = 1,
+    EncapsulationFailed = 2,
+    DecapsulationFailed = 3,
+    Ohttp = 4,
+    InvalidArgument = 5,
+    ResponseMalformed = 6,
+    MalformedConfig = 7,
+    ResponseTruncated = 8,
+    BufferTooSmall = 9,
+    SafePanic = 10,
+    #[cfg(feature = "java")]
+    JniProblem = 11,
+    ResponseEmpty = 12,
+    ResponseAuthFailed = 13,
+    UnsupportedSuite = 14,
+    ResponseFramingMismatch = 15,
+    InvalidHandle = 16,
+    ContextEmpty = 17,
+    RngUnavailable = 18,
+    SuiteMismatch = 19,
+    CallbackAborted = 20,
+    KemNotCompiled = 21,
+}
+
+impl ClientError {
+    pub fn kind(&self) -> ClientErrorKind {
+        match self {
+            ClientError::RequestContextInitialization(_) => {
+                ClientErrorKind::RequestContextInitialization
+            }
+            ClientError::EncapsulationFailed(_) => ClientErrorKind::EncapsulationFailed,
+            ClientError::DecapsulationFailed(_) => ClientErrorKind::DecapsulationFailed,
+            ClientError::Ohttp(_) => ClientErrorKind::Ohttp,
+            ClientError::InvalidArgument(_) => ClientErrorKind::InvalidArgument,
+            ClientError::ResponseMalformed(_) => ClientErrorKind::ResponseMalformed,
+            ClientError::MalformedConfig(_) => ClientErrorKind::MalformedConfig,
+            ClientError::ResponseTruncated { .. } => ClientErrorKind::ResponseTruncated,
+            ClientError::BufferTooSmall { .. } => ClientErrorKind::BufferTooSmall,
+            ClientError::SafePanic(_) => ClientErrorKind::SafePanic,
+            #[cfg(feature = "java")]
+            ClientError::JniProblem(_) => ClientErrorKind::JniProblem,
+            ClientError::ResponseEmpty => ClientErrorKind::ResponseEmpty,
+            ClientError::ResponseAuthFailed(_) => ClientErrorKind::ResponseAuthFailed,
+            ClientError::UnsupportedSuite => ClientErrorKind::UnsupportedSuite,
+            ClientError::ResponseFramingMismatch { .. } => ClientErrorKind::ResponseFramingMismatch,
+            ClientError::InvalidHandle => ClientErrorKind::InvalidHandle,
+            ClientError::ContextEmpty => ClientErrorKind::ContextEmpty,
+            ClientError::RngUnavailable(_) => ClientErrorKind::RngUnavailable,
+            ClientError::SuiteMismatch { .. } => ClientErrorKind::SuiteMismatch,
+            ClientError::CallbackAborted => ClientErrorKind::CallbackAborted,
+            ClientError::KemNotCompiled(_) => ClientErrorKind::KemNotCompiled,
+        }
+    }
+}
+
+/// Lets `?` lift errors of direct `ohttp` calls, the source stays reachable
+/// through [`std::error::Error::source`].
+impl From<ohttp::Error> for ClientError {
+    fn from(err: ohttp::Error) -> Self {
+        ClientError::Ohttp(err)
+    }
 }
 
+/// C types of the FFI, `libc` declares none for wasm32-unknown-unknown.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod libc {
+    pub use std::ffi::{c_char, c_int};
+    pub type size_t = usize;
+    pub type ssize_t = isize;
+}
+
+#[cfg(all(feature = "java", feature = "no-error-global"))]
+compile_error!("the `java` feature reports errors as strings and requires the error global");
+
 #[cfg(feature = "java")]
 pub mod android;
 
 pub mod error_ffi;
 
+mod handles;
+
+pub mod bhttp;
+
+pub mod config;
+
+pub mod crypto;
+
+#[cfg(feature = "jwk")]
+pub mod jwk;
+
+#[cfg(feature = "serde")]
+pub mod log_record;
+
+pub mod layout;
+
+pub mod media_type;
+
+pub mod grpc;
+
+pub mod jitter;
+
+#[cfg(feature = "dev-config")]
+pub mod dev;
+
+#[cfg(feature = "debug-tools")]
+pub mod debug_tools;
+
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+
+pub mod multihop;
+
+pub mod multiplex;
+
+pub mod split;
+
+pub mod deprecation;
+
+#[cfg(feature = "workers")]
+pub mod workers;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+/// Length of the SHA-256 digest returned by `request_context_fingerprint_ffi`.
+pub const REQUEST_FINGERPRINT_LEN: usize = 32;
+
+/// Copy of the plaintext kept by `debug-plaintext` builds, wiped on drop with `zeroize`.
+#[cfg(feature = "debug-plaintext")]
+struct DebugPlaintext(Vec<u8>);
+
+#[cfg(all(feature = "debug-plaintext", feature = "zeroize"))]
+impl Drop for DebugPlaintext {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+/// An encapsulated request together with the state needed to decapsulate its response.
+///
+/// Contexts created empty by `request_context_new_ffi` hold no request until
+/// [`RequestContext::encapsulate_into`] fills them.
 pub struct RequestContext {
     encapsulated_request: Vec<u8>,
-    response_context: ClientResponse,
+    decapsulator: Option<ResponseDecapsulator>,
+    /// Whether the decapsulator was taken to decapsulate the response, telling a second
+    /// attempt apart from a context that never held a request.
+    decapsulated: bool,
+    #[cfg(feature = "debug-plaintext")]
+    plaintext: DebugPlaintext,
+    #[cfg(feature = "diagnostics")]
+    live_bytes: diagnostics::LiveBytes,
+}
+
+impl RequestContext {
+    /// Encapsulates the provided `msg` using `encoded_config`, with the first suite of the
+    /// configuration this build supports.
+    ///
+    /// Fails with [`ClientError::RngUnavailable`] if the system random number generator the
+    /// ephemeral key is drawn from can not be read.
+    pub fn new(encoded_config: &[u8], msg: &[u8]) -> Result<Self, ClientError> {
+        Self::with_provider(&OhttpProvider, encoded_config, msg)
+    }
+
+    /// Like [`RequestContext::new`], encapsulating with `provider` instead of `ohttp`,
+    /// e.g. the `MockCryptoProvider` of `mock-crypto` builds in tests.
+    pub fn with_provider(
+        provider: &dyn CryptoProvider,
+        encoded_config: &[u8],
+        msg: &[u8],
+    ) -> Result<Self, ClientError> {
+        let config = KeyConfig::decode(encoded_config)?;
+        let suite = config.select_supported_suite()?;
+        Self::encapsulate_to(provider, &config.restricted_to(suite), suite, msg)
+    }
+
+    /// Encapsulates `msg` to `config`, already restricted to the selected `suite`.
+    pub(crate) fn encapsulate_to(
+        provider: &dyn CryptoProvider,
+        config: &KeyConfig,
+        suite: HpkeSuite,
+        msg: &[u8],
+    ) -> Result<Self, ClientError> {
+        let (encapsulated_request, opener) = provider.encapsulate(config, msg)?;
+        if let Err(err) = check_request_header(&encapsulated_request, config.key_id, suite) {
+            if cfg!(feature = "suite-checks") {
+                return Err(err);
+            }
+            if cfg!(debug_assertions) {
+                panic!("{err}");
+            }
+        }
+        Ok(RequestContext {
+            #[cfg(feature = "diagnostics")]
+            live_bytes: diagnostics::LiveBytes::track(
+                std::mem::size_of::<RequestContext>() + encapsulated_request.capacity(),
+            ),
+            encapsulated_request,
+            decapsulator: Some(ResponseDecapsulator { opener, suite }),
+            decapsulated: false,
+            #[cfg(feature = "debug-plaintext")]
+            plaintext: DebugPlaintext(msg.to_vec()),
+        })
+    }
+
+    /// A context holding no request, for pooling.
+    pub(crate) fn empty() -> Self {
+        RequestContext {
+            encapsulated_request: Vec::new(),
+            decapsulator: None,
+            decapsulated: false,
+            #[cfg(feature = "debug-plaintext")]
+            plaintext: DebugPlaintext(Vec::new()),
+            #[cfg(feature = "diagnostics")]
+            live_bytes: diagnostics::LiveBytes::track(std::mem::size_of::<RequestContext>()),
+        }
+    }
+
+    /// Replaces whatever the context held with a fresh encapsulation of `msg`.
+    ///
+    /// The previous request and its decapsulation state are discarded first, so the
+    /// context is empty if encapsulation fails.
+    pub fn encapsulate_into(
+        &mut self,
+        encoded_config: &[u8],
+        msg: &[u8],
+    ) -> Result<(), ClientError> {
+        self.encapsulate_into_with_provider(&OhttpProvider, encoded_config, msg)
+    }
+
+    /// Like [`RequestContext::encapsulate_into`], encapsulating with `provider` instead of
+    /// `ohttp`.
+    pub fn encapsulate_into_with_provider(
+        &mut self,
+        provider: &dyn CryptoProvider,
+        encoded_config: &[u8],
+        msg: &[u8],
+    ) -> Result<(), ClientError> {
+        *self = Self::empty();
+        *self = Self::with_provider(provider, encoded_config, msg)?;
+        Ok(())
+    }
+
+    /// Encapsulates `msg` with the suite of `encoded_config` using `kdf` and `aead`, see
+    /// [`KeyConfig::select_suite`].
+    pub fn with_suite(
+        encoded_config: &[u8],
+        msg: &[u8],
+        kdf: u16,
+        aead: u16,
+    ) -> Result<Self, ClientError> {
+        let config = KeyConfig::decode(encoded_config)?;
+        let suite = config.select_suite(kdf, aead)?;
+        Self::encapsulate_to(&OhttpProvider, &config.restricted_to(suite), suite, msg)
+    }
+
+    /// Encapsulates `msg` after padding it with zeros to the next multiple of `bucket_size`.
+    pub fn new_padded(
+        encoded_config: &[u8],
+        msg: &[u8],
+        bucket_size: usize,
+    ) -> Result<Self, ClientError> {
+        if bucket_size == 0 {
+            return Err(ClientError::InvalidArgument(
+                "bucket_size must not be 0".to_owned(),
+            ));
+        }
+        let mut padded = msg.to_vec();
+        padded.resize(msg.len().div_ceil(bucket_size) * bucket_size, 0);
+        Self::new(encoded_config, &padded)
+    }
+
+    /// The encapsulated request that should be sent to the relay, as the body of a POST
+    /// with the `message/ohttp-req` content type:
+    ///
+    /// ```
+    /// # #[cfg(feature = "server")]
+    /// # {
+    /// # use ohttp::hpke::{Aead, Kdf, Kem};
+    /// # use ohttp::{KeyConfig, SymmetricSuite};
+    /// # // An in-process gateway stands in for the relay.
+    /// # let suite = SymmetricSuite::new(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    /// # let config = KeyConfig::new(1, Kem::X25519Sha256, vec![suite]).unwrap();
+    /// # let encoded_config = config.encode().unwrap();
+    /// # let mut relay = apprelay::server::TestServer::new(config).unwrap();
+    /// # let mut post = |body: &[u8]| relay.handle(body, |_| b"HTTP/1.1 204".to_vec()).unwrap();
+    /// let mut context = apprelay::RequestContext::new(&encoded_config, b"GET / HTTP/1.1")?;
+    /// let response = post(context.encapsulated_request());
+    /// assert_eq!(context.decapsulate(&response)?, b"HTTP/1.1 204");
+    /// # }
+    /// # Ok::<(), apprelay::ClientError>(())
+    /// ```
+    pub fn encapsulated_request(&self) -> &[u8] {
+        &self.encapsulated_request
+    }
+
+    /// SHA-256 digest of the encapsulated request, for correlating requests in logs.
+    ///
+    /// The encapsulated request is already ciphertext, so the digest reveals nothing about the message.
+    pub fn fingerprint(&self) -> [u8; REQUEST_FINGERPRINT_LEN] {
+        let mut fingerprint = [0; REQUEST_FINGERPRINT_LEN];
+        fingerprint.copy_from_slice(&Sha256::digest(&self.encapsulated_request));
+        fingerprint
+    }
+
+    /// The message the request was encapsulated from, including any padding.
+    ///
+    /// Only for debugging, the plaintext stays in memory as long as the context.
+    #[cfg(feature = "debug-plaintext")]
+    pub fn plaintext(&self) -> &[u8] {
+        &self.plaintext.0
+    }
+
+    /// The HPKE suite the request was encapsulated with, `None` for an empty context.
+    pub fn suite(&self) -> Option<HpkeSuite> {
+        self.decapsulator
+            .as_ref()
+            .map(|decapsulator| decapsulator.suite)
+    }
+
+    /// Key identifier of the configuration the request was encapsulated to,
+    /// `None` for an empty context.
+    pub fn key_id(&self) -> Option<u8> {
+        self.suite()?;
+        self.encapsulated_request.first().copied()
+    }
+
+    /// The encapsulated key (`enc`) following the header of the request,
+    /// `None` for an empty context.
+    pub fn enc(&self) -> Option<&[u8]> {
+        let enc_len = config::kem_enc_len(self.suite()?.kem)?;
+        self.encapsulated_request
+            .get(config::REQUEST_HEADER_LEN..config::REQUEST_HEADER_LEN + enc_len)
+    }
+
+    /// Splits the context into the encapsulated request and the
+    /// [`ResponseDecapsulator`] used to decapsulate the relay's reply.
+    ///
+    /// Fails with [`ClientError::ContextEmpty`] if the context holds no request.
+    ///
+    /// ```
+    /// # #[cfg(feature = "server")]
+    /// # {
+    /// # use ohttp::hpke::{Aead, Kdf, Kem};
+    /// # use ohttp::{KeyConfig, SymmetricSuite};
+    /// # // An in-process gateway stands in for the relay.
+    /// # let suite = SymmetricSuite::new(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    /// # let config = KeyConfig::new(1, Kem::X25519Sha256, vec![suite]).unwrap();
+    /// # let encoded_config = config.encode().unwrap();
+    /// # let mut relay = apprelay::server::TestServer::new(config).unwrap();
+    /// # let mut post = |body: &[u8]| relay.handle(body, |_| b"HTTP/1.1 204".to_vec()).unwrap();
+    /// let context = apprelay::RequestContext::new(&encoded_config, b"GET / HTTP/1.1")?;
+    /// let (request, decapsulator) = context.into_parts()?;
+    /// let response = post(&request);
+    /// assert_eq!(decapsulator.decapsulate(&response)?, b"HTTP/1.1 204");
+    /// # }
+    /// # Ok::<(), apprelay::ClientError>(())
+    /// ```
+    pub fn into_parts(self) -> Result<(Vec<u8>, ResponseDecapsulator), ClientError> {
+        let decapsulator = self.decapsulator.ok_or(ClientError::ContextEmpty)?;
+        Ok((self.encapsulated_request, decapsulator))
+    }
+
+    /// Reassembles a context from the two fields an encapsulated request was carried in,
+    /// `header` holding the key id, the suite and `enc`, and `ciphertext` the rest, with the
+    /// `decapsulator` split off the original context by [`RequestContext::into_parts`].
+    ///
+    /// Fails with [`ClientError::InvalidArgument`] if `header` is not as long as the KEM's
+    /// `enc` requires and with [`ClientError::SuiteMismatch`] if it names another key id than
+    /// `encoded_config` or another suite than the decapsulator. In `debug-plaintext` builds
+    /// the plaintext of the reassembled context is empty.
+    pub fn from_parts(
+        encoded_config: &[u8],
+        header: &[u8],
+        ciphertext: &[u8],
+        decapsulator: ResponseDecapsulator,
+    ) -> Result<Self, ClientError> {
+        check_request_parts(encoded_config, header, decapsulator.suite)?;
+        let mut encapsulated_request = Vec::with_capacity(header.len() + ciphertext.len());
+        encapsulated_request.extend_from_slice(header);
+        encapsulated_request.extend_from_slice(ciphertext);
+        Ok(RequestContext {
+            #[cfg(feature = "diagnostics")]
+            live_bytes: diagnostics::LiveBytes::track(
+                std::mem::size_of::<RequestContext>() + encapsulated_request.capacity(),
+            ),
+            encapsulated_request,
+            decapsulator: Some(decapsulator),
+            decapsulated: false,
+            #[cfg(feature = "debug-plaintext")]
+            plaintext: DebugPlaintext(Vec::new()),
+        })
+    }
+
+    /// Decapsulates the relay's response, see [`ResponseDecapsulator::decapsulate`], leaving
+    /// the context without a request.
+    ///
+    /// A second call fails with [`ClientError::InvalidArgument`] since the HPKE state
+    /// of the request is consumed by the first, whether it succeeded or not, and a context
+    /// that never held a request fails with [`ClientError::ContextEmpty`].
+    pub fn decapsulate(&mut self, encapsulated_response: &[u8]) -> Result<Vec<u8>, ClientError> {
+        self.take_decapsulator()?.decapsulate(encapsulated_response)
+    }
+
+    /// Takes the decapsulation state out, leaving the context without a request.
+    pub(crate) fn take_decapsulator(&mut self) -> Result<ResponseDecapsulator, ClientError> {
+        let decapsulator = match self.decapsulator.take() {
+            Some(decapsulator) => decapsulator,
+            None if self.decapsulated => {
+                return Err(ClientError::InvalidArgument(
+                    "response already decapsulated".to_owned(),
+                ))
+            }
+            None => return Err(ClientError::ContextEmpty),
+        };
+        self.decapsulated = true;
+        self.encapsulated_request.clear();
+        Ok(decapsulator)
+    }
+}
+
+/// Reads a byte from the system random number generator.
+///
+/// `ohttp` seeds the generator of the ephemeral key from the system and panics if that
+/// fails, so probing it first turns a sandbox blocking `getrandom` into a proper error.
+pub(crate) fn check_system_rng() -> Result<(), ClientError> {
+    getrandom::getrandom(&mut [0u8; 1]).map_err(ClientError::RngUnavailable)
+}
+
+/// Checks that the header of `encapsulated_request` names the key id and suite the context
+/// records, which its response is decapsulated with.
+///
+/// A mismatch means the configuration used for encapsulation diverged from the one the suite
+/// was selected from, e.g. a stale cached parse. Debug builds assert, `suite-checks` builds
+/// return [`ClientError::SuiteMismatch`] instead.
+fn check_request_header(
+    encapsulated_request: &[u8],
+    key_id: u8,
+    suite: HpkeSuite,
+) -> Result<(), ClientError> {
+    let mut expected = [0; config::REQUEST_HEADER_LEN];
+    expected[0] = key_id;
+    expected[1..3].copy_from_slice(&suite.kem.to_be_bytes());
+    expected[3..5].copy_from_slice(&suite.kdf.to_be_bytes());
+    expected[5..7].copy_from_slice(&suite.aead.to_be_bytes());
+
+    let mut actual = [0; config::REQUEST_HEADER_LEN];
+    let header = encapsulated_request
+        .get(..config::REQUEST_HEADER_LEN)
+        .unwrap_or(encapsulated_request);
+    actual[..header.len()].copy_from_slice(header);
+    if actual != expected || header.len() < config::REQUEST_HEADER_LEN {
+        return Err(ClientError::SuiteMismatch {
+            expected: suite,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that `header` is the header and `enc` of a request encapsulated with `suite` to
+/// `encoded_config`, before [`RequestContext::from_parts`] reassembles the request.
+pub(crate) fn check_request_parts(
+    encoded_config: &[u8],
+    header: &[u8],
+    suite: HpkeSuite,
+) -> Result<(), ClientError> {
+    let config = KeyConfig::decode(encoded_config)?;
+    let enc_len = config::kem_enc_len(suite.kem).ok_or(ClientError::UnsupportedSuite)?;
+    let expected = config::REQUEST_HEADER_LEN + enc_len;
+    if header.len() != expected {
+        return Err(ClientError::InvalidArgument(format!(
+            "Encapsulated request header is {} bytes, the suite's KEM needs {expected}",
+            header.len()
+        )));
+    }
+    check_request_header(header, config.key_id, suite)
+}
+
+/// Decapsulation half of a [`RequestContext`].
+///
+/// `ohttp` consumes the HPKE context of a request when decapsulating its response, so
+/// [`ResponseDecapsulator::decapsulate`] takes the decapsulator by value and a second
+/// decapsulation does not compile:
+///
+/// ```compile_fail
+/// # fn twice(context: apprelay::RequestContext, response: &[u8]) -> Result<(), apprelay::ClientError> {
+/// let (_request, decapsulator) = context.into_parts()?;
+/// decapsulator.decapsulate(response)?;
+/// decapsulator.decapsulate(response)?; // use of moved value: `decapsulator`
+/// # Ok(())
+/// # }
+/// ```
+pub struct ResponseDecapsulator {
+    opener: Box<dyn ResponseOpener>,
+    suite: HpkeSuite,
+}
+
+impl ResponseDecapsulator {
+    /// Decapsulates the relay's response to the request this decapsulator was created for.
+    ///
+    /// Responses too short to hold the response nonce and the AEAD tag are rejected
+    /// with [`ClientError::ResponseTruncated`] without attempting to open them.
+    ///
+    /// The response key is derived from this request's HPKE context, so a response
+    /// encapsulated for a different request (even under the same key configuration) or
+    /// altered in transit fails the AEAD check with [`ClientError::ResponseAuthFailed`]
+    /// and never yields plaintext. Any other failure to open the response is reported as
+    /// [`ClientError::DecapsulationFailed`].
+    pub fn decapsulate(self, encapsulated_response: &[u8]) -> Result<Vec<u8>, ClientError> {
+        if let Some(expected) = self.suite.min_response_len() {
+            if encapsulated_response.len() < expected {
+                return Err(ClientError::ResponseTruncated {
+                    expected,
+                    actual: encapsulated_response.len(),
+                });
+            }
+        }
+        self.opener.open(encapsulated_response)
+    }
+}
+
+/// Borrowed view of bytes owned by another object.
+///
+/// The view is only valid as long as the owning object is alive.
+/// Laid out as `ptr` at offset 0 followed by `len`, see [`layout::StructSizes`].
+#[repr(C)]
+pub struct BytesView {
+    pub ptr: *const u8,
+    pub len: libc::size_t,
+}
+
+impl BytesView {
+    pub(crate) fn from_slice(bytes: &[u8]) -> Self {
+        BytesView {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    pub(crate) fn empty() -> Self {
+        BytesView {
+            ptr: ptr::null(),
+            len: 0,
+        }
+    }
 }
 
 #[macro_export]
@@ -58,6 +661,51 @@ macro_rules! null_safe_ptr {
     };
 }
 
+/// Copies `bytes` into the caller provided `out` buffer of `out_cap` bytes.
+///
+/// Returns the number of bytes written, or -1 if `out` is NULL or too small.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+pub(crate) unsafe fn write_out_buffer(
+    bytes: &[u8],
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let out = null_safe_ptr!(out, -1, out);
+    if bytes.len() > out_cap {
+        update_last_error(ClientError::BufferTooSmall {
+            needed: bytes.len(),
+            available: out_cap,
+        });
+        return -1;
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len());
+    bytes.len() as libc::ssize_t
+}
+
+/// Borrows the NUL terminated C string `ptr` as UTF-8, `field_name` names the argument in errors.
+///
+/// Returns `ClientError::InvalidArgument` if `ptr` is NULL or not valid UTF-8, callers report it
+/// through `safe_unwrap!` like any other error.
+///
+/// # Safety
+/// The invariants are described here [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety)
+pub(crate) unsafe fn cstr_to_str<'a>(
+    ptr: *const libc::c_char,
+    field_name: &str,
+) -> Result<&'a str, ClientError> {
+    if ptr.is_null() {
+        return Err(ClientError::InvalidArgument(format!(
+            "Passed null pointer argument {}",
+            field_name
+        )));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| ClientError::InvalidArgument(format!("{} is not valid UTF-8", field_name)))
+}
+
 macro_rules! catch_panics {
     ($possibly_panic:expr, $error_ret:expr) => {
         match catch_unwind(|| $possibly_panic) {
@@ -71,6 +719,8 @@ macro_rules! catch_panics {
     };
 }
 
+pub(crate) use catch_panics;
+
 #[macro_export]
 macro_rules! safe_unwrap {
     ($possibly_err:expr, $error_ret:expr, $err_context:expr) => {
@@ -94,13 +744,12 @@ macro_rules! safe_unwrap {
 /// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
 #[no_mangle]
 pub unsafe extern "C" fn request_context_message_ffi(context: *mut RequestContext) -> *mut u8 {
-    null_safe_ptr!(
-        context,
+    null_safe_ptr!(context, ptr::null_mut(), ());
+    safe_unwrap!(
+        handles::with(context, |context| context.encapsulated_request.as_ptr()),
         ptr::null_mut(),
-        (*(*Box::into_raw(Box::new(context))))
-            .encapsulated_request
-            .as_mut_ptr() as *mut u8
-    )
+        identity
+    ) as *mut u8
 }
 
 /// Return the size in bytes of the encapsulated request.
@@ -114,12 +763,93 @@ pub unsafe extern "C" fn request_context_message_ffi(context: *mut RequestContex
 pub unsafe extern "C" fn request_context_message_len_ffi(
     context: *mut RequestContext,
 ) -> libc::size_t {
-    null_safe_ptr!(
-        context,
+    null_safe_ptr!(context, 0, ());
+    safe_unwrap!(
+        handles::with(context, |context| context.encapsulated_request.len()),
         0,
-        (*(*Box::into_raw(Box::new(context))))
-            .encapsulated_request
-            .len()
+        identity
+    )
+}
+
+/// Returns the `Content-Length` of the POST carrying the request to the relay.
+///
+/// The body of that POST is the encapsulated request and nothing else, with content type
+/// `message/ohttp-req`, so this is the length of [`request_context_message_ffi`]. The BHTTP
+/// message inside declares lengths of its own, which the relay never sees.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn request_context_post_body_len_ffi(
+    context: *const RequestContext,
+) -> libc::size_t {
+    null_safe_ptr!(context, 0, ());
+    safe_unwrap!(
+        handles::with(context, |context| context.encapsulated_request.len()),
+        0,
+        identity
+    )
+}
+
+/// Writes the SHA-256 digest of the encapsulated request, [`REQUEST_FINGERPRINT_LEN`] bytes, into `out`.
+///
+/// The digest is computed over the ciphertext only, so it can be logged to trace a request
+/// through relays without revealing anything about its content.
+/// Returns the number of bytes written, or -1 if `out` is too small.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn request_context_fingerprint_ffi(
+    context: *const RequestContext,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    null_safe_ptr!(context, -1, ());
+    safe_unwrap!(
+        handles::with(context, |context| write_out_buffer(
+            &context.fingerprint(),
+            out,
+            out_cap
+        )),
+        -1,
+        identity
+    )
+}
+
+/// Writes the plaintext message `context` was encapsulated from into `out`, to check the
+/// BHTTP encoding during integration.
+///
+/// **Insecure, only available in `debug-plaintext` builds which must never ship to production.**
+/// Returns the number of bytes written, or -1 if `out` is too small.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[cfg(feature = "debug-plaintext")]
+#[no_mangle]
+pub unsafe extern "C" fn request_context_plaintext_ffi(
+    context: *const RequestContext,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    null_safe_ptr!(context, -1, ());
+    safe_unwrap!(
+        handles::with(context, |context| write_out_buffer(
+            context.plaintext(),
+            out,
+            out_cap
+        )),
+        -1,
+        identity
     )
 }
 
@@ -134,47 +864,222 @@ pub unsafe extern "C" fn request_context_message_len_ffi(
 /// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
 #[no_mangle]
 pub unsafe extern "C" fn request_context_message_drop_ffi(context: *mut RequestContext) {
-    null_safe_ptr!(context, (), {
-        let _context = Box::from_raw(context);
-    })
+    null_safe_ptr!(context, (), ());
+    if let Err(err) = handles::take(context) {
+        update_last_error(err);
+    }
 }
 
+/// Decapsulated response returned by `decapsulate_response_ffi`.
+///
+/// The accessors only borrow the context, so a C caller reads a response with
+/// `response_context_message_len_ffi`, allocates that many bytes, copies them with
+/// `response_context_copy_ffi` (or reads `response_context_message_ffi` in place) and
+/// finally frees the context once with `response_context_drop_ffi`.
 pub struct ResponseContext {
     response: Vec<u8>,
+    #[cfg(feature = "diagnostics")]
+    live_bytes: diagnostics::LiveBytes,
+}
+
+impl ResponseContext {
+    pub(crate) fn new(response: Vec<u8>) -> Self {
+        ResponseContext {
+            #[cfg(feature = "diagnostics")]
+            live_bytes: diagnostics::LiveBytes::track(
+                std::mem::size_of::<ResponseContext>() + response.capacity(),
+            ),
+            response,
+        }
+    }
+
+    /// The decapsulated response, borrowed until the context is dropped.
+    ///
+    /// Use it to parse the response in place, e.g. with [`bhttp::BhttpResponse::decode`]:
+    ///
+    /// ```
+    /// # // The test wrapper stands in for a decapsulated response.
+    /// # #[cfg(feature = "testutil")]
+    /// # {
+    /// # let plaintext = b"\x01\x40\xc8\x00\x02hi\x00";
+    /// # let response = unsafe {
+    /// #     Box::from_raw(apprelay::response_context_from_plaintext_ffi(
+    /// #         plaintext.as_ptr(),
+    /// #         plaintext.len(),
+    /// #     ))
+    /// # };
+    /// let parsed = apprelay::bhttp::BhttpResponse::decode(response.body())?;
+    /// assert_eq!(parsed.status(), 200);
+    /// assert_eq!(parsed.body(), b"hi");
+    /// # }
+    /// # Ok::<(), apprelay::ClientError>(())
+    /// ```
+    pub fn body(&self) -> &[u8] {
+        &self.response
+    }
+
+    /// Reads the decapsulated response in place, for parsers and decompressors consuming
+    /// [`std::io::Read`]:
+    ///
+    /// ```
+    /// # use std::io::{BufRead, BufReader};
+    /// # // The test wrapper stands in for a decapsulated response.
+    /// # #[cfg(feature = "testutil")]
+    /// # {
+    /// # let plaintext = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+    /// # let response = unsafe {
+    /// #     Box::from_raw(apprelay::response_context_from_plaintext_ffi(
+    /// #         plaintext.as_ptr(),
+    /// #         plaintext.len(),
+    /// #     ))
+    /// # };
+    /// let mut line = String::new();
+    /// BufReader::new(response.reader()).read_line(&mut line)?;
+    /// assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+    /// # }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn reader(&self) -> impl std::io::Read + '_ {
+        std::io::Cursor::new(self.body())
+    }
+
+    /// Takes ownership of the decapsulated response without copying it.
+    ///
+    /// ```
+    /// # // The test wrapper stands in for a decapsulated response.
+    /// # #[cfg(feature = "testutil")]
+    /// # {
+    /// # let plaintext = b"HTTP/1.1 204 No Content\r\n\r\n";
+    /// # let response = unsafe {
+    /// #     Box::from_raw(apprelay::response_context_from_plaintext_ffi(
+    /// #         plaintext.as_ptr(),
+    /// #         plaintext.len(),
+    /// #     ))
+    /// # };
+    /// let in_place = response.body().as_ptr();
+    /// let bytes: Vec<u8> = response.into_bytes();
+    /// assert_eq!(bytes.as_ptr(), in_place);
+    /// assert_eq!(bytes, b"HTTP/1.1 204 No Content\r\n\r\n");
+    /// # }
+    /// ```
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.response
+    }
 }
 
 /// Return a pointer to the decapsulated response.
 ///
+/// The pointer borrows the context and stays valid until it is dropped.
+///
 /// # Safety
-/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Dereferences a pointer to `ResponseContext` passed by the caller.
 /// Be sure that the context has not been yet freed and that you are using a valid pointer.
 ///
 /// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
 #[no_mangle]
 pub unsafe extern "C" fn response_context_message_ffi(context: *mut ResponseContext) -> *mut u8 {
-    null_safe_ptr!(
-        context,
-        ptr::null_mut(),
-        (*(*Box::into_raw(Box::new(context)))).response.as_mut_ptr() as *mut u8
-    )
+    null_safe_ptr!(context, ptr::null_mut(), (*context).response.as_mut_ptr())
 }
 
-/// Return size in bytes of the decapsulated response.
+/// Return size in bytes of the decapsulated response, without consuming the context.
 ///
 /// # Safety
-/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Dereferences a pointer to `ResponseContext` passed by the caller.
 /// Be sure that the context has not been yet freed and that you are using a valid pointer.
 ///
 /// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
 #[no_mangle]
 pub unsafe extern "C" fn response_context_message_len_ffi(
-    context: *mut ResponseContext,
+    context: *const ResponseContext,
 ) -> libc::size_t {
-    null_safe_ptr!(
-        context,
-        0,
-        (*(*Box::into_raw(Box::new(context)))).response.len()
-    )
+    null_safe_ptr!(context, 0, (*context).response.len())
+}
+
+/// Copies the decapsulated response into `out`, leaving the context untouched.
+///
+/// Returns the number of bytes written, or -1 if `out` is smaller than
+/// `response_context_message_len_ffi` reports.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn response_context_copy_ffi(
+    context: *const ResponseContext,
+    out: *mut u8,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    null_safe_ptr!(context, -1, ());
+    write_out_buffer(&(*context).response, out, out_cap)
+}
+
+/// Frees up the decapsulated response once it has been read.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn response_context_drop_ffi(context: *mut ResponseContext) {
+    null_safe_ptr!(context, (), {
+        let _context = Box::from_raw(context);
+    })
+}
+
+/// Consumes `context` and hands its decapsulated response over to the caller, writing
+/// its length to `*out_len`, instead of copying it out with [`response_context_copy_ffi`].
+///
+/// Free the bytes with [`split::apprelay_free_bytes_ffi`] and that length, the context must
+/// not be used or freed afterwards. The buffer is first shrunk to the length of the
+/// response, which allocators usually do in place.
+///
+/// This function will return a NULL pointer, leaving `context` untouched, if `out_len` is NULL.
+///
+/// **Deprecated**, read the response in place with [`response_context_message_ffi`] or copy
+/// it with [`response_context_copy_ffi`]. Both borrow the context, while a context used after
+/// this call reads freed memory.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn response_context_take_bytes_ffi(
+    context: *mut ResponseContext,
+    out_len: *mut libc::size_t,
+) -> *mut u8 {
+    deprecation::warn_deprecated!(
+        "response_context_take_bytes_ffi",
+        "response_context_message_ffi or response_context_copy_ffi"
+    );
+    null_safe_ptr!(context, ptr::null_mut(), ());
+    null_safe_ptr!(out_len, ptr::null_mut(), ());
+    let response = Box::from_raw(context).into_bytes().into_boxed_slice();
+    *out_len = response.len();
+    Box::into_raw(response) as *mut u8
+}
+
+/// Wraps an already decapsulated response in a `ResponseContext`, to exercise
+/// `parse_bhttp_response_ffi` and the response accessors without a crypto round trip.
+///
+/// **Test helper, only available in `testutil` builds and not meant for production.**
+/// The bytes are copied, free the context with [`response_context_drop_ffi`].
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[cfg(feature = "testutil")]
+#[no_mangle]
+pub unsafe extern "C" fn response_context_from_plaintext_ffi(
+    plaintext_ptr: *const u8,
+    plaintext_len: libc::size_t,
+) -> *mut ResponseContext {
+    let plaintext_ptr = null_safe_ptr!(plaintext_ptr, ptr::null_mut(), plaintext_ptr);
+    let response = slice::from_raw_parts(plaintext_ptr, plaintext_len).to_vec();
+    Box::into_raw(Box::new(ResponseContext::new(response)))
 }
 
 /// Encapsulates the provided `encoded_msg` using `encoded_config` and returns
@@ -196,6 +1101,286 @@ pub unsafe extern "C" fn encapsulate_request_ffi(
     encoded_msg_ptr: *const u8,
     encoded_msg_len: libc::size_t,
 ) -> *mut RequestContext {
+    encapsulate_request_with(
+        encoded_config_ptr,
+        encoded_config_len,
+        encoded_msg_ptr,
+        encoded_msg_len,
+        RequestContext::new,
+    )
+}
+
+/// Encapsulates the provided `encoded_msg` like [`encapsulate_request_ffi`], after padding
+/// it with zeros to the next multiple of `bucket_size` bytes.
+///
+/// Padding with zeros is the BHTTP padding mechanism, so the gateway discards it when
+/// decoding the message. Requests grow by at most `bucket_size - 1` bytes, and since the
+/// encapsulation overhead is constant for a given configuration, all messages from the
+/// same bucket produce encapsulated requests of the same size.
+///
+/// This function will return a NULL pointer if `bucket_size` is 0 or encapsulation fails.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_request_padded_ffi(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+    bucket_size: libc::size_t,
+) -> *mut RequestContext {
+    encapsulate_request_with(
+        encoded_config_ptr,
+        encoded_config_len,
+        encoded_msg_ptr,
+        encoded_msg_len,
+        |config, msg| RequestContext::new_padded(config, msg, bucket_size),
+    )
+}
+
+/// Encapsulates the provided `encoded_msg` like [`encapsulate_request_ffi`], with the suite of
+/// `encoded_config` using `kdf` and `aead` instead of the first supported one, so a client
+/// policy, e.g. ChaCha20Poly1305 only on hardware without AES instructions, overrides the
+/// order of the configuration.
+///
+/// This function will return a NULL pointer with `UnsupportedSuite` if the configuration
+/// does not offer the suite or this build does not support it, or if encapsulation fails.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_request_with_suite_ffi(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+    kdf: u16,
+    aead: u16,
+) -> *mut RequestContext {
+    encapsulate_request_with(
+        encoded_config_ptr,
+        encoded_config_len,
+        encoded_msg_ptr,
+        encoded_msg_len,
+        |config, msg| RequestContext::with_suite(config, msg, kdf, aead),
+    )
+}
+
+/// Encapsulates `original_msg` again for `new_config`, to retry an exchange the gateway
+/// rejected because it rotated its key after the configuration was fetched.
+///
+/// Behaves like [`encapsulate_request_ffi`]. Contexts do not keep the message they were
+/// encapsulated from, so the caller has to supply the same BHTTP message again; the
+/// encoding does not depend on the configuration and needs no rebuilding. In
+/// `debug-plaintext` builds it can be copied out of the failed context with
+/// `request_context_plaintext_ffi`, before the context is decapsulated or dropped.
+///
+/// This function will return a NULL pointer if the configuration is invalid or
+/// encapsulation fails.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn reencapsulate_ffi(
+    new_config_ptr: *const u8,
+    new_config_len: libc::size_t,
+    original_msg_ptr: *const u8,
+    original_msg_len: libc::size_t,
+) -> *mut RequestContext {
+    encapsulate_request_with(
+        new_config_ptr,
+        new_config_len,
+        original_msg_ptr,
+        original_msg_len,
+        RequestContext::new,
+    )
+}
+
+/// Everything needed to send an encapsulated request and later decapsulate its response.
+#[repr(C)]
+pub struct EncapsulateResult {
+    /// Context to pass to `decapsulate_response_ffi`, NULL if encapsulation failed.
+    pub context: *mut RequestContext,
+    /// The encapsulated request, owned by `context`.
+    pub message: *const u8,
+    pub message_len: libc::size_t,
+    /// The `ClientErrorKind` of the failure, 0 on success.
+    pub error_code: libc::c_int,
+}
+
+/// Routing-relevant header of an encapsulated request.
+#[repr(C)]
+pub struct OhttpHeader {
+    pub key_id: u8,
+    pub kem: u16,
+    pub kdf: u16,
+    pub aead: u16,
+    /// The encapsulated key, borrowed from the context.
+    pub enc_ptr: *const u8,
+    pub enc_len: libc::size_t,
+}
+
+/// Fills `out` with the key id, the suite and the encapsulated key (`enc`) of the request
+/// held by `context`.
+///
+/// `enc_ptr` points into the encapsulated request owned by `context`, so it stays valid
+/// until `context` is decapsulated, refilled or dropped.
+///
+/// Returns 0 on success, or -1 if the context is not live or holds no request.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn request_context_header_ffi(
+    context: *const RequestContext,
+    out: *mut OhttpHeader,
+) -> libc::c_int {
+    let out = null_safe_ptr!(out, -1, out);
+    let header = safe_unwrap!(
+        handles::with(context, |context| -> Result<OhttpHeader, ClientError> {
+            let suite = context.suite().ok_or(ClientError::ContextEmpty)?;
+            let key_id = context.key_id().ok_or(ClientError::ContextEmpty)?;
+            let enc = context.enc().ok_or(ClientError::ContextEmpty)?;
+            Ok(OhttpHeader {
+                key_id,
+                kem: suite.kem,
+                kdf: suite.kdf,
+                aead: suite.aead,
+                enc_ptr: enc.as_ptr(),
+                enc_len: enc.len(),
+            })
+        })
+        .and_then(identity),
+        -1,
+        identity
+    );
+    ptr::write(out, header);
+    0
+}
+
+/// Encapsulates the provided `encoded_msg` like [`encapsulate_request_ffi`] and returns the
+/// context together with the encapsulated request it owns.
+///
+/// `message` stays valid until `context` is decapsulated or dropped. On failure `context`
+/// and `message` are NULL and `error_code` holds the code also returned by `last_error_code`.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_request_full_ffi(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+) -> EncapsulateResult {
+    let context = encapsulate_request_ffi(
+        encoded_config_ptr,
+        encoded_config_len,
+        encoded_msg_ptr,
+        encoded_msg_len,
+    );
+    if context.is_null() {
+        return EncapsulateResult {
+            context,
+            message: ptr::null(),
+            message_len: 0,
+            error_code: error_ffi::last_error_code(),
+        };
+    }
+    let message = (*context).encapsulated_request();
+    EncapsulateResult {
+        context,
+        message: message.as_ptr(),
+        message_len: message.len(),
+        error_code: 0,
+    }
+}
+
+/// Creates an empty context to be filled by [`encapsulate_into_context_ffi`], for pools reusing
+/// contexts across requests.
+///
+/// Decapsulating a response empties such a context instead of freeing it, free it with
+/// `request_context_message_drop_ffi` when tearing the pool down.
+#[no_mangle]
+pub extern "C" fn request_context_new_ffi() -> *mut RequestContext {
+    handles::register_pooled()
+}
+
+/// Encapsulates the provided `encoded_msg` using `encoded_config` into `context`,
+/// discarding any request it held before.
+///
+/// Returns 0 on success, or -1 if encapsulation fails, leaving `context` empty.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_into_context_ffi(
+    context: *mut RequestContext,
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+) -> libc::c_int {
+    encapsulate_into_context_with_provider(
+        &OhttpProvider,
+        context,
+        encoded_config_ptr,
+        encoded_config_len,
+        encoded_msg_ptr,
+        encoded_msg_len,
+    )
+}
+
+/// Like [`encapsulate_into_context_ffi`], encapsulating with `provider` instead of `ohttp`,
+/// e.g. the `MockCryptoProvider` of `mock-crypto` builds in tests.
+///
+/// # Safety
+/// Same as [`encapsulate_into_context_ffi`].
+pub unsafe fn encapsulate_into_context_with_provider(
+    provider: &dyn CryptoProvider,
+    context: *mut RequestContext,
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+) -> libc::c_int {
+    null_safe_ptr!(context, -1, ());
+    let encoded_config_ptr = null_safe_ptr!(encoded_config_ptr, -1, encoded_config_ptr);
+    let encoded_msg_ptr = null_safe_ptr!(encoded_msg_ptr, -1, encoded_msg_ptr);
+
+    let encoded_config = slice::from_raw_parts(encoded_config_ptr, encoded_config_len);
+    let encoded_msg = slice::from_raw_parts(encoded_msg_ptr, encoded_msg_len);
+
+    // The context is only reached through its handle and is emptied before encapsulating,
+    // so a panic can not leave a half-written request behind.
+    let encapsulate = AssertUnwindSafe(|| {
+        handles::with_mut(context, |context| {
+            context.encapsulate_into_with_provider(provider, encoded_config, encoded_msg)
+        })
+    });
+    let encapsulated = catch_panics!(encapsulate(), return -1);
+    safe_unwrap!(encapsulated.and_then(identity), -1, identity);
+    0
+}
+
+/// Shared argument handling of the `encapsulate_request*_ffi` functions.
+unsafe fn encapsulate_request_with<F>(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+    encapsulate: F,
+) -> *mut RequestContext
+where
+    F: FnOnce(&[u8], &[u8]) -> Result<RequestContext, ClientError> + UnwindSafe,
+{
     let encoded_config_ptr =
         null_safe_ptr!(encoded_config_ptr, ptr::null_mut(), encoded_config_ptr);
     let encoded_msg_ptr = null_safe_ptr!(encoded_msg_ptr, ptr::null_mut(), encoded_msg_ptr);
@@ -207,23 +1392,12 @@ pub unsafe extern "C" fn encapsulate_request_ffi(
 
     catch_panics!(
         {
-            let client = safe_unwrap!(
-                { ClientRequest::new(encoded_config) },
+            let ctx = safe_unwrap!(
+                encapsulate(encoded_config, encoded_msg),
                 ptr::null_mut(),
-                ClientError::RequestContextInitialization
+                identity
             );
-
-            let (enc_request, client_response) = safe_unwrap!(
-                client.encapsulate(encoded_msg),
-                ptr::null_mut(),
-                ClientError::EncapsulationFailed
-            );
-
-            let ctx = Box::new(RequestContext {
-                encapsulated_request: enc_request,
-                response_context: client_response,
-            });
-            Box::into_raw(ctx)
+            handles::register(ctx)
         },
         ptr::null_mut()
     )
@@ -232,6 +1406,10 @@ pub unsafe extern "C" fn encapsulate_request_ffi(
 /// Decapsulates the provided `encapsulated_response` using `context`.
 ///
 /// This function will return a NULL pointer if decapsulation fails.
+/// An empty response fails with `ResponseEmpty` before `context` is consumed,
+/// so the caller may retry the exchange with it or has to free it.
+/// Contexts created by [`request_context_new_ffi`] are emptied instead of freed,
+/// ready for [`encapsulate_into_context_ffi`].
 ///
 /// # Safety
 /// Dereferences a pointer to `RequestContext` passed by the caller.
@@ -244,11 +1422,10 @@ pub unsafe extern "C" fn decapsulate_response_ffi(
     encapsulated_response_ptr: *const u8,
     encapsulated_response_len: libc::size_t,
 ) -> *mut ResponseContext {
-    let context = null_safe_ptr!(
-        context,
-        null_mut(),
-        Box::from_raw(context)
-    );
+    if encapsulated_response_len == 0 {
+        update_last_error(ClientError::ResponseEmpty);
+        return null_mut();
+    }
 
     let encapsulated_response_ptr = null_safe_ptr!(
         encapsulated_response_ptr,
@@ -256,6 +1433,9 @@ pub unsafe extern "C" fn decapsulate_response_ffi(
         encapsulated_response_ptr
     );
 
+    null_safe_ptr!(context, null_mut(), ());
+    let decapsulator = safe_unwrap!(handles::take_decapsulator(context), null_mut(), identity);
+
     let encapsulated_response: &[u8] = slice::from_raw_parts_mut(
         encapsulated_response_ptr as *mut u8,
         encapsulated_response_len as usize,
@@ -264,12 +1444,200 @@ pub unsafe extern "C" fn decapsulate_response_ffi(
     catch_panics!(
         {
             let response = safe_unwrap!(
-                context.response_context.decapsulate(encapsulated_response),
+                decapsulator.decapsulate(encapsulated_response),
                 ptr::null_mut(),
-                ClientError::DecapsulationFailed
+                identity
             );
-            Box::into_raw(Box::new(ResponseContext { response }))
+            jitter::delay_response();
+            Box::into_raw(Box::new(ResponseContext::new(response)))
         },
         ptr::null_mut()
     )
 }
+
+/// The relay's response body is the encapsulated response itself.
+pub const RESPONSE_FRAMING_NONE: libc::c_int = 0;
+/// The encapsulated response is prefixed by its length as a network-order 16-bit integer.
+pub const RESPONSE_FRAMING_U16: libc::c_int = 1;
+/// The encapsulated response is prefixed by its length as a network-order 32-bit integer.
+pub const RESPONSE_FRAMING_U32: libc::c_int = 2;
+
+/// Strips the length prefix `framing` adds in front of the encapsulated response, checking
+/// that it declares exactly the remaining length.
+pub fn strip_response_framing(framed: &[u8], framing: libc::c_int) -> Result<&[u8], ClientError> {
+    let prefix_len = match framing {
+        RESPONSE_FRAMING_NONE => return Ok(framed),
+        RESPONSE_FRAMING_U16 => 2,
+        RESPONSE_FRAMING_U32 => 4,
+        _ => {
+            return Err(ClientError::InvalidArgument(format!(
+                "unknown response framing {}",
+                framing
+            )))
+        }
+    };
+    if framed.len() < prefix_len {
+        return Err(ClientError::ResponseTruncated {
+            expected: prefix_len,
+            actual: framed.len(),
+        });
+    }
+    let (prefix, response) = framed.split_at(prefix_len);
+    let declared = prefix
+        .iter()
+        .fold(0usize, |acc, b| (acc << 8) | usize::from(*b));
+    if declared != response.len() {
+        return Err(ClientError::ResponseFramingMismatch {
+            declared,
+            actual: response.len(),
+        });
+    }
+    Ok(response)
+}
+
+/// Decapsulates a response some relays send with a length prefix, see [`RESPONSE_FRAMING_U16`]
+/// and [`RESPONSE_FRAMING_U32`]. [`RESPONSE_FRAMING_NONE`] behaves like [`decapsulate_response_ffi`].
+///
+/// This function will return a NULL pointer if the framing is unknown or does not match the
+/// response, before `context` is consumed, or if decapsulation fails.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn decapsulate_response_framed_ffi(
+    context: *mut RequestContext,
+    framed_ptr: *const u8,
+    framed_len: libc::size_t,
+    framing: libc::c_int,
+) -> *mut ResponseContext {
+    let framed_ptr = null_safe_ptr!(framed_ptr, ptr::null_mut(), framed_ptr);
+    let framed = slice::from_raw_parts(framed_ptr, framed_len);
+    let response = safe_unwrap!(
+        strip_response_framing(framed, framing),
+        ptr::null_mut(),
+        identity
+    );
+    decapsulate_response_ffi(context, response.as_ptr(), response.len())
+}
+
+/// Largest chunk `decapsulate_response_cb_ffi` passes to its callback at once.
+pub const RESPONSE_CHUNK_LEN: usize = 16384;
+
+/// Receives the decapsulated response of `decapsulate_response_cb_ffi` chunk by chunk,
+/// `user` being the pointer passed along. Returns `false` to abort.
+pub type ResponseChunkCallback =
+    extern "C" fn(chunk_ptr: *const u8, chunk_len: libc::size_t, user: *mut c_void) -> bool;
+
+/// Decapsulates the provided `encapsulated_response` like [`decapsulate_response_ffi`], but
+/// hands the response to `callback` in chunks of at most [`RESPONSE_CHUNK_LEN`] bytes
+/// instead of returning a `ResponseContext`.
+///
+/// The response is authenticated as a whole before the first chunk is passed, so the
+/// callback never sees unauthenticated bytes; an empty response produces no call. The
+/// chunks are borrowed for the duration of the call only.
+///
+/// Returns 0 once every chunk was passed, or -1 if decapsulation fails or `callback`
+/// returns `false`, which stops further calls and fails with `CallbackAborted`. `context`
+/// is consumed either way, except for an empty `encapsulated_response` which fails with
+/// `ResponseEmpty` first. The plaintext is freed before returning.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn decapsulate_response_cb_ffi(
+    context: *mut RequestContext,
+    encapsulated_response_ptr: *const u8,
+    encapsulated_response_len: libc::size_t,
+    callback: Option<ResponseChunkCallback>,
+    user: *mut c_void,
+) -> libc::c_int {
+    if encapsulated_response_len == 0 {
+        update_last_error(ClientError::ResponseEmpty);
+        return -1;
+    }
+    let encapsulated_response_ptr =
+        null_safe_ptr!(encapsulated_response_ptr, -1, encapsulated_response_ptr);
+    let callback = match callback {
+        Some(callback) => callback,
+        None => {
+            update_last_error(ClientError::InvalidArgument(
+                "Passed null pointer argument callback".to_owned(),
+            ));
+            return -1;
+        }
+    };
+
+    null_safe_ptr!(context, -1, ());
+    let decapsulator = safe_unwrap!(handles::take_decapsulator(context), -1, identity);
+    let encapsulated_response =
+        slice::from_raw_parts(encapsulated_response_ptr, encapsulated_response_len);
+
+    let response = catch_panics!(decapsulator.decapsulate(encapsulated_response), return -1);
+    let response = safe_unwrap!(response, -1, identity);
+    jitter::delay_response();
+    for chunk in response.chunks(RESPONSE_CHUNK_LEN) {
+        if !callback(chunk.as_ptr(), chunk.len(), user) {
+            update_last_error(ClientError::CallbackAborted);
+            return -1;
+        }
+    }
+    0
+}
+
+static BACKEND_INIT: Once = Once::new();
+
+/// Initializes the crypto backend of `ohttp` once per process.
+///
+/// The rust-hpke backend this library is built with needs no initialization, so calling the
+/// other functions first is allowed and this only returns 0. Backends such as NSS require it
+/// to run before any other function, embedders should call it at startup to stay portable.
+/// Calling it again, from any thread, does nothing and returns 0.
+#[no_mangle]
+pub extern "C" fn apprelay_init_ffi() -> libc::c_int {
+    BACKEND_INIT.call_once(|| {
+        // rust-hpke keeps no process state, backends that do initialize it here.
+    });
+    0
+}
+
+static WARMED_UP: AtomicBool = AtomicBool::new(false);
+
+/// Encapsulates an empty message to a throwaway configuration, so the first real request
+/// does not pay for opening the system random number generator and faulting in the X25519
+/// and AEAD code.
+///
+/// Returns 0 once a warmup succeeded, later calls return 0 right away. Returns -1 if the
+/// throwaway encapsulation fails, e.g. with `RngUnavailable`, and tries again on the next
+/// call. Concurrent first calls may each encapsulate once, which is harmless.
+#[no_mangle]
+pub extern "C" fn apprelay_warmup_ffi() -> libc::c_int {
+    if WARMED_UP.load(Ordering::Acquire) {
+        return 0;
+    }
+    let warmup = catch_panics!(
+        {
+            let symmetric = vec![config::SymmetricSuite {
+                kdf: config::KDF_HKDF_SHA256,
+                aead: config::AEAD_AES_128_GCM,
+            }];
+            KeyConfig::new(0, config::KEM_X25519_SHA256, vec![9; 32], symmetric)
+                .and_then(|config| RequestContext::new(&config.encode(), &[]))
+        },
+        return -1
+    );
+    safe_unwrap!(warmup, -1, identity);
+    WARMED_UP.store(true, Ordering::Release);
+    0
+}
+
+/// Tears down the crypto backend at process exit, a no-op with the rust-hpke backend.
+///
+/// No other function may be called afterwards on backends that require [`apprelay_init_ffi`].
+#[no_mangle]
+pub extern "C" fn apprelay_shutdown_ffi() {}
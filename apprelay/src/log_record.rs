@@ -0,0 +1,77 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! One JSON record per request for logging pipelines, so embedders log the same fields.
+//!
+//! Only built with the `serde` feature, which pulls in `serde_json`. The record holds the
+//! routing header and sizes of the request and its fingerprint, no key material or
+//! plaintext.
+
+use std::convert::identity;
+
+use serde_json::json;
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{handles, safe_unwrap, write_out_buffer, ClientError, RequestContext};
+
+/// The log record of `context` as a single line of JSON:
+///
+/// ```text
+/// {"key_id":1,"kem":32,"kdf":1,"aead":1,"enc_len":32,"encapsulated_len":87,"fingerprint":"5f0c…"}
+/// ```
+///
+/// Suite ids are numbers, so ids unknown to this build are logged like any other.
+/// `fingerprint` is the lowercase hex of [`RequestContext::fingerprint`].
+///
+/// Fails with [`ClientError::ContextEmpty`] if the context holds no request.
+pub fn log_record(context: &RequestContext) -> Result<String, ClientError> {
+    let suite = context.suite().ok_or(ClientError::ContextEmpty)?;
+    let key_id = context.key_id().ok_or(ClientError::ContextEmpty)?;
+    let enc_len = context.enc().map_or(0, <[u8]>::len);
+    let fingerprint: String = context
+        .fingerprint()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(json!({
+        "key_id": key_id,
+        "kem": suite.kem,
+        "kdf": suite.kdf,
+        "aead": suite.aead,
+        "enc_len": enc_len,
+        "encapsulated_len": context.encapsulated_request().len(),
+        "fingerprint": fingerprint,
+    })
+    .to_string())
+}
+
+/// Writes the NUL terminated JSON log record of `context` into `out`, see [`log_record`].
+///
+/// Returns the length of the record without the NUL terminator, or -1 if the context is
+/// not live, holds no request or `out` is too small.
+///
+/// # Safety
+/// Dereferences a pointer to `RequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn request_context_log_record_ffi(
+    context: *const RequestContext,
+    out: *mut libc::c_char,
+    out_cap: libc::size_t,
+) -> libc::ssize_t {
+    let record = safe_unwrap!(
+        handles::with(context, log_record).and_then(identity),
+        -1,
+        identity
+    );
+    let mut bytes = record.into_bytes();
+    bytes.push(0);
+    match write_out_buffer(&bytes, out as *mut u8, out_cap) {
+        -1 => -1,
+        written => written - 1,
+    }
+}
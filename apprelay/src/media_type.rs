@@ -0,0 +1,241 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Media types of the relay exchange for each encapsulation mode, the check telling a
+//! gateway response apart from an error of the relay, and a guess at what a blob of bytes is.
+
+use std::{ptr, slice};
+
+use crate::config::{self, REQUEST_HEADER_LEN};
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{bhttp, cstr_to_str, null_safe_ptr, ClientError};
+
+/// Requests encapsulated in one shot, `message/ohttp-req` and `message/ohttp-res`.
+pub const OHTTP_MODE_ONE_SHOT: libc::c_int = 0;
+/// Chunked requests, `message/ohttp-chunked-req` and `message/ohttp-chunked-res`.
+///
+/// This library does not produce chunked requests yet, see the README.
+pub const OHTTP_MODE_CHUNKED: libc::c_int = 1;
+
+/// Content types of the request to POST to the relay and of the response it returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MediaTypes {
+    pub request: &'static str,
+    pub response: &'static str,
+}
+
+/// [`classify_message_ffi`] found neither structure.
+pub const MESSAGE_UNKNOWN: libc::c_int = 0;
+/// [`classify_message_ffi`] found the header of an encapsulated OHTTP request.
+pub const MESSAGE_ENCAPSULATED_REQUEST: libc::c_int = 1;
+/// [`classify_message_ffi`] found the start of a BHTTP request or response.
+pub const MESSAGE_BHTTP: libc::c_int = 2;
+
+const ONE_SHOT: MediaTypes = MediaTypes {
+    request: "message/ohttp-req",
+    response: "message/ohttp-res",
+};
+
+const CHUNKED: MediaTypes = MediaTypes {
+    request: "message/ohttp-chunked-req",
+    response: "message/ohttp-chunked-res",
+};
+
+/// Media type of a `Content-Type` header value of the relay's response, see
+/// [`parse_media_type`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OhttpMediaType {
+    /// `message/ohttp-res`, a response to decapsulate.
+    Response,
+    /// `message/ohttp-chunked-res`, which this library can not decapsulate yet.
+    ChunkedResponse,
+    /// Anything else, e.g. an error page of the relay, lowercased and without parameters.
+    Other(String),
+}
+
+impl MediaTypes {
+    pub fn for_mode(mode: libc::c_int) -> Result<Self, ClientError> {
+        match mode {
+            OHTTP_MODE_ONE_SHOT => Ok(ONE_SHOT),
+            OHTTP_MODE_CHUNKED => Ok(CHUNKED),
+            _ => Err(ClientError::InvalidArgument(format!(
+                "unknown encapsulation mode {}",
+                mode
+            ))),
+        }
+    }
+
+    /// Whether a `Content-Type` header value names the response media type.
+    ///
+    /// The comparison is case-insensitive and ignores parameters and surrounding whitespace.
+    pub fn is_response(&self, header_value: &str) -> bool {
+        header_value
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .eq_ignore_ascii_case(self.response)
+    }
+}
+
+/// Whether the relay's response carries an encapsulated response worth decapsulating: a
+/// status of 200 and a `message/ohttp-res` content type, `content_type` being `None` if
+/// the header is absent.
+///
+/// Relays report their own failures, e.g. a 502 when the gateway is unreachable, with a
+/// plain body. Decapsulating it would fail authentication and pass for tampering.
+pub fn should_decapsulate(outer_status: u16, content_type: Option<&str>) -> bool {
+    outer_status == 200 && content_type.map_or(false, |value| ONE_SHOT.is_response(value))
+}
+
+/// Parses the `Content-Type` header value of the relay's response, so Rust clients check
+/// what they received before decapsulating it.
+///
+/// Like [`MediaTypes::is_response`] the comparison is case-insensitive and ignores
+/// parameters and surrounding whitespace.
+pub fn parse_media_type(header_value: &str) -> OhttpMediaType {
+    if ONE_SHOT.is_response(header_value) {
+        OhttpMediaType::Response
+    } else if CHUNKED.is_response(header_value) {
+        OhttpMediaType::ChunkedResponse
+    } else {
+        let essence = header_value.split(';').next().unwrap_or_default();
+        OhttpMediaType::Other(essence.trim().to_ascii_lowercase())
+    }
+}
+
+/// Returns the NUL terminated content type to POST requests encapsulated in `mode` with,
+/// [`OHTTP_MODE_ONE_SHOT`] or [`OHTTP_MODE_CHUNKED`].
+///
+/// The string is static and must not be freed. Returns a NULL pointer for an unknown mode.
+#[no_mangle]
+pub extern "C" fn expected_content_type_for_mode_ffi(mode: libc::c_int) -> *const libc::c_char {
+    match mode {
+        OHTTP_MODE_ONE_SHOT => b"message/ohttp-req\0".as_ptr() as *const libc::c_char,
+        OHTTP_MODE_CHUNKED => b"message/ohttp-chunked-req\0".as_ptr() as *const libc::c_char,
+        _ => {
+            if let Err(err) = MediaTypes::for_mode(mode) {
+                update_last_error(err);
+            }
+            ptr::null()
+        }
+    }
+}
+
+/// Checks that the `Content-Type` header value of the relay's response matches the response
+/// media type of `mode`, before decapsulating it.
+///
+/// Returns false on a mismatch, and also if `header_value` is NULL or not valid UTF-8 or the
+/// mode is unknown, in which case the last error is set.
+///
+/// # Safety
+/// The invariants are described here [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety)
+#[no_mangle]
+pub unsafe extern "C" fn check_response_content_type_ffi(
+    header_value: *const libc::c_char,
+    mode: libc::c_int,
+) -> bool {
+    let checked = cstr_to_str(header_value, "header_value").and_then(|header_value| {
+        MediaTypes::for_mode(mode).map(|types| types.is_response(header_value))
+    });
+    match checked {
+        Ok(matches) => matches,
+        Err(err) => {
+            update_last_error(err);
+            false
+        }
+    }
+}
+
+/// Returns 1 if the relay's response with status `outer_status` and `Content-Type` header
+/// value `content_type` carries an encapsulated response, 0 if it is an error of the relay
+/// or gateway to surface as such instead of decapsulating it. Pass NULL for `content_type`
+/// if the header is absent.
+///
+/// Returns -1 and sets the last error if `content_type` is not valid UTF-8.
+///
+/// # Safety
+/// The invariants are described here [`CStr::from_ptr`](std::ffi::CStr::from_ptr#safety)
+#[no_mangle]
+pub unsafe extern "C" fn should_decapsulate_ffi(
+    outer_status: u16,
+    content_type: *const libc::c_char,
+) -> libc::c_int {
+    let content_type = if content_type.is_null() {
+        None
+    } else {
+        match cstr_to_str(content_type, "content_type") {
+            Ok(content_type) => Some(content_type),
+            Err(err) => {
+                update_last_error(err);
+                return -1;
+            }
+        }
+    };
+    should_decapsulate(outer_status, content_type).into()
+}
+
+/// What a blob of bytes looks like, see [`classify_message`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind {
+    EncapsulatedRequest,
+    Bhttp,
+    Unknown,
+}
+
+/// Guesses whether `data` is an encapsulated OHTTP request or a plain BHTTP message, for
+/// debugging and proxy logic.
+///
+/// An encapsulated request needs a header naming a registered KEM, KDF and sealing AEAD and
+/// enough bytes for the encapsulated key and an AEAD tag. A BHTTP message needs a framing
+/// indicator followed by a method or a status code. Both are a best-effort guess from the
+/// first bytes and not a security boundary: the ciphertext is not checked, so arbitrary bytes
+/// can pass for an encapsulated request.
+pub fn classify_message(data: &[u8]) -> MessageKind {
+    if looks_like_encapsulated_request(data) {
+        MessageKind::EncapsulatedRequest
+    } else if bhttp::looks_like_bhttp(data) {
+        MessageKind::Bhttp
+    } else {
+        MessageKind::Unknown
+    }
+}
+
+fn looks_like_encapsulated_request(data: &[u8]) -> bool {
+    let header = match data.get(..REQUEST_HEADER_LEN) {
+        Some(header) => header,
+        None => return false,
+    };
+    let id = |offset: usize| u16::from_be_bytes([header[offset], header[offset + 1]]);
+    let (kem, kdf, aead) = (id(1), id(3), id(5));
+    match (
+        config::kem_enc_len(kem),
+        config::kdf_name(kdf),
+        config::aead_params(aead),
+    ) {
+        (Some(enc_len), Some(_), Some(aead)) => {
+            data.len() >= REQUEST_HEADER_LEN + enc_len + aead.tag_len
+        }
+        _ => false,
+    }
+}
+
+/// Returns [`MESSAGE_ENCAPSULATED_REQUEST`], [`MESSAGE_BHTTP`] or [`MESSAGE_UNKNOWN`] for the
+/// `len` bytes at `ptr`, see [`classify_message`]. The result is a best-effort guess, not a
+/// security boundary.
+///
+/// Returns -1 if `ptr` is NULL.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn classify_message_ffi(ptr: *const u8, len: libc::size_t) -> libc::c_int {
+    let ptr = null_safe_ptr!(ptr, -1, ptr);
+    match classify_message(slice::from_raw_parts(ptr, len)) {
+        MessageKind::EncapsulatedRequest => MESSAGE_ENCAPSULATED_REQUEST,
+        MessageKind::Bhttp => MESSAGE_BHTTP,
+        MessageKind::Unknown => MESSAGE_UNKNOWN,
+    }
+}
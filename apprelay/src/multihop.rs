@@ -0,0 +1,164 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Double encapsulation for architectures chaining two relays.
+//!
+//! The BHTTP message is first encapsulated for the gateway with the inner configuration.
+//! The resulting `message/ohttp-req` is then encapsulated again with the outer configuration
+//! of the first relay, which decapsulates it and forwards the inner `message/ohttp-req` to
+//! the gateway. On the way back the first relay encapsulates the gateway's
+//! `message/ohttp-res` with its outer response context, so the client peels the outer
+//! layer first and the inner layer second.
+
+use std::panic::catch_unwind;
+use std::{ptr, slice};
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{
+    catch_panics, jitter, null_safe_ptr, safe_unwrap, BytesView, ClientError, RequestContext,
+    ResponseContext, ResponseDecapsulator,
+};
+
+pub struct DoubleRequestContext {
+    outer: RequestContext,
+    inner: ResponseDecapsulator,
+}
+
+impl DoubleRequestContext {
+    /// Encapsulates `msg` with `inner_config` and the result with `outer_config`.
+    pub fn new(inner_config: &[u8], outer_config: &[u8], msg: &[u8]) -> Result<Self, ClientError> {
+        let (inner_request, inner) = RequestContext::new(inner_config, msg)?.into_parts()?;
+        let outer = RequestContext::new(outer_config, &inner_request)?;
+        Ok(DoubleRequestContext { outer, inner })
+    }
+
+    /// The doubly encapsulated request that should be sent to the first relay.
+    pub fn encapsulated_request(&self) -> &[u8] {
+        self.outer.encapsulated_request()
+    }
+
+    /// Peels the outer and then the inner layer of the first relay's response.
+    pub fn decapsulate(self, encapsulated_response: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let (_, outer) = self.outer.into_parts()?;
+        let inner_response = outer.decapsulate(encapsulated_response)?;
+        self.inner.decapsulate(&inner_response)
+    }
+}
+
+/// Encapsulates the provided `encoded_msg` for the gateway using `inner_config`, and the
+/// resulting encapsulated request for the first relay using `outer_config`.
+///
+/// This function will return a NULL pointer if either configuration is invalid or
+/// encapsulation fails.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_double_ffi(
+    inner_config_ptr: *const u8,
+    inner_config_len: libc::size_t,
+    outer_config_ptr: *const u8,
+    outer_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+) -> *mut DoubleRequestContext {
+    let inner_config_ptr = null_safe_ptr!(inner_config_ptr, ptr::null_mut(), inner_config_ptr);
+    let outer_config_ptr = null_safe_ptr!(outer_config_ptr, ptr::null_mut(), outer_config_ptr);
+    let encoded_msg_ptr = null_safe_ptr!(encoded_msg_ptr, ptr::null_mut(), encoded_msg_ptr);
+
+    let inner_config = slice::from_raw_parts(inner_config_ptr, inner_config_len);
+    let outer_config = slice::from_raw_parts(outer_config_ptr, outer_config_len);
+    let encoded_msg = slice::from_raw_parts(encoded_msg_ptr, encoded_msg_len);
+
+    catch_panics!(
+        {
+            let ctx = safe_unwrap!(
+                DoubleRequestContext::new(inner_config, outer_config, encoded_msg),
+                ptr::null_mut(),
+                std::convert::identity
+            );
+            Box::into_raw(Box::new(ctx))
+        },
+        ptr::null_mut()
+    )
+}
+
+/// Return the doubly encapsulated request to send to the first relay.
+///
+/// The view borrows the context and is valid until the context is consumed or dropped.
+///
+/// # Safety
+/// Dereferences a pointer to `DoubleRequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn double_request_context_message_ffi(
+    context: *const DoubleRequestContext,
+) -> BytesView {
+    null_safe_ptr!(
+        context,
+        BytesView::empty(),
+        BytesView::from_slice((*context).encapsulated_request())
+    )
+}
+
+/// Decapsulates both layers of the first relay's response, consuming `context`.
+///
+/// This function will return a NULL pointer if decapsulating either layer fails.
+/// An empty or NULL response fails before `context` is consumed, the former with `ResponseEmpty`.
+///
+/// # Safety
+/// Dereferences a pointer to `DoubleRequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn decapsulate_double_response_ffi(
+    context: *mut DoubleRequestContext,
+    encapsulated_response_ptr: *const u8,
+    encapsulated_response_len: libc::size_t,
+) -> *mut ResponseContext {
+    if encapsulated_response_len == 0 {
+        update_last_error(ClientError::ResponseEmpty);
+        return ptr::null_mut();
+    }
+    // The response is checked first, so a NULL response leaves the context to the caller.
+    let encapsulated_response_ptr = null_safe_ptr!(
+        encapsulated_response_ptr,
+        ptr::null_mut(),
+        encapsulated_response_ptr
+    );
+    let context = null_safe_ptr!(context, ptr::null_mut(), Box::from_raw(context));
+    let encapsulated_response =
+        slice::from_raw_parts(encapsulated_response_ptr, encapsulated_response_len);
+
+    catch_panics!(
+        {
+            let response = safe_unwrap!(
+                context.decapsulate(encapsulated_response),
+                ptr::null_mut(),
+                std::convert::identity
+            );
+            jitter::delay_response();
+            Box::into_raw(Box::new(ResponseContext::new(response)))
+        },
+        ptr::null_mut()
+    )
+}
+
+/// Frees up a double request context that is not going to be decapsulated.
+///
+/// # Safety
+/// Dereferences a pointer to `DoubleRequestContext` passed by the caller.
+/// Be sure that the context has not been yet freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn double_request_context_drop_ffi(context: *mut DoubleRequestContext) {
+    null_safe_ptr!(context, (), {
+        let _context = Box::from_raw(context);
+    })
+}
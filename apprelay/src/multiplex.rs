@@ -0,0 +1,238 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Responses to several requests that a relay multiplexes into a single body.
+//!
+//! Each encapsulated response is framed like a lone one with
+//! [`RESPONSE_FRAMING_U32`](crate::RESPONSE_FRAMING_U32): its length as a network-order
+//! 32-bit integer, then the response. The frames follow each other in the order of the
+//! requests. Every request was sealed under its own HPKE context, so the caller passes the
+//! `RequestContext` of each request and response `i` is opened with context `i`.
+
+use std::convert::identity;
+use std::panic::catch_unwind;
+use std::ptr::{self, null_mut};
+use std::slice;
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{
+    handles, jitter, null_safe_ptr, safe_unwrap, ClientError, RequestContext, ResponseContext,
+};
+
+/// Length of the prefix in front of every multiplexed response.
+const FRAME_PREFIX_LEN: usize = 4;
+
+/// A final frame cut short, e.g. because the relay is still sending it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TruncatedFrame {
+    /// Bytes the prefix declares, or the length of the prefix if it is cut short itself.
+    pub expected: usize,
+    /// Bytes present.
+    pub actual: usize,
+}
+
+impl From<TruncatedFrame> for ClientError {
+    fn from(truncated: TruncatedFrame) -> Self {
+        ClientError::ResponseTruncated {
+            expected: truncated.expected,
+            actual: truncated.actual,
+        }
+    }
+}
+
+/// Splits a multiplexed body into the encapsulated responses it frames.
+///
+/// A truncated final frame is not returned with the complete ones but described separately.
+pub fn split_multiplexed(body: &[u8]) -> (Vec<&[u8]>, Option<TruncatedFrame>) {
+    let mut frames = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        if rest.len() < FRAME_PREFIX_LEN {
+            let truncated = TruncatedFrame {
+                expected: FRAME_PREFIX_LEN,
+                actual: rest.len(),
+            };
+            return (frames, Some(truncated));
+        }
+        let (prefix, tail) = rest.split_at(FRAME_PREFIX_LEN);
+        let declared = prefix
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | usize::from(*b));
+        if tail.len() < declared {
+            let truncated = TruncatedFrame {
+                expected: declared,
+                actual: tail.len(),
+            };
+            return (frames, Some(truncated));
+        }
+        let (frame, tail) = tail.split_at(declared);
+        frames.push(frame);
+        rest = tail;
+    }
+    (frames, None)
+}
+
+/// Decapsulated responses returned by [`decapsulate_multiplexed_ffi`], one entry per context
+/// passed, holding either the response or the reason it is missing.
+pub struct ResponseContextArray {
+    responses: Vec<Result<ResponseContext, ClientError>>,
+}
+
+impl ResponseContextArray {
+    /// The entries in the order of the contexts passed.
+    pub fn responses(&self) -> &[Result<ResponseContext, ClientError>] {
+        &self.responses
+    }
+}
+
+/// Decapsulates the responses a relay multiplexed into `body`, framed as described in the
+/// [module documentation](self), opening response `i` with `contexts[i]`.
+///
+/// Contexts are consumed like by [`decapsulate_response_ffi`](crate::decapsulate_response_ffi)
+/// when their response is present, whether it decapsulates or not. A context whose response
+/// is missing, because the body holds fewer responses or its final one is truncated, is left
+/// untouched for the caller to decapsulate once the rest arrives or to free. The array
+/// records the outcome per context, see [`response_context_array_error_ffi`].
+///
+/// This function will return a NULL pointer, before any context is consumed, if `body` holds
+/// more responses than `contexts_len`.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety),
+/// `contexts` must point to `contexts_len` contexts that have not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn decapsulate_multiplexed_ffi(
+    contexts: *const *mut RequestContext,
+    contexts_len: libc::size_t,
+    body_ptr: *const u8,
+    body_len: libc::size_t,
+) -> *mut ResponseContextArray {
+    let contexts = null_safe_ptr!(contexts, ptr::null_mut(), contexts);
+    let body_ptr = null_safe_ptr!(body_ptr, ptr::null_mut(), body_ptr);
+    let contexts = slice::from_raw_parts(contexts, contexts_len);
+    let body = slice::from_raw_parts(body_ptr, body_len);
+
+    let (frames, truncated) = split_multiplexed(body);
+    if frames.len() > contexts.len() {
+        update_last_error(ClientError::InvalidArgument(format!(
+            "{} multiplexed responses for {} contexts",
+            frames.len(),
+            contexts.len()
+        )));
+        return null_mut();
+    }
+
+    let responses = contexts
+        .iter()
+        .enumerate()
+        .map(|(index, &context)| {
+            let frame = match frames.get(index) {
+                Some(frame) => *frame,
+                None => match truncated {
+                    Some(truncated) if index == frames.len() => return Err(truncated.into()),
+                    _ => {
+                        return Err(ClientError::InvalidArgument(format!(
+                            "no response for context {index}"
+                        )))
+                    }
+                },
+            };
+            let decapsulator = handles::take_decapsulator(context)?;
+            catch_unwind(|| decapsulator.decapsulate(frame))
+                .unwrap_or_else(|err| Err(ClientError::SafePanic(err)))
+                .map(ResponseContext::new)
+        })
+        .collect();
+    jitter::delay_response();
+    Box::into_raw(Box::new(ResponseContextArray { responses }))
+}
+
+/// Returns the number of entries of `array`, the number of contexts passed to
+/// [`decapsulate_multiplexed_ffi`].
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+/// Be sure that the array has not been yet freed and that you are using a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn response_context_array_len_ffi(
+    array: *const ResponseContextArray,
+) -> libc::size_t {
+    null_safe_ptr!(array, 0, (*array).responses.len())
+}
+
+/// Returns the error code of entry `index`, 0 if its response was decapsulated.
+///
+/// An entry whose response is missing from the body reports `ResponseTruncated` if the final
+/// response was cut short and `InvalidArgument` otherwise, its context was not consumed.
+/// Returns -1 if `index` is out of range.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+/// Be sure that the array has not been yet freed and that you are using a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn response_context_array_error_ffi(
+    array: *const ResponseContextArray,
+    index: libc::size_t,
+) -> libc::c_int {
+    let array = null_safe_ptr!(array, -1, &*array);
+    match safe_unwrap!(entry(array, index), -1, identity) {
+        Ok(_) => 0,
+        Err(err) => err.kind() as libc::c_int,
+    }
+}
+
+/// Returns the response of entry `index`, borrowed from `array`: read it with the
+/// `response_context_*` accessors but never free it with `response_context_drop_ffi`.
+///
+/// This function will return a NULL pointer if `index` is out of range or the entry holds
+/// no response, see [`response_context_array_error_ffi`].
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+/// Be sure that the array has not been yet freed and that you are using a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn response_context_array_get_ffi(
+    array: *mut ResponseContextArray,
+    index: libc::size_t,
+) -> *mut ResponseContext {
+    let array = null_safe_ptr!(array, ptr::null_mut(), &mut *array);
+    if let Err(err) = entry(array, index) {
+        update_last_error(err);
+        return null_mut();
+    }
+    match &mut array.responses[index] {
+        Ok(response) => response as *mut ResponseContext,
+        Err(err) => {
+            update_last_error(ClientError::InvalidArgument(format!(
+                "response {index} failed: {err}"
+            )));
+            null_mut()
+        }
+    }
+}
+
+/// Frees `array` along with the responses it holds.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseContextArray` passed by the caller.
+/// Be sure that the array has not been yet freed and that you are using a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn response_context_array_drop_ffi(array: *mut ResponseContextArray) {
+    null_safe_ptr!(array, (), {
+        let _array = Box::from_raw(array);
+    })
+}
+
+fn entry(
+    array: &ResponseContextArray,
+    index: usize,
+) -> Result<&Result<ResponseContext, ClientError>, ClientError> {
+    array.responses.get(index).ok_or_else(|| {
+        ClientError::InvalidArgument(format!(
+            "index {index} out of range for {} responses",
+            array.responses.len()
+        ))
+    })
+}
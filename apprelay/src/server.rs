@@ -0,0 +1,144 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Gateway side of the exchange, for testing clients locally.
+
+#[cfg(feature = "testutil")]
+use std::{convert::identity, slice};
+
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, Server, SymmetricSuite};
+
+use crate::config::{
+    HpkeSuite, AEAD_AES_128_GCM, AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+#[cfg(feature = "testutil")]
+use crate::error_ffi::update_last_error;
+#[cfg(all(feature = "testutil", target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::ClientError;
+#[cfg(feature = "testutil")]
+use crate::{null_safe_ptr, safe_unwrap};
+
+/// Input keying material the key pair of [`crate::dev::DEV_KEY_CONFIG`] is derived
+/// from using the HPKE `DeriveKeyPair` function of DHKEM(X25519, HKDF-SHA256).
+#[cfg(feature = "dev-config")]
+const DEV_KEY_IKM: &[u8; 32] = b"apprelay development gateway key";
+
+/// In-process gateway decapsulating requests and encapsulating responses.
+pub struct TestServer {
+    server: Server,
+}
+
+impl TestServer {
+    pub fn new(config: KeyConfig) -> Result<Self, ohttp::Error> {
+        Ok(TestServer {
+            server: Server::new(config)?,
+        })
+    }
+
+    /// Gateway holding the private key of [`crate::dev::DEV_KEY_CONFIG`].
+    #[cfg(feature = "dev-config")]
+    pub fn dev() -> Result<Self, ohttp::Error> {
+        let config = KeyConfig::derive(
+            crate::dev::DEV_KEY_ID,
+            Kem::X25519Sha256,
+            vec![
+                SymmetricSuite::new(Kdf::HkdfSha256, Aead::Aes128Gcm),
+                SymmetricSuite::new(Kdf::HkdfSha256, Aead::ChaCha20Poly1305),
+            ],
+            DEV_KEY_IKM,
+        )?;
+        Self::new(config)
+    }
+
+    /// Gateway holding the key pair derived from `seed`, e.g. the private key returned by
+    /// `generate_test_keypair_ffi`, for `key_id` and `suite`.
+    pub fn from_seed(key_id: u8, suite: HpkeSuite, seed: &[u8]) -> Result<Self, ClientError> {
+        let config = derive_config(key_id, suite, seed)?;
+        Self::new(config).map_err(ClientError::Ohttp)
+    }
+
+    /// Decapsulates `encapsulated_request` and encapsulates the response produced by `respond`.
+    pub fn handle(
+        &mut self,
+        encapsulated_request: &[u8],
+        respond: impl FnOnce(&[u8]) -> Vec<u8>,
+    ) -> Result<Vec<u8>, ohttp::Error> {
+        let (request, server_response) = self.server.decapsulate(encapsulated_request)?;
+        server_response.encapsulate(&respond(&request))
+    }
+}
+
+/// Length of the private key returned by `generate_test_keypair_ffi`.
+pub const TEST_PRIVATE_KEY_LEN: usize = 32;
+
+/// Derives the key pair of `suite` from `seed` with the HPKE `DeriveKeyPair` function.
+fn derive_config(key_id: u8, suite: HpkeSuite, seed: &[u8]) -> Result<KeyConfig, ClientError> {
+    let kem = match suite.kem {
+        KEM_X25519_SHA256 => Kem::X25519Sha256,
+        _ => return Err(ClientError::UnsupportedSuite),
+    };
+    let kdf = match suite.kdf {
+        KDF_HKDF_SHA256 => Kdf::HkdfSha256,
+        _ => return Err(ClientError::UnsupportedSuite),
+    };
+    let aead = match suite.aead {
+        AEAD_AES_128_GCM => Aead::Aes128Gcm,
+        AEAD_CHACHA20_POLY1305 => Aead::ChaCha20Poly1305,
+        _ => return Err(ClientError::UnsupportedSuite),
+    };
+    KeyConfig::derive(key_id, kem, vec![SymmetricSuite::new(kdf, aead)], seed)
+        .map_err(ClientError::Ohttp)
+}
+
+/// Generates a fresh key pair for `key_id` and the suite of `kem`, `kdf` and `aead`, writing
+/// the encoded key configuration for the client into `out_config` and the private key for
+/// a test gateway, [`TEST_PRIVATE_KEY_LEN`] bytes, into `out_private`.
+///
+/// **Test helper, only available in builds with both `server` and `testutil`. The private
+/// key must never ship.** It is the seed the key pair is derived from, which `ohttp` accepts
+/// instead of the raw key, so load it with [`TestServer::from_seed`] and the same
+/// parameters.
+///
+/// Returns the length of the configuration, or -1 if the suite is not supported or either
+/// buffer is too small, in which case neither is written.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts_mut`](std::slice::from_raw_parts_mut#safety)
+#[cfg(feature = "testutil")]
+#[allow(clippy::too_many_arguments)]
+#[no_mangle]
+pub unsafe extern "C" fn generate_test_keypair_ffi(
+    kem: u16,
+    kdf: u16,
+    aead: u16,
+    key_id: u8,
+    out_config: *mut u8,
+    config_cap: libc::size_t,
+    out_private: *mut u8,
+    private_cap: libc::size_t,
+) -> libc::c_int {
+    let out_config = null_safe_ptr!(out_config, -1, out_config);
+    let out_private = null_safe_ptr!(out_private, -1, out_private);
+
+    let mut seed = [0; TEST_PRIVATE_KEY_LEN];
+    safe_unwrap!(
+        getrandom::getrandom(&mut seed),
+        -1,
+        ClientError::RngUnavailable
+    );
+    let suite = HpkeSuite { kem, kdf, aead };
+    let config = safe_unwrap!(derive_config(key_id, suite, &seed), -1, identity);
+    let encoded = safe_unwrap!(config.encode(), -1, ClientError::Ohttp);
+
+    for (needed, available) in [(encoded.len(), config_cap), (seed.len(), private_cap)] {
+        if needed > available {
+            update_last_error(ClientError::BufferTooSmall { needed, available });
+            return -1;
+        }
+    }
+    slice::from_raw_parts_mut(out_config, encoded.len()).copy_from_slice(&encoded);
+    slice::from_raw_parts_mut(out_private, seed.len()).copy_from_slice(&seed);
+    encoded.len() as libc::c_int
+}
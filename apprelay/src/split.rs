@@ -0,0 +1,188 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Encapsulation returning the request bytes and the decapsulation state as separate
+//! allocations, for transports where the two travel independently.
+//!
+//! Unlike a `RequestContext`, neither allocation borrows from the other: the request bytes
+//! can be freed with `apprelay_free_bytes_ffi` as soon as they are sent, and the
+//! `ResponseDecapsulator` stays valid on its own until the response arrives.
+//! `request_context_from_parts_ffi` joins them back into a `RequestContext`.
+
+use std::convert::identity;
+use std::panic::catch_unwind;
+use std::ptr::{self, null_mut};
+use std::slice;
+
+use crate::error_ffi::update_last_error;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+use crate::libc;
+use crate::{
+    catch_panics, check_request_parts, handles, jitter, null_safe_ptr, safe_unwrap, ClientError,
+    RequestContext, ResponseContext, ResponseDecapsulator,
+};
+
+/// Encapsulates the provided `encoded_msg` using `encoded_config`, handing the request bytes
+/// and the state decapsulating its response to the caller separately.
+///
+/// On success `*out_req_bytes` and `*out_req_len` describe the encapsulated request, to be
+/// freed with [`apprelay_free_bytes_ffi`], and `*out_decapsulator` the state to pass to
+/// [`response_decapsulator_decapsulate_ffi`] or free with [`response_decapsulator_drop_ffi`].
+///
+/// Returns 0 on success, or -1 if encapsulation fails, leaving the outputs untouched.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety)
+/// and the output pointers must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn encapsulate_split_ffi(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    encoded_msg_ptr: *const u8,
+    encoded_msg_len: libc::size_t,
+    out_req_bytes: *mut *mut u8,
+    out_req_len: *mut libc::size_t,
+    out_decapsulator: *mut *mut ResponseDecapsulator,
+) -> libc::c_int {
+    let encoded_config_ptr = null_safe_ptr!(encoded_config_ptr, -1, encoded_config_ptr);
+    let encoded_msg_ptr = null_safe_ptr!(encoded_msg_ptr, -1, encoded_msg_ptr);
+    let out_req_bytes = null_safe_ptr!(out_req_bytes, -1, out_req_bytes);
+    let out_req_len = null_safe_ptr!(out_req_len, -1, out_req_len);
+    let out_decapsulator = null_safe_ptr!(out_decapsulator, -1, out_decapsulator);
+
+    let encoded_config = slice::from_raw_parts(encoded_config_ptr, encoded_config_len);
+    let encoded_msg = slice::from_raw_parts(encoded_msg_ptr, encoded_msg_len);
+
+    let parts = catch_panics!(
+        RequestContext::new(encoded_config, encoded_msg).and_then(RequestContext::into_parts),
+        return -1
+    );
+    let (request, decapsulator) = safe_unwrap!(parts, -1, identity);
+    let request = request.into_boxed_slice();
+    *out_req_len = request.len();
+    *out_req_bytes = Box::into_raw(request) as *mut u8;
+    *out_decapsulator = Box::into_raw(Box::new(decapsulator));
+    0
+}
+
+/// Frees request bytes returned by [`encapsulate_split_ffi`] or response bytes returned by
+/// [`response_context_take_bytes_ffi`](crate::response_context_take_bytes_ffi), `len` being
+/// the length returned along with them.
+///
+/// # Safety
+/// `bytes` and `len` must come from a single successful call of either function,
+/// and `bytes` must not be used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn apprelay_free_bytes_ffi(bytes: *mut u8, len: libc::size_t) {
+    null_safe_ptr!(bytes, (), {
+        let _bytes = Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, len));
+    })
+}
+
+/// Decapsulates the provided `encapsulated_response` with the state returned by
+/// [`encapsulate_split_ffi`], consuming it.
+///
+/// This function will return a NULL pointer if decapsulation fails. An empty response fails
+/// with `ResponseEmpty` before `decapsulator` is consumed, so the caller may retry the
+/// exchange with it or has to free it.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseDecapsulator` passed by the caller.
+/// Be sure that it has not been yet consumed or freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn response_decapsulator_decapsulate_ffi(
+    decapsulator: *mut ResponseDecapsulator,
+    encapsulated_response_ptr: *const u8,
+    encapsulated_response_len: libc::size_t,
+) -> *mut ResponseContext {
+    if encapsulated_response_len == 0 {
+        update_last_error(ClientError::ResponseEmpty);
+        return null_mut();
+    }
+    let encapsulated_response_ptr = null_safe_ptr!(
+        encapsulated_response_ptr,
+        null_mut(),
+        encapsulated_response_ptr
+    );
+    let decapsulator = null_safe_ptr!(decapsulator, null_mut(), Box::from_raw(decapsulator));
+    let encapsulated_response =
+        slice::from_raw_parts(encapsulated_response_ptr, encapsulated_response_len);
+
+    catch_panics!(
+        {
+            let response = safe_unwrap!(
+                decapsulator.decapsulate(encapsulated_response),
+                null_mut(),
+                identity
+            );
+            jitter::delay_response();
+            Box::into_raw(Box::new(ResponseContext::new(response)))
+        },
+        null_mut()
+    )
+}
+
+/// Frees up decapsulation state returned by [`encapsulate_split_ffi`] whose response is
+/// not going to be decapsulated.
+///
+/// # Safety
+/// Dereferences a pointer to `ResponseDecapsulator` passed by the caller.
+/// Be sure that it has not been yet consumed or freed and that you are using a valid pointer.
+///
+/// <https://doc.rust-lang.org/book/ch19-01-unsafe-rust.html#dereferencing-a-raw-pointer>
+#[no_mangle]
+pub unsafe extern "C" fn response_decapsulator_drop_ffi(decapsulator: *mut ResponseDecapsulator) {
+    null_safe_ptr!(decapsulator, (), {
+        let _decapsulator = Box::from_raw(decapsulator);
+    })
+}
+
+/// Reassembles a `RequestContext` from an encapsulated request delivered as two fields,
+/// `header` holding the key id, the suite and `enc` and `ciphertext` the rest, and the
+/// `decapsulator` returned along with the request by [`encapsulate_split_ffi`].
+///
+/// The HPKE state needed for the response can not be derived from the request bytes, so the
+/// context takes over `decapsulator` and is used like any other, e.g. with
+/// `decapsulate_response_ffi`.
+///
+/// This function will return a NULL pointer if `encoded_config` is invalid, if `header` is
+/// not as long as the KEM's `enc` requires or names another key id or suite than
+/// `encoded_config` and `decapsulator`. `decapsulator` is only consumed on success.
+///
+/// # Safety
+/// The invariants are described here [`from_raw_parts`](std::slice::from_raw_parts#safety).
+/// `decapsulator` must not have been consumed or freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn request_context_from_parts_ffi(
+    encoded_config_ptr: *const u8,
+    encoded_config_len: libc::size_t,
+    header_ptr: *const u8,
+    header_len: libc::size_t,
+    ciphertext_ptr: *const u8,
+    ciphertext_len: libc::size_t,
+    decapsulator: *mut ResponseDecapsulator,
+) -> *mut RequestContext {
+    let encoded_config_ptr = null_safe_ptr!(encoded_config_ptr, null_mut(), encoded_config_ptr);
+    let header_ptr = null_safe_ptr!(header_ptr, null_mut(), header_ptr);
+    let ciphertext_ptr = null_safe_ptr!(ciphertext_ptr, null_mut(), ciphertext_ptr);
+    let decapsulator = null_safe_ptr!(decapsulator, null_mut(), decapsulator);
+
+    let encoded_config = slice::from_raw_parts(encoded_config_ptr, encoded_config_len);
+    let header = slice::from_raw_parts(header_ptr, header_len);
+    let ciphertext = slice::from_raw_parts(ciphertext_ptr, ciphertext_len);
+
+    safe_unwrap!(
+        check_request_parts(encoded_config, header, (*decapsulator).suite),
+        null_mut(),
+        identity
+    );
+    let decapsulator = *Box::from_raw(decapsulator);
+    let context = safe_unwrap!(
+        RequestContext::from_parts(encoded_config, header, ciphertext, decapsulator),
+        null_mut(),
+        identity
+    );
+    handles::register(context)
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! JavaScript API of the `workers` build, a `wasm32-unknown-unknown` module for Cloudflare
+//! Workers and other edge runtimes, see `examples/workers`.
+//!
+//! Bytes cross the boundary as `Uint8Array`s and errors are thrown as JavaScript `Error`s
+//! carrying the message of the [`ClientError`] and its causes.
+
+use std::error::Error;
+
+use js_sys::Uint8Array;
+use wasm_bindgen::prelude::*;
+
+use crate::{ClientError, RequestContext, ResponseDecapsulator};
+
+fn js_error(err: ClientError) -> JsError {
+    let mut message = err.to_string();
+    let mut cause = err.source();
+    while let Some(parent) = cause {
+        message.push_str(&format!(": {parent}"));
+        cause = parent.source();
+    }
+    JsError::new(&message)
+}
+
+/// An encapsulated request and the state decapsulating its response.
+///
+/// ```js
+/// const request = new OhttpRequest(config, bhttpRequest);
+/// const reply = await fetch(relayUrl, { method: "POST", body: request.encapsulated, headers });
+/// const bhttpResponse = request.decapsulate(new Uint8Array(await reply.arrayBuffer()));
+/// ```
+#[wasm_bindgen(js_name = OhttpRequest)]
+pub struct WorkerRequest {
+    encapsulated: Vec<u8>,
+    decapsulator: Option<ResponseDecapsulator>,
+}
+
+#[wasm_bindgen(js_class = OhttpRequest)]
+impl WorkerRequest {
+    /// Encapsulates the BHTTP message `msg` with the encoded key configuration `config`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(config: &[u8], msg: &[u8]) -> Result<WorkerRequest, JsError> {
+        let (encapsulated, decapsulator) = RequestContext::new(config, msg)
+            .and_then(RequestContext::into_parts)
+            .map_err(js_error)?;
+        Ok(WorkerRequest {
+            encapsulated,
+            decapsulator: Some(decapsulator),
+        })
+    }
+
+    /// The `message/ohttp-req` body to POST to the relay.
+    #[wasm_bindgen(getter)]
+    pub fn encapsulated(&self) -> Uint8Array {
+        Uint8Array::from(&self.encapsulated[..])
+    }
+
+    /// Decapsulates the relay's `message/ohttp-res` body into the BHTTP response.
+    ///
+    /// Only one response can be decapsulated, later calls throw.
+    pub fn decapsulate(&mut self, response: &[u8]) -> Result<Uint8Array, JsError> {
+        let decapsulator = self
+            .decapsulator
+            .take()
+            .ok_or_else(|| js_error(ClientError::ContextEmpty))?;
+        let response = decapsulator.decapsulate(response).map_err(js_error)?;
+        Ok(Uint8Array::from(&response[..]))
+    }
+}
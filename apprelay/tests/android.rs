@@ -0,0 +1,60 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The parts of the JNI entry points that run without a JVM.
+
+use apprelay::android::{discard_context, encapsulated_request_region};
+use apprelay::error_ffi::{last_error_code, update_last_error};
+use apprelay::{
+    encapsulate_request_ffi, request_context_message_drop_ffi, request_context_message_ffi,
+    request_context_message_len_ffi, ClientError, ClientErrorKind,
+};
+
+mod common;
+use common::config;
+
+#[test]
+fn direct_request_region_matches_the_copied_request() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+
+        // getEncapsulatedRequest copies the same bytes into a Java array.
+        let copied = std::slice::from_raw_parts(
+            request_context_message_ffi(context),
+            request_context_message_len_ffi(context),
+        )
+        .to_vec();
+        let (ptr, len) = encapsulated_request_region(context as i64).unwrap();
+        assert_eq!(std::slice::from_raw_parts(ptr, len), &copied[..]);
+
+        request_context_message_drop_ffi(context);
+        assert!(matches!(
+            encapsulated_request_region(context as i64),
+            Err(ClientError::InvalidHandle)
+        ));
+    }
+}
+
+#[test]
+fn failed_result_frees_the_context_and_keeps_the_error() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+
+        // As a failing getEncapsulatedRequest would have reported it.
+        update_last_error(ClientError::ResponseEmpty);
+        discard_context(context as i64);
+        assert_eq!(last_error_code(), ClientErrorKind::ResponseEmpty as i32);
+        assert!(matches!(
+            encapsulated_request_region(context as i64),
+            Err(ClientError::InvalidHandle)
+        ));
+    }
+}
@@ -0,0 +1,365 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Encoding of BHTTP requests through the Rust API and `build_bhttp_request_ffi`, and
+//! decoding of responses.
+
+use std::ffi::CString;
+
+use apprelay::bhttp::{
+    bhttp_response_drop_ffi, bhttp_response_header_count_ffi, bhttp_response_header_name_ffi,
+    bhttp_response_informational_count_ffi, bhttp_response_informational_header_count_ffi,
+    bhttp_response_informational_header_name_ffi, bhttp_response_informational_header_value_ffi,
+    bhttp_response_informational_status_ffi, bhttp_response_status_ffi,
+    bhttp_response_trailer_count_ffi, bhttp_response_trailer_name_ffi,
+    bhttp_response_trailer_value_ffi, build_bhttp_request_ffi, parse_bhttp_response_ffi,
+    set_max_response_body_ffi, BhttpRequest, BhttpResponse, FieldLimits, BHTTP_CANONICAL_HEADERS,
+    BHTTP_DEFAULT_MAX_HEADERS,
+};
+use apprelay::error_ffi::{last_error_code, take_last_error};
+use apprelay::{BytesView, ClientError, ClientErrorKind};
+
+const BODY: &[u8] = b"{}";
+
+fn build_ffi(headers: &[(&str, &str)], flags: libc::c_int) -> Vec<u8> {
+    let names: Vec<CString> = headers
+        .iter()
+        .map(|(name, _)| CString::new(*name).unwrap())
+        .collect();
+    let values: Vec<CString> = headers
+        .iter()
+        .map(|(_, value)| CString::new(*value).unwrap())
+        .collect();
+    let name_ptrs: Vec<_> = names.iter().map(|name| name.as_ptr()).collect();
+    let value_ptrs: Vec<_> = values.iter().map(|value| value.as_ptr()).collect();
+    let (method, scheme, authority, path) = (
+        CString::new("POST").unwrap(),
+        CString::new("https").unwrap(),
+        CString::new("example.com").unwrap(),
+        CString::new("/query").unwrap(),
+    );
+
+    let mut out = vec![0; 1024];
+    let written = unsafe {
+        build_bhttp_request_ffi(
+            method.as_ptr(),
+            scheme.as_ptr(),
+            authority.as_ptr(),
+            path.as_ptr(),
+            name_ptrs.as_ptr(),
+            value_ptrs.as_ptr(),
+            headers.len(),
+            BODY.as_ptr(),
+            BODY.len(),
+            flags,
+            out.as_mut_ptr(),
+            out.len(),
+        )
+    };
+    assert!(written > 0);
+    out.truncate(written as usize);
+    out
+}
+
+#[test]
+fn canonical_headers_ignore_input_order() {
+    let first = [
+        ("Content-Type", "application/json"),
+        ("accept", "*/*"),
+        ("X-Trace", "b"),
+        ("x-trace", "a"),
+    ];
+    let second = [
+        ("x-trace", "a"),
+        ("Accept", "*/*"),
+        ("content-type", "application/json"),
+        ("X-Trace", "b"),
+    ];
+
+    let canonical = build_ffi(&first, BHTTP_CANONICAL_HEADERS);
+    assert_eq!(canonical, build_ffi(&second, BHTTP_CANONICAL_HEADERS));
+    assert_ne!(build_ffi(&first, 0), build_ffi(&second, 0));
+
+    let request = second
+        .iter()
+        .fold(
+            BhttpRequest::new("POST", "https", "example.com", "/query"),
+            |request, (name, value)| request.header(name, value),
+        )
+        .canonical();
+    assert_eq!(request.encode(BODY), canonical);
+}
+
+#[test]
+fn response_body_limit() {
+    // Known-length response, status 200, no headers, then content of 2^62 - 1 bytes
+    // declared but only three present.
+    let mut enormous = vec![0x01, 0x40, 0xc8, 0x00];
+    enormous.extend_from_slice(&[0xff; 8]);
+    enormous.extend_from_slice(b"abc");
+
+    set_max_response_body_ffi(1024);
+    let response = unsafe { parse_bhttp_response_ffi(enormous.as_ptr(), enormous.len()) };
+    set_max_response_body_ffi(usize::MAX);
+    assert!(response.is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::ResponseMalformed as libc::c_int
+    );
+
+    // Content actually present is bounded as well.
+    let small = [0x01, 0x40, 0xc8, 0x00, 0x03, b'a', b'b', b'c'];
+    let limits = FieldLimits {
+        max_body_bytes: 2,
+        ..FieldLimits::default()
+    };
+    assert!(matches!(
+        BhttpResponse::decode_with_limits(&small, limits),
+        Err(ClientError::ResponseMalformed(_))
+    ));
+    let limits = FieldLimits {
+        max_body_bytes: 3,
+        ..limits
+    };
+    assert_eq!(
+        BhttpResponse::decode_with_limits(&small, limits)
+            .unwrap()
+            .body(),
+        b"abc"
+    );
+}
+
+fn length_prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    assert!(bytes.len() < 64);
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(bytes);
+}
+
+fn field_section(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut section = Vec::new();
+    for (name, value) in fields {
+        length_prefixed(&mut section, name.as_bytes());
+        length_prefixed(&mut section, value.as_bytes());
+    }
+    let mut out = Vec::new();
+    length_prefixed(&mut out, &section);
+    out
+}
+
+unsafe fn view(view: BytesView) -> &'static [u8] {
+    std::slice::from_raw_parts(view.ptr, view.len)
+}
+
+#[test]
+fn early_hints_precede_final_response() {
+    let mut early_hints = vec![0x40, 103];
+    early_hints.extend(field_section(&[("link", "</style.css>; rel=preload")]));
+    let mut ok = vec![0x40, 0xc8];
+    ok.extend(field_section(&[("content-type", "text/plain")]));
+    length_prefixed(&mut ok, b"hello");
+    let message = [&[0x01][..], &early_hints, &ok].concat();
+
+    unsafe {
+        let response = parse_bhttp_response_ffi(message.as_ptr(), message.len());
+        assert!(!response.is_null());
+        assert_eq!(bhttp_response_status_ffi(response), 200);
+        assert_eq!(bhttp_response_informational_count_ffi(response), 1);
+        assert_eq!(bhttp_response_informational_status_ffi(response, 0), 103);
+        assert_eq!(
+            bhttp_response_informational_header_count_ffi(response, 0),
+            1
+        );
+        assert_eq!(
+            view(bhttp_response_informational_header_name_ffi(response, 0, 0)),
+            b"link"
+        );
+        assert_eq!(
+            view(bhttp_response_informational_header_value_ffi(
+                response, 0, 0
+            )),
+            b"</style.css>; rel=preload"
+        );
+        assert_eq!(bhttp_response_informational_status_ffi(response, 1), 0);
+        assert_eq!(
+            last_error_code(),
+            ClientErrorKind::InvalidArgument as libc::c_int
+        );
+        bhttp_response_drop_ffi(response);
+    }
+
+    let response = BhttpResponse::decode(&[&[0x01][..], &ok].concat()).unwrap();
+    assert!(response.informational().is_empty());
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body(), b"hello");
+}
+
+#[test]
+fn trailers_stay_out_of_the_header_section() {
+    let mut message = vec![0x01, 0x40, 0xc8];
+    message.extend(field_section(&[("content-type", "text/plain")]));
+    length_prefixed(&mut message, b"hello");
+    message.extend(field_section(&[
+        ("server-timing", "total;dur=12"),
+        ("digest", "sha-256=abc"),
+    ]));
+
+    let response = BhttpResponse::decode(&message).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body(), b"hello");
+    let headers: Vec<_> = response
+        .headers()
+        .iter()
+        .map(|field| field.name())
+        .collect();
+    assert_eq!(headers, [&b"content-type"[..]]);
+    let trailers: Vec<_> = response
+        .trailers()
+        .iter()
+        .map(|field| (field.name(), field.value()))
+        .collect();
+    assert_eq!(
+        trailers,
+        [
+            (&b"server-timing"[..], &b"total;dur=12"[..]),
+            (&b"digest"[..], &b"sha-256=abc"[..]),
+        ]
+    );
+
+    unsafe {
+        let response = parse_bhttp_response_ffi(message.as_ptr(), message.len());
+        assert!(!response.is_null());
+        assert_eq!(bhttp_response_header_count_ffi(response), 1);
+        assert_eq!(
+            view(bhttp_response_header_name_ffi(response, 0)),
+            b"content-type"
+        );
+        assert!(bhttp_response_header_name_ffi(response, 1).ptr.is_null());
+        assert_eq!(bhttp_response_trailer_count_ffi(response), 2);
+        assert_eq!(
+            view(bhttp_response_trailer_name_ffi(response, 1)),
+            b"digest"
+        );
+        assert_eq!(
+            view(bhttp_response_trailer_value_ffi(response, 1)),
+            b"sha-256=abc"
+        );
+        bhttp_response_drop_ffi(response);
+    }
+}
+
+/// Encodes a bodiless request from raw C strings, `None` if it is rejected.
+unsafe fn build_raw(method: &[u8], path: &[u8], header: (&[u8], &[u8])) -> Option<Vec<u8>> {
+    let names = [header.0.as_ptr().cast()];
+    let values = [header.1.as_ptr().cast()];
+    let mut out = vec![0; 256];
+    let written = build_bhttp_request_ffi(
+        method.as_ptr().cast(),
+        b"https\0".as_ptr().cast(),
+        b"example.com\0".as_ptr().cast(),
+        path.as_ptr().cast(),
+        names.as_ptr(),
+        values.as_ptr(),
+        1,
+        std::ptr::null(),
+        0,
+        0,
+        out.as_mut_ptr(),
+        out.len(),
+    );
+    if written < 0 {
+        return None;
+    }
+    out.truncate(written as usize);
+    Some(out)
+}
+
+fn assert_rejected_argument(name: &str) {
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+    let message = take_last_error().unwrap().to_string();
+    assert!(message.contains(name), "{message}");
+}
+
+#[test]
+fn malformed_c_strings_are_rejected() {
+    let header = (&b"accept\0"[..], &b"*/*\0"[..]);
+    unsafe {
+        assert!(build_raw(b"G\xffT\0", b"/\0", header).is_none());
+        assert_rejected_argument("method is not valid UTF-8");
+
+        assert!(build_raw(b"GET\0", b"/\xc3\0", header).is_none());
+        assert_rejected_argument("path is not valid UTF-8");
+
+        assert!(build_raw(b"GET\0", b"/\0", (b"accept\0", b"\x80\0")).is_none());
+        assert_rejected_argument("header_values is not valid UTF-8");
+
+        // A C string ends at its first NUL, whatever follows is never read.
+        let truncated = build_raw(b"GET\0", b"/a\0/b\0", (b"accept\0junk\0", b"*/*\0")).unwrap();
+        let expected = BhttpRequest::new("GET", "https", "example.com", "/a")
+            .header("accept", "*/*")
+            .encode(b"");
+        assert_eq!(truncated, expected);
+    }
+}
+
+/// Response with `count` header fields `x: y` and an empty body.
+fn response_with_headers(count: usize) -> Vec<u8> {
+    let section_len = 4 * count;
+    assert!(section_len < 1 << 14);
+    let mut message = vec![0x01, 0x40, 0xc8];
+    message.extend_from_slice(&(section_len as u16 | 0x4000).to_be_bytes());
+    for _ in 0..count {
+        message.extend_from_slice(&[1, b'x', 1, b'y']);
+    }
+    message.push(0);
+    message
+}
+
+#[test]
+fn header_count_limit() {
+    let at_limit = response_with_headers(BHTTP_DEFAULT_MAX_HEADERS);
+    let over_limit = response_with_headers(BHTTP_DEFAULT_MAX_HEADERS + 1);
+    unsafe {
+        let response = parse_bhttp_response_ffi(at_limit.as_ptr(), at_limit.len());
+        assert!(!response.is_null());
+        assert_eq!(
+            bhttp_response_header_count_ffi(response),
+            BHTTP_DEFAULT_MAX_HEADERS
+        );
+        bhttp_response_drop_ffi(response);
+
+        assert!(parse_bhttp_response_ffi(over_limit.as_ptr(), over_limit.len()).is_null());
+        assert_eq!(
+            last_error_code(),
+            ClientErrorKind::ResponseMalformed as libc::c_int
+        );
+    }
+
+    let limits = FieldLimits {
+        max_fields: 3,
+        ..FieldLimits::default()
+    };
+    assert_eq!(
+        BhttpResponse::decode_with_limits(&response_with_headers(3), limits)
+            .unwrap()
+            .headers()
+            .len(),
+        3
+    );
+    assert!(matches!(
+        BhttpResponse::decode_with_limits(&response_with_headers(4), limits),
+        Err(ClientError::ResponseMalformed(_))
+    ));
+
+    // Names and values count towards the byte limit, four fields carry eight bytes.
+    let limits = FieldLimits {
+        max_total_bytes: 7,
+        ..FieldLimits::default()
+    };
+    assert!(matches!(
+        BhttpResponse::decode_with_limits(&response_with_headers(4), limits),
+        Err(ClientError::ResponseMalformed(_))
+    ));
+}
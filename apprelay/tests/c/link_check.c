@@ -0,0 +1,111 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+// Includes apprelay.h and links the static library, built with `testutil`, the way a C
+// integration does. Run by tests/c_header.rs.
+
+#include <stddef.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "apprelay.h"
+
+#define CHECK(condition)                                                   \
+  do {                                                                     \
+    if (!(condition)) {                                                    \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,     \
+              #condition);                                                 \
+      return 1;                                                            \
+    }                                                                      \
+  } while (0)
+
+// Key id 1, X25519 with an arbitrary public key, HKDF-SHA256 and AES-128-GCM.
+static uint8_t config[MIN_KEY_CONFIG_LEN];
+
+static void build_config(void) {
+  size_t offset = 0;
+  config[offset++] = 1;
+  config[offset++] = KEM_X25519_SHA256 >> 8;
+  config[offset++] = KEM_X25519_SHA256 & 0xff;
+  memset(config + offset, 9, 32);
+  offset += 32;
+  config[offset++] = 0;
+  config[offset++] = 4;
+  config[offset++] = KDF_HKDF_SHA256 >> 8;
+  config[offset++] = KDF_HKDF_SHA256 & 0xff;
+  config[offset++] = AEAD_AES_128_GCM >> 8;
+  config[offset++] = AEAD_AES_128_GCM & 0xff;
+}
+
+static int encapsulate_full(void) {
+  const uint8_t msg[] = "GET / HTTP/1.1";
+  EncapsulateResult result =
+      encapsulate_request_full_ffi(config, sizeof(config), msg, sizeof(msg) - 1);
+  CHECK(result.context != NULL);
+  CHECK(result.error_code == 0);
+  CHECK(result.message == request_context_message_ffi(result.context));
+  // Header, X25519 enc, the message and the AES-128-GCM tag.
+  CHECK(result.message_len == 7 + 32 + (sizeof(msg) - 1) + 16);
+  CHECK(result.message[0] == 1);
+  request_context_message_drop_ffi(result.context);
+
+  result = encapsulate_request_full_ffi(config, 3, msg, sizeof(msg) - 1);
+  CHECK(result.context == NULL);
+  CHECK(result.message == NULL);
+  CHECK(result.error_code == MalformedConfig);
+  CHECK(last_error_code() == MalformedConfig);
+
+  // The same configuration offering only AES-256-GCM, which this build can not seal with.
+  uint8_t aes_256_gcm[MIN_KEY_CONFIG_LEN];
+  memcpy(aes_256_gcm, config, sizeof(config));
+  aes_256_gcm[sizeof(aes_256_gcm) - 1] = AEAD_AES_256_GCM & 0xff;
+  result = encapsulate_request_full_ffi(aes_256_gcm, sizeof(aes_256_gcm), msg, sizeof(msg) - 1);
+  CHECK(result.context == NULL);
+  CHECK(result.message_len == 0);
+  CHECK(result.error_code == UnsupportedSuite);
+  return 0;
+}
+
+static int copy_response(void) {
+  const uint8_t plaintext[] = "HTTP/1.1 204 No Content\r\n\r\n";
+  struct ResponseContext *response =
+      response_context_from_plaintext_ffi(plaintext, sizeof(plaintext) - 1);
+  CHECK(response != NULL);
+
+  size_t len = response_context_message_len_ffi(response);
+  CHECK(len == sizeof(plaintext) - 1);
+  uint8_t *copy = malloc(len);
+  CHECK(copy != NULL);
+  CHECK(response_context_copy_ffi(response, copy, len - 1) == -1);
+  CHECK(response_context_copy_ffi(response, copy, len) == (ssize_t)len);
+  CHECK(memcmp(copy, plaintext, len) == 0);
+  free(copy);
+
+  // The accessors only borrowed the context, it is freed exactly once.
+  CHECK(response_context_message_len_ffi(response) == len);
+  response_context_drop_ffi(response);
+  return 0;
+}
+
+// The layout this compiler gives the structs matches the library's.
+static int struct_sizes(void) {
+  StructSizes sizes = {0};
+  apprelay_struct_sizes_ffi(&sizes);
+  CHECK(sizes.struct_sizes_size == sizeof(StructSizes));
+  CHECK(sizes.bytes_view_size == sizeof(BytesView));
+  CHECK(sizes.bytes_view_align == _Alignof(BytesView));
+  CHECK(sizes.bytes_view_ptr_offset == offsetof(BytesView, ptr));
+  CHECK(sizes.bytes_view_len_offset == offsetof(BytesView, len));
+  CHECK(sizes.encapsulate_result_size == sizeof(EncapsulateResult));
+  CHECK(sizes.encapsulate_result_align == _Alignof(EncapsulateResult));
+  return 0;
+}
+
+int main(void) {
+  build_config();
+  if (encapsulate_full() != 0 || copy_response() != 0 || struct_sizes() != 0) {
+    return 1;
+  }
+  puts("ok");
+  return 0;
+}
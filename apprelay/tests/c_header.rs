@@ -0,0 +1,76 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Compiles `tests/c/link_check.c` against `apprelay.h`, links it with the static library and
+//! runs it. This needs a full cargo build and a C compiler, so the test is ignored by default,
+//! run it with `cargo test --test c_header -- --ignored`.
+
+use std::path::Path;
+use std::process::Command;
+
+/// The system libraries the Rust standard library needs when linked statically into a C
+/// program, as printed by `--print native-static-libs`.
+#[cfg(target_os = "linux")]
+const NATIVE_LIBS: &[&str] = &[
+    "-lgcc_s",
+    "-lutil",
+    "-lrt",
+    "-lpthread",
+    "-lm",
+    "-ldl",
+    "-lc",
+];
+#[cfg(target_os = "macos")]
+const NATIVE_LIBS: &[&str] = &[
+    "-framework",
+    "Security",
+    "-liconv",
+    "-lSystem",
+    "-lc",
+    "-lm",
+];
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[test]
+#[ignore]
+fn c_program_links_against_the_header() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir.join("target").join("c-header");
+    let built = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()))
+        .current_dir(manifest_dir)
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "testutil",
+            "--target-dir",
+        ])
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(built.success(), "the static library does not build");
+
+    let program = target_dir.join("link_check");
+    let compiled = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .current_dir(manifest_dir)
+        .args(["-std=c11", "-Wall", "-Wextra", "-Werror", "-I."])
+        .arg("tests/c/link_check.c")
+        .arg(target_dir.join("debug").join("libapprelay.a"))
+        .args(NATIVE_LIBS)
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(compiled.success(), "link_check.c does not compile or link");
+
+    let output = Command::new(&program)
+        .output()
+        .expect("failed to run link_check");
+    assert!(
+        output.status.success(),
+        "link_check failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"ok\n");
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! `ClientError::kind` and the source chain seen by Rust callers.
+
+use std::error::Error;
+
+use apprelay::config::{HpkeSuite, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256};
+use apprelay::{ClientError, ClientErrorKind};
+
+fn every_variant() -> Vec<(ClientError, ClientErrorKind)> {
+    let suite = HpkeSuite {
+        kem: KEM_X25519_SHA256,
+        kdf: KDF_HKDF_SHA256,
+        aead: AEAD_AES_128_GCM,
+    };
+    vec![
+        (
+            ClientError::RequestContextInitialization(ohttp::Error::Truncated),
+            ClientErrorKind::RequestContextInitialization,
+        ),
+        (
+            ClientError::EncapsulationFailed(ohttp::Error::Truncated),
+            ClientErrorKind::EncapsulationFailed,
+        ),
+        (
+            ClientError::DecapsulationFailed(ohttp::Error::Truncated),
+            ClientErrorKind::DecapsulationFailed,
+        ),
+        (
+            ClientError::Ohttp(ohttp::Error::Truncated),
+            ClientErrorKind::Ohttp,
+        ),
+        (
+            ClientError::InvalidArgument("x".to_owned()),
+            ClientErrorKind::InvalidArgument,
+        ),
+        (
+            ClientError::ResponseMalformed("x".to_owned()),
+            ClientErrorKind::ResponseMalformed,
+        ),
+        (
+            ClientError::MalformedConfig("x".to_owned()),
+            ClientErrorKind::MalformedConfig,
+        ),
+        (
+            ClientError::ResponseTruncated {
+                expected: 32,
+                actual: 31,
+            },
+            ClientErrorKind::ResponseTruncated,
+        ),
+        (
+            ClientError::BufferTooSmall {
+                needed: 2,
+                available: 1,
+            },
+            ClientErrorKind::BufferTooSmall,
+        ),
+        (
+            ClientError::SafePanic(Box::new("panicked")),
+            ClientErrorKind::SafePanic,
+        ),
+        #[cfg(feature = "java")]
+        (
+            ClientError::JniProblem(jni::errors::Error::NullPtr("env")),
+            ClientErrorKind::JniProblem,
+        ),
+        (ClientError::ResponseEmpty, ClientErrorKind::ResponseEmpty),
+        (
+            ClientError::ResponseAuthFailed(ohttp::Error::Truncated),
+            ClientErrorKind::ResponseAuthFailed,
+        ),
+        (
+            ClientError::UnsupportedSuite,
+            ClientErrorKind::UnsupportedSuite,
+        ),
+        (
+            ClientError::ResponseFramingMismatch {
+                declared: 4,
+                actual: 3,
+            },
+            ClientErrorKind::ResponseFramingMismatch,
+        ),
+        (ClientError::InvalidHandle, ClientErrorKind::InvalidHandle),
+        (ClientError::ContextEmpty, ClientErrorKind::ContextEmpty),
+        (
+            ClientError::RngUnavailable(getrandom::Error::UNSUPPORTED),
+            ClientErrorKind::RngUnavailable,
+        ),
+        (
+            ClientError::SuiteMismatch {
+                expected: suite,
+                actual: [0; 7],
+            },
+            ClientErrorKind::SuiteMismatch,
+        ),
+        (
+            ClientError::CallbackAborted,
+            ClientErrorKind::CallbackAborted,
+        ),
+        (
+            ClientError::KemNotCompiled(0x0010),
+            ClientErrorKind::KemNotCompiled,
+        ),
+    ]
+}
+
+#[test]
+fn kind_matches_the_variant() {
+    for (err, kind) in every_variant() {
+        assert_eq!(err.kind(), kind, "{err:?}");
+    }
+}
+
+fn client(encoded_config: &[u8]) -> Result<ohttp::ClientRequest, ClientError> {
+    Ok(ohttp::ClientRequest::new(encoded_config)?)
+}
+
+#[test]
+fn sources_are_chained() {
+    let err = client(&[1, 0]).err().unwrap();
+    assert_eq!(err.kind(), ClientErrorKind::Ohttp);
+
+    let wrapping_ohttp = [
+        err,
+        ClientError::RequestContextInitialization(ohttp::Error::Truncated),
+        ClientError::EncapsulationFailed(ohttp::Error::Truncated),
+        ClientError::DecapsulationFailed(ohttp::Error::Truncated),
+        ClientError::ResponseAuthFailed(ohttp::Error::Truncated),
+    ];
+    for err in wrapping_ohttp {
+        let source = err.source().expect("the ohttp error is the source");
+        assert!(source.downcast_ref::<ohttp::Error>().is_some(), "{err:?}");
+    }
+
+    let rng = ClientError::RngUnavailable(getrandom::Error::UNSUPPORTED);
+    assert!(rng
+        .source()
+        .and_then(|source| source.downcast_ref::<getrandom::Error>())
+        .is_some());
+
+    assert!(ClientError::InvalidHandle.source().is_none());
+    assert!(ClientError::MalformedConfig("x".to_owned())
+        .source()
+        .is_none());
+}
@@ -0,0 +1,30 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Fixtures shared by the integration tests, each test binary uses only some of them.
+#![allow(dead_code)]
+
+use apprelay::config::{
+    KeyConfig, SymmetricSuite, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+
+/// Encoded configuration of a made-up X25519 key offering HKDF-SHA256 with AES-128-GCM.
+/// Requests encapsulate to it, but no gateway can open them.
+pub fn config() -> Vec<u8> {
+    config_with_key_id(1)
+}
+
+/// Like [`config`], under `key_id`.
+pub fn config_with_key_id(key_id: u8) -> Vec<u8> {
+    KeyConfig::new(
+        key_id,
+        KEM_X25519_SHA256,
+        vec![1; 32],
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_128_GCM,
+        }],
+    )
+    .unwrap()
+    .encode()
+}
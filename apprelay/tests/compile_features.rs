@@ -0,0 +1,184 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Builds the library with the supported feature combinations and checks which FFI symbols
+//! each build exports. Every combination is a full cargo build, so the tests are ignored by
+//! default, run them with `cargo test --test compile_features -- --ignored`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+struct Combination {
+    name: &'static str,
+    default_features: bool,
+    features: &'static str,
+    present: &'static [&'static str],
+    absent: &'static [&'static str],
+}
+
+const JNI_SYMBOL: &str = "Java_org_platform_OHttpNativeWrapper_encapsulateRequest";
+const DEV_CONFIG_SYMBOL: &str = "apprelay_dev_config_ffi";
+
+fn cargo_build(name: &str, default_features: bool, features: &str) -> (bool, PathBuf) {
+    cargo_build_profile(name, "dev", default_features, features)
+}
+
+fn cargo_build_profile(
+    name: &str,
+    profile: &str,
+    default_features: bool,
+    features: &str,
+) -> (bool, PathBuf) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir
+        .join("target")
+        .join("feature-matrix")
+        .join(name);
+    let mut cargo = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()));
+    cargo
+        .current_dir(manifest_dir)
+        .args(["build", "--lib", "--profile", profile, "--target-dir"])
+        .arg(&target_dir);
+    if !default_features {
+        cargo.arg("--no-default-features");
+    }
+    if !features.is_empty() {
+        cargo.args(["--features", features]);
+    }
+    let status = cargo.status().expect("failed to run cargo");
+    let profile_dir = if profile == "dev" { "debug" } else { profile };
+    let library = target_dir.join(profile_dir).join(format!(
+        "{}apprelay{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ));
+    (status.success(), library)
+}
+
+fn exports(library: &[u8], symbol: &str) -> bool {
+    library
+        .windows(symbol.len())
+        .any(|window| window == symbol.as_bytes())
+}
+
+fn check(combination: &Combination) {
+    let (built, library) = cargo_build(
+        combination.name,
+        combination.default_features,
+        combination.features,
+    );
+    assert!(built, "`{}` does not build", combination.name);
+    let library = std::fs::read(&library).expect("built library not found");
+    for symbol in combination.present {
+        assert!(
+            exports(&library, symbol),
+            "`{}` does not export {}",
+            combination.name,
+            symbol
+        );
+    }
+    for symbol in combination.absent {
+        assert!(
+            !exports(&library, symbol),
+            "`{}` unexpectedly exports {}",
+            combination.name,
+            symbol
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn default_features() {
+    check(&Combination {
+        name: "default",
+        default_features: true,
+        features: "",
+        present: &[JNI_SYMBOL, "encapsulate_request_ffi", "last_error_message"],
+        absent: &[DEV_CONFIG_SYMBOL],
+    });
+}
+
+#[test]
+#[ignore]
+fn no_default_features() {
+    check(&Combination {
+        name: "no-default",
+        default_features: false,
+        features: "",
+        present: &["encapsulate_request_ffi", "last_error_message"],
+        absent: &[JNI_SYMBOL, DEV_CONFIG_SYMBOL],
+    });
+}
+
+#[test]
+#[ignore]
+fn no_error_global() {
+    check(&Combination {
+        name: "no-error-global",
+        default_features: false,
+        features: "no-error-global",
+        present: &["last_error_code"],
+        absent: &[
+            "last_error_message",
+            "last_error_length",
+            "last_error_debug_ffi",
+            JNI_SYMBOL,
+        ],
+    });
+}
+
+/// Prints the size the `no-error-global` feature saves on the size optimized shared library of
+/// this toolchain and target, run with `--nocapture` to see it.
+#[test]
+#[ignore]
+fn no_error_global_size_delta() {
+    let profile = "release-space-optimized";
+    let (built, with_global) = cargo_build_profile("size-error-global", profile, false, "");
+    assert!(built);
+    let (built, without_global) =
+        cargo_build_profile("size-no-error-global", profile, false, "no-error-global");
+    assert!(built);
+
+    let with_global = std::fs::metadata(with_global).unwrap().len();
+    let without_global = std::fs::metadata(without_global).unwrap().len();
+    println!(
+        "no-error-global: {without_global} bytes, {} bytes less than the {with_global} bytes with the error global",
+        with_global as i64 - without_global as i64
+    );
+    assert!(without_global <= with_global);
+}
+
+#[test]
+#[ignore]
+fn dev_config() {
+    check(&Combination {
+        name: "dev-config",
+        default_features: false,
+        features: "dev-config",
+        present: &[DEV_CONFIG_SYMBOL],
+        absent: &[JNI_SYMBOL],
+    });
+}
+
+#[test]
+#[ignore]
+fn dev_config_and_server() {
+    check(&Combination {
+        name: "dev-config-server",
+        default_features: true,
+        features: "dev-config,server",
+        present: &[DEV_CONFIG_SYMBOL, JNI_SYMBOL],
+        absent: &[],
+    });
+}
+
+#[test]
+#[ignore]
+fn java_rejects_no_error_global() {
+    let (built, _) = cargo_build("java-no-error-global", true, "no-error-global");
+    assert!(
+        !built,
+        "`java` and `no-error-global` must not build together"
+    );
+}
@@ -0,0 +1,760 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Parsing of key configurations through the C API.
+
+use apprelay::config::{
+    config_all_suites_ffi, config_symmetric_suite_count_ffi, config_symmetric_suite_ffi,
+    diagnose_key_config_ffi, kem_enc_len_ffi, key_config_from_svcb_param_ffi,
+    key_config_is_expired_ffi, key_config_list_merge_ffi, key_config_parse_one_ffi,
+    min_key_config_len_ffi, odoh_config_to_ohttp_ffi, preflight_encapsulate_ffi,
+    suite_description_ffi, KeyConfig, SuiteTriple, SymmetricSuite, AEAD_AES_128_GCM,
+    AEAD_AES_256_GCM, AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+    KEY_CONFIG_EXPIRED, KEY_CONFIG_NO_EXPIRY, KEY_CONFIG_VALID, MIN_KEY_CONFIG_LEN,
+    PREFLIGHT_BAD_CONFIG, PREFLIGHT_MESSAGE_TOO_LARGE, PREFLIGHT_UNSUPPORTED_SUITE,
+};
+use apprelay::error_ffi::last_error_code;
+use apprelay::{
+    encapsulate_request_ffi, encapsulate_request_with_suite_ffi, request_context_message_drop_ffi,
+    ClientError, ClientErrorKind, RequestContext,
+};
+
+fn config(key_id: u8, aeads: &[u16]) -> Vec<u8> {
+    let symmetric = aeads
+        .iter()
+        .map(|&aead| SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead,
+        })
+        .collect();
+    KeyConfig::new(key_id, KEM_X25519_SHA256, vec![key_id; 32], symmetric)
+        .unwrap()
+        .encode()
+}
+
+#[test]
+fn parse_one_walks_concatenated_configs() {
+    let configs = [
+        config(1, &[AEAD_AES_128_GCM]),
+        config(2, &[AEAD_CHACHA20_POLY1305, AEAD_AES_128_GCM]),
+        config(3, &[AEAD_CHACHA20_POLY1305]),
+    ];
+    let concatenated = configs.concat();
+
+    let mut offset = 0;
+    for expected in &configs {
+        let mut out = [0; 64];
+        let mut consumed = 0;
+        let status = unsafe {
+            key_config_parse_one_ffi(
+                concatenated[offset..].as_ptr(),
+                concatenated.len() - offset,
+                out.as_mut_ptr(),
+                out.len(),
+                &mut consumed,
+            )
+        };
+        assert_eq!(status, 0);
+        assert_eq!(consumed, expected.len());
+        assert_eq!(&out[..consumed], &expected[..]);
+        offset += consumed;
+    }
+    assert_eq!(offset, concatenated.len());
+}
+
+#[test]
+fn parse_one_rejects_truncated_config() {
+    let encoded = config(1, &[AEAD_AES_128_GCM]);
+    let mut out = [0; 64];
+    let mut consumed = 0;
+    let status = unsafe {
+        key_config_parse_one_ffi(
+            encoded.as_ptr(),
+            encoded.len() - 1,
+            out.as_mut_ptr(),
+            out.len(),
+            &mut consumed,
+        )
+    };
+    assert_eq!(status, -1);
+    assert_eq!(consumed, 0);
+}
+
+#[test]
+fn min_len_bounds_real_configs() {
+    assert_eq!(min_key_config_len_ffi(), MIN_KEY_CONFIG_LEN);
+    let smallest = config(1, &[AEAD_AES_128_GCM]);
+    assert!(smallest.len() >= MIN_KEY_CONFIG_LEN);
+    assert!(config(2, &[AEAD_CHACHA20_POLY1305, AEAD_AES_128_GCM]).len() > MIN_KEY_CONFIG_LEN);
+    KeyConfig::decode(&smallest).unwrap();
+}
+
+#[test]
+fn short_config_is_rejected_by_length() {
+    match KeyConfig::decode(&[1, 0x00, 0x20]) {
+        Err(ClientError::MalformedConfig(reason)) => assert!(reason.contains("minimum")),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn svcb_param_yields_first_supported_config() {
+    let mut unsupported = config(1, &[AEAD_AES_128_GCM]);
+    // AES-256-GCM is registered but not implemented by the rust-hpke backend.
+    unsupported[unsupported.len() - 1] = 0x02;
+    let supported = config(2, &[AEAD_CHACHA20_POLY1305]);
+    let mut param = Vec::new();
+    for encoded in [&unsupported, &supported] {
+        param.extend_from_slice(&(encoded.len() as u16).to_be_bytes());
+        param.extend_from_slice(encoded);
+    }
+
+    let mut out = [0; 64];
+    let written = unsafe {
+        key_config_from_svcb_param_ffi(param.as_ptr(), param.len(), out.as_mut_ptr(), out.len())
+    };
+    assert_eq!(written, supported.len() as isize);
+    assert_eq!(&out[..supported.len()], &supported[..]);
+
+    let truncated = &param[..param.len() - 1];
+    let written = unsafe {
+        key_config_from_svcb_param_ffi(
+            truncated.as_ptr(),
+            truncated.len(),
+            out.as_mut_ptr(),
+            out.len(),
+        )
+    };
+    assert_eq!(written, -1);
+}
+
+#[test]
+fn svcb_param_skips_config_with_unknown_kem() {
+    let mut unknown_kem = config(1, &[AEAD_AES_128_GCM]);
+    unknown_kem[1..3].copy_from_slice(&0x7777u16.to_be_bytes());
+    let supported = config(2, &[AEAD_CHACHA20_POLY1305]);
+    let mut param = Vec::new();
+    for encoded in [&unknown_kem, &supported] {
+        param.extend_from_slice(&(encoded.len() as u16).to_be_bytes());
+        param.extend_from_slice(encoded);
+    }
+
+    let mut out = [0; 64];
+    let written = unsafe {
+        key_config_from_svcb_param_ffi(param.as_ptr(), param.len(), out.as_mut_ptr(), out.len())
+    };
+    assert_eq!(written, supported.len() as isize);
+    assert_eq!(&out[..supported.len()], &supported[..]);
+
+    let param = [&(unknown_kem.len() as u16).to_be_bytes()[..], &unknown_kem].concat();
+    let written = unsafe {
+        key_config_from_svcb_param_ffi(param.as_ptr(), param.len(), out.as_mut_ptr(), out.len())
+    };
+    assert_eq!(written, -1);
+    assert_eq!(last_error_code(), ClientErrorKind::UnsupportedSuite as i32);
+}
+
+#[test]
+fn first_supported_symmetric_suite_is_selected() {
+    // AES-256-GCM is listed first but not implemented by the rust-hpke backend.
+    let config = config(5, &[AEAD_AES_256_GCM, AEAD_CHACHA20_POLY1305]);
+
+    unsafe {
+        assert_eq!(
+            config_symmetric_suite_count_ffi(config.as_ptr(), config.len()),
+            2
+        );
+        let (mut kdf, mut aead) = (0, 0);
+        let suites: Vec<_> = (0..2)
+            .map(|index| {
+                let supported = config_symmetric_suite_ffi(
+                    config.as_ptr(),
+                    config.len(),
+                    index,
+                    &mut kdf,
+                    &mut aead,
+                );
+                (kdf, aead, supported)
+            })
+            .collect();
+        assert_eq!(
+            suites,
+            [
+                (KDF_HKDF_SHA256, AEAD_AES_256_GCM, 0),
+                (KDF_HKDF_SHA256, AEAD_CHACHA20_POLY1305, 1),
+            ]
+        );
+        assert_eq!(
+            config_symmetric_suite_ffi(config.as_ptr(), config.len(), 2, &mut kdf, &mut aead),
+            -1
+        );
+    }
+
+    let selected = KeyConfig::decode(&config)
+        .unwrap()
+        .select_supported_suite()
+        .unwrap();
+    assert_eq!(selected.aead, AEAD_CHACHA20_POLY1305);
+    let context = RequestContext::new(&config, b"GET / HTTP/1.1").unwrap();
+    assert_eq!(context.suite(), Some(selected));
+}
+
+/// Guards against reintroducing a cache keyed on the configuration pointer: a buffer refilled
+/// in place with another configuration must be parsed again.
+#[test]
+fn encapsulation_follows_config_rewritten_in_place() {
+    let mut buffer = config(1, &[AEAD_AES_128_GCM]);
+    let msg = b"ping";
+    let encapsulate = |buffer: &[u8]| unsafe {
+        let context =
+            encapsulate_request_ffi(buffer.as_ptr(), buffer.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+        let selected = (
+            (*context).key_id().unwrap(),
+            (*context).suite().unwrap().aead,
+        );
+        request_context_message_drop_ffi(context);
+        selected
+    };
+
+    assert_eq!(encapsulate(&buffer), (1, AEAD_AES_128_GCM));
+    assert_eq!(encapsulate(&buffer), (1, AEAD_AES_128_GCM));
+
+    let ptr = buffer.as_ptr();
+    buffer.copy_from_slice(&config(2, &[AEAD_CHACHA20_POLY1305]));
+    assert_eq!(buffer.as_ptr(), ptr);
+    assert_eq!(encapsulate(&buffer), (2, AEAD_CHACHA20_POLY1305));
+}
+
+#[test]
+fn kem_missing_from_backend_is_named() {
+    // DHKEM(P-256, HKDF-SHA256), registered but not implemented by the rust-hpke backend.
+    const KEM_P256_SHA256: u16 = 0x0010;
+    let mut public_key = vec![0x42; 65];
+    public_key[0] = 0x04;
+    let symmetric = vec![SymmetricSuite {
+        kdf: KDF_HKDF_SHA256,
+        aead: AEAD_AES_128_GCM,
+    }];
+    let config = KeyConfig::new(5, KEM_P256_SHA256, public_key, symmetric)
+        .unwrap()
+        .encode();
+
+    assert!(matches!(
+        RequestContext::new(&config, b"ping"),
+        Err(ClientError::KemNotCompiled(KEM_P256_SHA256))
+    ));
+    let context =
+        unsafe { encapsulate_request_ffi(config.as_ptr(), config.len(), b"ping".as_ptr(), 4) };
+    assert!(context.is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::KemNotCompiled as libc::c_int
+    );
+}
+
+#[test]
+fn pinned_suite_overrides_config_order() {
+    let config = config(6, &[AEAD_AES_128_GCM, AEAD_CHACHA20_POLY1305]);
+    let msg = b"ping";
+    let encapsulate = |aead| unsafe {
+        encapsulate_request_with_suite_ffi(
+            config.as_ptr(),
+            config.len(),
+            msg.as_ptr(),
+            msg.len(),
+            KDF_HKDF_SHA256,
+            aead,
+        )
+    };
+
+    let context = encapsulate(AEAD_CHACHA20_POLY1305);
+    assert!(!context.is_null());
+    let suite = unsafe { (*context).suite() }.unwrap();
+    assert_eq!(suite.aead, AEAD_CHACHA20_POLY1305);
+    unsafe { request_context_message_drop_ffi(context) };
+
+    assert!(encapsulate(AEAD_AES_256_GCM).is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::UnsupportedSuite as libc::c_int
+    );
+    assert!(matches!(
+        RequestContext::with_suite(&config, msg, KDF_HKDF_SHA256, AEAD_AES_256_GCM),
+        Err(ClientError::UnsupportedSuite)
+    ));
+}
+
+#[test]
+fn all_suites_cover_every_symmetric_suite() {
+    let config = config(7, &[AEAD_CHACHA20_POLY1305, AEAD_AES_128_GCM]);
+    let mut out = [SuiteTriple::default(); 2];
+
+    let written = unsafe {
+        config_all_suites_ffi(config.as_ptr(), config.len(), out.as_mut_ptr(), out.len())
+    };
+    assert_eq!(written, 2);
+    let triple = |aead| SuiteTriple {
+        kem: KEM_X25519_SHA256,
+        kdf: KDF_HKDF_SHA256,
+        aead,
+    };
+    assert_eq!(
+        out,
+        [triple(AEAD_CHACHA20_POLY1305), triple(AEAD_AES_128_GCM)]
+    );
+
+    let written =
+        unsafe { config_all_suites_ffi(config.as_ptr(), config.len(), out.as_mut_ptr(), 1) };
+    assert_eq!(written, -1);
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::BufferTooSmall as libc::c_int
+    );
+}
+
+fn assert_declared_length_exceeds_buffer(config: &[u8]) {
+    match KeyConfig::decode(config) {
+        Err(ClientError::MalformedConfig(reason)) => {
+            assert!(
+                reason.starts_with("declared length exceeds buffer"),
+                "{reason}"
+            )
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    let context =
+        unsafe { encapsulate_request_ffi(config.as_ptr(), config.len(), b"ping".as_ptr(), 4) };
+    assert!(context.is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::MalformedConfig as libc::c_int
+    );
+}
+
+#[test]
+fn declared_lengths_past_the_end_are_rejected() {
+    // Suite list declaring two suites but carrying one.
+    let mut suites_past_end = config(8, &[AEAD_AES_128_GCM]);
+    suites_past_end[35..37].copy_from_slice(&8u16.to_be_bytes());
+    assert_declared_length_exceeds_buffer(&suites_past_end);
+
+    // DHKEM(X448, HKDF-SHA512) declares a 56 byte public key, only 45 bytes follow.
+    let mut public_key_past_end = vec![8, 0x00, 0x21];
+    public_key_past_end.extend_from_slice(&[0x42; 45]);
+    assert!(public_key_past_end.len() >= MIN_KEY_CONFIG_LEN);
+    assert_declared_length_exceeds_buffer(&public_key_past_end);
+
+    // Extension block declaring more bytes than present.
+    let mut extensions_past_end = config(8, &[AEAD_AES_128_GCM]);
+    extensions_past_end.extend_from_slice(&[0x00, 0x10, 0x00]);
+    assert_declared_length_exceeds_buffer(&extensions_past_end);
+}
+
+fn is_expired(config: &[u8], now_unix: i64) -> libc::c_int {
+    unsafe { key_config_is_expired_ffi(config.as_ptr(), config.len(), now_unix) }
+}
+
+#[test]
+fn expiry_extension_is_checked_against_now() {
+    let expiry = 1_700_000_000;
+    let mut expiring = KeyConfig::decode(&config(1, &[AEAD_AES_128_GCM])).unwrap();
+    expiring.expiry = Some(expiry);
+    let expiring = expiring.encode();
+    assert_eq!(KeyConfig::decode(&expiring).unwrap().expiry, Some(expiry));
+
+    assert_eq!(is_expired(&expiring, expiry - 1), KEY_CONFIG_VALID);
+    assert_eq!(is_expired(&expiring, expiry), KEY_CONFIG_EXPIRED);
+    assert_eq!(is_expired(&expiring, expiry + 86_400), KEY_CONFIG_EXPIRED);
+
+    let plain = config(1, &[AEAD_AES_128_GCM]);
+    assert_eq!(is_expired(&plain, expiry), KEY_CONFIG_NO_EXPIRY);
+
+    // An unknown extension ahead of the expiry is skipped.
+    let mut with_unknown = plain.clone();
+    with_unknown.extend_from_slice(&17u16.to_be_bytes());
+    with_unknown.extend_from_slice(&[0x7f, 0x00, 0x00, 0x01, 0xaa]);
+    with_unknown.extend_from_slice(&[0x00, 0x01, 0x00, 0x08]);
+    with_unknown.extend_from_slice(&expiry.to_be_bytes());
+    assert_eq!(is_expired(&with_unknown, expiry - 1), KEY_CONFIG_VALID);
+
+    // An unknown extension alone carries no expiry.
+    let mut unknown_only = plain;
+    unknown_only.extend_from_slice(&[0x00, 0x05, 0x7f, 0x00, 0x00, 0x01, 0xaa]);
+    assert_eq!(is_expired(&unknown_only, expiry), KEY_CONFIG_NO_EXPIRY);
+
+    assert_eq!(is_expired(&expiring[..expiring.len() - 1], expiry), -1);
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::MalformedConfig as libc::c_int
+    );
+}
+
+fn preflight(config: &[u8], msg_len: usize) -> libc::c_int {
+    unsafe { preflight_encapsulate_ffi(config.as_ptr(), config.len(), msg_len) }
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn preflight_reports_each_problem() {
+    // AES-128-GCM and AES-256-GCM both seal at most 2^36 - 32 bytes.
+    let too_large = (1 << 36) - 31;
+    let supported = config(1, &[AEAD_AES_128_GCM]);
+    let unsupported = config(1, &[AEAD_AES_256_GCM]);
+
+    assert_eq!(preflight(&supported, 1024), 0);
+    assert_eq!(preflight(&supported, too_large - 1), 0);
+    assert_eq!(
+        preflight(&supported, too_large),
+        PREFLIGHT_MESSAGE_TOO_LARGE
+    );
+    assert_eq!(preflight(&unsupported, 1024), PREFLIGHT_UNSUPPORTED_SUITE);
+    assert_eq!(
+        preflight(&unsupported, too_large),
+        PREFLIGHT_UNSUPPORTED_SUITE | PREFLIGHT_MESSAGE_TOO_LARGE
+    );
+
+    // Nothing else is checked once the configuration does not parse.
+    assert_eq!(preflight(&supported[..3], 1024), PREFLIGHT_BAD_CONFIG);
+    assert_eq!(preflight(&supported[..3], too_large), PREFLIGHT_BAD_CONFIG);
+    assert_eq!(
+        unsafe { preflight_encapsulate_ffi(std::ptr::null(), 0, 0) },
+        PREFLIGHT_BAD_CONFIG
+    );
+}
+
+fn describe(kem: u16, kdf: u16, aead: u16) -> Option<String> {
+    let mut out = [0 as libc::c_char; 128];
+    let written = unsafe { suite_description_ffi(kem, kdf, aead, out.as_mut_ptr(), out.len()) };
+    if written < 0 {
+        return None;
+    }
+    let description = unsafe { std::ffi::CStr::from_ptr(out.as_ptr()) };
+    assert_eq!(description.to_bytes().len(), written as usize);
+    Some(description.to_str().unwrap().to_owned())
+}
+
+#[test]
+fn suites_are_described_by_name() {
+    assert_eq!(
+        describe(KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_AES_128_GCM).unwrap(),
+        "DHKEM(X25519, HKDF-SHA256) / HKDF-SHA256 / AES-128-GCM"
+    );
+    assert_eq!(
+        describe(KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_CHACHA20_POLY1305).unwrap(),
+        "DHKEM(X25519, HKDF-SHA256) / HKDF-SHA256 / ChaCha20Poly1305"
+    );
+    assert_eq!(
+        describe(0x0010, 0x0001, AEAD_AES_256_GCM).unwrap(),
+        "DHKEM(P-256, HKDF-SHA256) / HKDF-SHA256 / AES-256-GCM"
+    );
+    assert_eq!(
+        describe(0x0011, 0x0002, AEAD_AES_256_GCM).unwrap(),
+        "DHKEM(P-384, HKDF-SHA384) / HKDF-SHA384 / AES-256-GCM"
+    );
+
+    assert!(describe(KEM_X25519_SHA256, KDF_HKDF_SHA256, 0x0042).is_none());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+
+    // The terminating NUL has to fit as well.
+    let description = "DHKEM(X25519, HKDF-SHA256) / HKDF-SHA256 / AES-128-GCM";
+    let mut out = vec![0 as libc::c_char; description.len()];
+    let written = unsafe {
+        suite_description_ffi(
+            KEM_X25519_SHA256,
+            KDF_HKDF_SHA256,
+            AEAD_AES_128_GCM,
+            out.as_mut_ptr(),
+            out.len(),
+        )
+    };
+    assert_eq!(written, -1);
+}
+
+#[test]
+fn enc_len_of_every_registered_kem() {
+    let registered = [
+        (0x0010, 65),
+        (0x0011, 97),
+        (0x0012, 133),
+        (KEM_X25519_SHA256, 32),
+        (0x0021, 56),
+    ];
+    for (kem, enc_len) in registered {
+        assert_eq!(kem_enc_len_ffi(kem), enc_len, "KEM {kem:#06x}");
+    }
+
+    for unknown in [0x0000, 0x0013, 0x0022, 0xffff] {
+        assert_eq!(kem_enc_len_ffi(unknown), -1);
+        assert_eq!(
+            last_error_code(),
+            ClientErrorKind::InvalidArgument as libc::c_int
+        );
+    }
+}
+
+#[test]
+fn supported_suites_of_this_build() {
+    let supported = [
+        (KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_AES_128_GCM),
+        (KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_CHACHA20_POLY1305),
+    ];
+    for (kem, kdf, aead) in supported {
+        assert!(is_suite_supported_ffi(kem, kdf, aead));
+    }
+
+    let unsupported = [
+        // Registered, but not implemented by the rust-hpke backend.
+        (KEM_X25519_SHA256, KDF_HKDF_SHA256, AEAD_AES_256_GCM),
+        (0x0010, KDF_HKDF_SHA256, AEAD_AES_128_GCM),
+        (0x0021, KDF_HKDF_SHA256, AEAD_CHACHA20_POLY1305),
+        (KEM_X25519_SHA256, 0x0002, AEAD_AES_128_GCM),
+        (KEM_X25519_SHA256, KDF_HKDF_SHA256, 0xffff),
+        // Not registered at all.
+        (0x0042, KDF_HKDF_SHA256, AEAD_AES_128_GCM),
+        (KEM_X25519_SHA256, 0x0042, AEAD_AES_128_GCM),
+        (KEM_X25519_SHA256, KDF_HKDF_SHA256, 0x0042),
+    ];
+    for (kem, kdf, aead) in unsupported {
+        assert!(
+            !is_suite_supported_ffi(kem, kdf, aead),
+            "{kem:#06x} {kdf:#06x} {aead:#06x}"
+        );
+    }
+}
+
+/// RFC 9230 `ObliviousDoHConfig` of `version` with the given suite and a 32 byte key.
+fn odoh_config(version: u16, kem: u16, kdf: u16, aead: u16, public_key: &[u8]) -> Vec<u8> {
+    let mut contents = Vec::new();
+    for id in [kem, kdf, aead, public_key.len() as u16] {
+        contents.extend_from_slice(&id.to_be_bytes());
+    }
+    contents.extend_from_slice(public_key);
+    let mut config = version.to_be_bytes().to_vec();
+    config.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+    config.extend(contents);
+    config
+}
+
+fn odoh_configs(configs: &[Vec<u8>]) -> Vec<u8> {
+    let configs = configs.concat();
+    let mut list = (configs.len() as u16).to_be_bytes().to_vec();
+    list.extend(configs);
+    list
+}
+
+fn odoh_to_ohttp(odoh: &[u8], key_id: u8) -> Option<Vec<u8>> {
+    let mut out = [0; 128];
+    let written = unsafe {
+        odoh_config_to_ohttp_ffi(
+            odoh.as_ptr(),
+            odoh.len(),
+            key_id,
+            out.as_mut_ptr(),
+            out.len(),
+        )
+    };
+    (written >= 0).then(|| out[..written as usize].to_vec())
+}
+
+#[test]
+fn odoh_config_is_translated() {
+    let public_key = [7; 32];
+    // A configuration of a future version precedes the one understood.
+    let odoh = odoh_configs(&[
+        odoh_config(0xff03, 0x0042, 0x0042, 0x0042, &[1, 2, 3]),
+        odoh_config(
+            0x0001,
+            KEM_X25519_SHA256,
+            KDF_HKDF_SHA256,
+            AEAD_AES_128_GCM,
+            &public_key,
+        ),
+    ]);
+
+    let translated = odoh_to_ohttp(&odoh, 4).unwrap();
+    let expected = KeyConfig::new(
+        4,
+        KEM_X25519_SHA256,
+        public_key.to_vec(),
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_128_GCM,
+        }],
+    )
+    .unwrap();
+    assert_eq!(KeyConfig::decode(&translated).unwrap(), expected);
+    assert!(RequestContext::new(&translated, b"GET / HTTP/1.1").is_ok());
+
+    // Export-only configurations can not seal anything.
+    let export_only = odoh_configs(&[odoh_config(
+        0x0001,
+        KEM_X25519_SHA256,
+        KDF_HKDF_SHA256,
+        0xffff,
+        &public_key,
+    )]);
+    assert!(odoh_to_ohttp(&export_only, 4).is_none());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::MalformedConfig as libc::c_int
+    );
+
+    // Neither do lists without a configuration of version 0x0001.
+    assert!(odoh_to_ohttp(&odoh_configs(&[]), 4).is_none());
+    assert!(odoh_to_ohttp(&odoh[..odoh.len() - 1], 4).is_none());
+}
+
+fn diagnose(config: &[u8]) -> String {
+    let mut out = [0 as libc::c_char; 512];
+    let len = unsafe {
+        diagnose_key_config_ffi(config.as_ptr(), config.len(), out.as_mut_ptr(), out.len())
+    };
+    assert!(len >= 0);
+    let report = unsafe { std::ffi::CStr::from_ptr(out.as_ptr()) };
+    assert_eq!(report.to_bytes().len(), len as usize);
+    report.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn diagnose_reports_every_problem() {
+    assert_eq!(diagnose(&config(1, &[AEAD_AES_128_GCM])), "");
+
+    // The same suite offered twice, then an empty extension block followed by two stray bytes.
+    let mut broken = vec![1];
+    broken.extend_from_slice(&KEM_X25519_SHA256.to_be_bytes());
+    broken.extend_from_slice(&[1; 32]);
+    broken.extend_from_slice(&8u16.to_be_bytes());
+    for _ in 0..2 {
+        broken.extend_from_slice(&KDF_HKDF_SHA256.to_be_bytes());
+        broken.extend_from_slice(&AEAD_AES_128_GCM.to_be_bytes());
+    }
+    broken.extend_from_slice(&[0, 0, 0xaa, 0xbb]);
+
+    let report = diagnose(&broken);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 2, "{report}");
+    assert!(lines[0].starts_with("duplicate suite"), "{report}");
+    assert_eq!(lines[1], "2 trailing bytes after the key configuration");
+
+    let mut small = [0 as libc::c_char; 8];
+    let status = unsafe {
+        diagnose_key_config_ffi(
+            broken.as_ptr(),
+            broken.len(),
+            small.as_mut_ptr(),
+            small.len(),
+        )
+    };
+    assert_eq!(status, -1);
+    assert_eq!(last_error_code(), ClientErrorKind::BufferTooSmall as i32);
+}
+
+/// Registered KEMs and the public key length each requires.
+const KEM_PUBLIC_KEY_LENS: [(u16, usize); 5] = [
+    (0x0010, 65),
+    (0x0011, 97),
+    (0x0012, 133),
+    (KEM_X25519_SHA256, 32),
+    (0x0021, 56),
+];
+
+fn raw_config(kem: u16, public_key_len: usize) -> Vec<u8> {
+    let mut config = vec![1];
+    config.extend_from_slice(&kem.to_be_bytes());
+    config.extend_from_slice(&vec![1; public_key_len]);
+    config.extend_from_slice(&4u16.to_be_bytes());
+    config.extend_from_slice(&KDF_HKDF_SHA256.to_be_bytes());
+    config.extend_from_slice(&AEAD_AES_128_GCM.to_be_bytes());
+    config
+}
+
+#[test]
+fn public_key_length_is_checked_against_the_kem() {
+    for (i, (kem, expected)) in KEM_PUBLIC_KEY_LENS.into_iter().enumerate() {
+        let decoded = KeyConfig::decode(&raw_config(kem, expected)).unwrap();
+        assert_eq!((decoded.kem, decoded.public_key.len()), (kem, expected));
+
+        let (_, actual) = KEM_PUBLIC_KEY_LENS[(i + 1) % KEM_PUBLIC_KEY_LENS.len()];
+        match KeyConfig::decode(&raw_config(kem, actual)) {
+            Err(ClientError::MalformedConfig(reason)) => assert_eq!(
+                reason,
+                format!(
+                    "public key of KEM {kem:#06x} must be {expected} bytes but the configuration carries {actual}"
+                )
+            ),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(matches!(
+            KeyConfig::new(1, kem, vec![1; actual], Vec::new()),
+            Err(ClientError::MalformedConfig(_))
+        ));
+    }
+}
+
+/// Encodes `configs` as an `application/ohttp-keys` list.
+fn list(configs: &[&[u8]]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for config in configs {
+        list.extend_from_slice(&(config.len() as u16).to_be_bytes());
+        list.extend_from_slice(config);
+    }
+    list
+}
+
+fn merge(a: &[u8], b: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![0; a.len() + b.len()];
+    let written = unsafe {
+        key_config_list_merge_ffi(
+            a.as_ptr(),
+            a.len(),
+            b.as_ptr(),
+            b.len(),
+            out.as_mut_ptr(),
+            out.len(),
+        )
+    };
+    out.truncate(usize::try_from(written).ok()?);
+    Some(out)
+}
+
+#[test]
+fn merging_overlapping_and_disjoint_lists() {
+    let one = config(1, &[AEAD_AES_128_GCM]);
+    let two = config(2, &[AEAD_AES_128_GCM]);
+    let two_other_suites = config(2, &[AEAD_CHACHA20_POLY1305]);
+    let three = config(3, &[AEAD_CHACHA20_POLY1305]);
+
+    assert_eq!(
+        merge(&list(&[&one, &two]), &list(&[&three])).unwrap(),
+        list(&[&one, &two, &three])
+    );
+    // Key id 2 with the same key is a duplicate, the first list's entry wins.
+    assert_eq!(
+        merge(&list(&[&one, &two]), &list(&[&two_other_suites, &three])).unwrap(),
+        list(&[&one, &two, &three])
+    );
+    assert_eq!(merge(&list(&[&one]), &[]).unwrap(), list(&[&one]));
+
+    let mut two_other_key = KeyConfig::decode(&two).unwrap();
+    two_other_key.public_key = vec![9; 32];
+    let two_other_key = two_other_key.encode();
+    assert_eq!(merge(&list(&[&two]), &list(&[&two_other_key])), None);
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::MalformedConfig as libc::c_int
+    );
+
+    let truncated = list(&[&one]);
+    assert_eq!(
+        merge(&truncated[..truncated.len() - 1], &list(&[&two])),
+        None
+    );
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::MalformedConfig as libc::c_int
+    );
+}
@@ -0,0 +1,62 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The plaintext kept by `debug-plaintext` builds.
+//! Run with `cargo test --features debug-plaintext --test debug_plaintext`.
+
+use apprelay::{
+    encapsulate_request_ffi, encapsulate_request_padded_ffi, request_context_message_drop_ffi,
+    request_context_plaintext_ffi, RequestContext,
+};
+
+mod common;
+use common::config;
+
+unsafe fn plaintext(context: *const RequestContext, out_cap: usize) -> Option<Vec<u8>> {
+    let mut out = vec![0; out_cap];
+    let written = request_context_plaintext_ffi(context, out.as_mut_ptr(), out.len());
+    if written < 0 {
+        return None;
+    }
+    out.truncate(written as usize);
+    Some(out)
+}
+
+#[test]
+fn plaintext_round_trips() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+        assert_eq!((*context).plaintext(), msg);
+        assert_eq!(plaintext(context, 64).unwrap(), msg);
+        assert_eq!(plaintext(context, msg.len()).unwrap(), msg);
+        assert!(plaintext(context, msg.len() - 1).is_none());
+        request_context_message_drop_ffi(context);
+
+        // The padding is part of the plaintext that was sealed.
+        let context = encapsulate_request_padded_ffi(
+            config.as_ptr(),
+            config.len(),
+            msg.as_ptr(),
+            msg.len(),
+            32,
+        );
+        let padded = plaintext(context, 64).unwrap();
+        assert_eq!(padded.len(), 32);
+        assert_eq!(&padded[..msg.len()], msg);
+        assert!(padded[msg.len()..].iter().all(|&byte| byte == 0));
+        request_context_message_drop_ffi(context);
+    }
+
+    // A context reassembled from its parts never saw the plaintext.
+    let (request, decapsulator) = RequestContext::new(&config, msg)
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    let context =
+        RequestContext::from_parts(&config, &request[..39], &request[39..], decapsulator).unwrap();
+    assert!(context.plaintext().is_empty());
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Hex conversion and curl commands of encapsulated requests for support tooling.
+//! Run with `cargo test --features debug-tools --test debug_tools`.
+
+use std::ffi::{CStr, CString};
+
+use apprelay::config::{
+    KeyConfig, SymmetricSuite, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+use apprelay::debug_tools::{
+    bytes_to_hex_ffi, curl_command, from_hex, hex_to_bytes_ffi, request_context_as_curl_ffi,
+};
+use apprelay::{encapsulate_request_ffi, request_context_message_drop_ffi, ClientError};
+
+fn invalid_argument(hex: &str) -> String {
+    match from_hex(hex) {
+        Err(ClientError::InvalidArgument(reason)) => reason,
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn hex_round_trip() {
+    let bytes: Vec<u8> = (0..=255).collect();
+    let mut hex = vec![0; 2 * bytes.len() + 1];
+    let written =
+        unsafe { bytes_to_hex_ffi(bytes.as_ptr(), bytes.len(), hex.as_mut_ptr(), hex.len()) };
+    assert_eq!(written, 2 * bytes.len() as isize);
+    assert_eq!(hex.last(), Some(&0));
+
+    let mut decoded = vec![0; bytes.len()];
+    let written = unsafe { hex_to_bytes_ffi(hex.as_ptr(), decoded.as_mut_ptr(), decoded.len()) };
+    assert_eq!(written, bytes.len() as isize);
+    assert_eq!(decoded, bytes);
+}
+
+#[test]
+fn hex_dumps_may_be_wrapped_and_upper_case() {
+    assert_eq!(
+        from_hex("01 AB\n  cd ef\n").unwrap(),
+        [0x01, 0xab, 0xcd, 0xef]
+    );
+}
+
+#[test]
+fn odd_length_is_rejected() {
+    assert!(invalid_argument("abc").contains("odd number of hex digits"));
+}
+
+#[test]
+fn invalid_character_position_is_reported() {
+    assert!(invalid_argument("0a1g").contains("'g' at position 3"));
+    let mut out = [0; 4];
+    let written = unsafe { hex_to_bytes_ffi(b"zz\0".as_ptr().cast(), out.as_mut_ptr(), out.len()) };
+    assert_eq!(written, -1);
+}
+
+#[test]
+fn curl_command_posts_the_request_to_the_url() {
+    let command = curl_command(
+        &[0x00, 0x01, 0x02, 0x03, 0xff],
+        "https://relay.example/gateway",
+    );
+    assert_eq!(
+        command,
+        "echo 'AAECA/8=' | base64 -d | curl -X POST --data-binary @- \
+         -H 'Content-Type: message/ohttp-req' 'https://relay.example/gateway'"
+    );
+
+    // Quotes in the URL can not end the shell word early.
+    let command = curl_command(b"", "https://relay.example/it's");
+    assert!(command.ends_with(r"'https://relay.example/it'\''s'"));
+}
+
+#[test]
+fn curl_command_of_a_context() {
+    let config = KeyConfig::new(
+        1,
+        KEM_X25519_SHA256,
+        vec![1; 32],
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_128_GCM,
+        }],
+    )
+    .unwrap()
+    .encode();
+    let url = CString::new("https://relay.example/gateway").unwrap();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        let mut out = vec![0; 1024];
+        let written =
+            request_context_as_curl_ffi(context, url.as_ptr(), out.as_mut_ptr(), out.len());
+        assert!(written > 0);
+        let command = CStr::from_ptr(out.as_ptr()).to_str().unwrap();
+        assert_eq!(command.len(), written as usize);
+        assert_eq!(
+            command,
+            curl_command(
+                (*context).encapsulated_request(),
+                "https://relay.example/gateway"
+            )
+        );
+        assert!(command.contains("-H 'Content-Type: message/ohttp-req'"));
+        assert!(command.ends_with(" 'https://relay.example/gateway'"));
+
+        // The NUL terminator has to fit as well.
+        assert_eq!(
+            request_context_as_curl_ffi(context, url.as_ptr(), out.as_mut_ptr(), written as usize),
+            -1
+        );
+        request_context_message_drop_ffi(context);
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! One-time warnings of deprecated FFI functions.
+//! Run with `cargo test --features testutil --test deprecation`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use apprelay::deprecation::apprelay_deprecation_probe_ffi;
+use apprelay::split::apprelay_free_bytes_ffi;
+use apprelay::{response_context_from_plaintext_ffi, response_context_take_bytes_ffi};
+use log::{LevelFilter, Log, Metadata, Record};
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static TAKE_BYTES_WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingLogger;
+
+impl Log for CountingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let message = record.args().to_string();
+        if message.contains("apprelay_deprecation_probe_ffi") {
+            WARNINGS.fetch_add(1, Ordering::SeqCst);
+        }
+        if message.contains("response_context_take_bytes_ffi is deprecated")
+            && message.contains("response_context_copy_ffi")
+        {
+            TAKE_BYTES_WARNINGS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// A single test, the logger and the warning are process wide.
+#[test]
+fn deprecation_warning_fires_once() {
+    log::set_logger(&CountingLogger).unwrap();
+    log::set_max_level(LevelFilter::Warn);
+
+    let callers: Vec<_> = (0..8)
+        .map(|_| thread::spawn(apprelay_deprecation_probe_ffi))
+        .collect();
+    for caller in callers {
+        caller.join().unwrap();
+    }
+    apprelay_deprecation_probe_ffi();
+
+    assert_eq!(WARNINGS.load(Ordering::SeqCst), 1);
+
+    for _ in 0..2 {
+        let plaintext = b"pong";
+        unsafe {
+            let context = response_context_from_plaintext_ffi(plaintext.as_ptr(), plaintext.len());
+            let mut len = 0;
+            let bytes = response_context_take_bytes_ffi(context, &mut len);
+            assert_eq!(std::slice::from_raw_parts(bytes, len), plaintext);
+            apprelay_free_bytes_ffi(bytes, len);
+        }
+    }
+    assert_eq!(TAKE_BYTES_WARNINGS.load(Ordering::SeqCst), 1);
+}
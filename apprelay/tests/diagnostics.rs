@@ -0,0 +1,33 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Accounting of the bytes held by live contexts.
+//! Run with `cargo test --features diagnostics,testutil --test diagnostics`.
+
+use apprelay::diagnostics::apprelay_live_context_bytes_ffi;
+use apprelay::{
+    request_context_message_drop_ffi, request_context_new_ffi, response_context_drop_ffi,
+    response_context_from_plaintext_ffi,
+};
+
+// A single test, the counter is process wide and parallel tests would disturb it.
+#[test]
+fn live_bytes_return_to_baseline() {
+    let baseline = apprelay_live_context_bytes_ffi();
+
+    let request = request_context_new_ffi();
+    let with_request = apprelay_live_context_bytes_ffi();
+    assert!(with_request > baseline);
+
+    let plaintext = [0u8; 1024];
+    let response =
+        unsafe { response_context_from_plaintext_ffi(plaintext.as_ptr(), plaintext.len()) };
+    assert!(apprelay_live_context_bytes_ffi() >= with_request + plaintext.len());
+
+    unsafe {
+        response_context_drop_ffi(response);
+        assert_eq!(apprelay_live_context_bytes_ffi(), with_request);
+        request_context_message_drop_ffi(request);
+    }
+    assert_eq!(apprelay_live_context_bytes_ffi(), baseline);
+}
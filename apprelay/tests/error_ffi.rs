@@ -0,0 +1,61 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Reporting of the last error through the FFI.
+
+use std::ffi::CStr;
+
+use apprelay::error_ffi::{
+    last_error_code, last_error_debug_ffi, last_error_message, take_last_error, update_last_error,
+};
+use apprelay::{decapsulate_response_ffi, encapsulate_request_ffi, ClientError, ClientErrorKind};
+
+mod common;
+use common::config;
+
+#[test]
+fn debug_form_includes_the_ohttp_error() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    // Long enough to be opened, but not produced by a gateway.
+    let response = [0u8; 64];
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+        let response_context = decapsulate_response_ffi(context, response.as_ptr(), response.len());
+        assert!(response_context.is_null());
+
+        let mut debug = vec![0; 1024];
+        assert_eq!(last_error_debug_ffi(debug.as_mut_ptr(), 4), -1);
+        let written = last_error_debug_ffi(debug.as_mut_ptr(), debug.len());
+        assert!(written > 0);
+        let debug = CStr::from_ptr(debug.as_ptr()).to_str().unwrap().to_owned();
+        assert_eq!(debug.len(), written as usize);
+
+        let mut display = vec![0; 1024];
+        assert!(last_error_message(display.as_mut_ptr(), display.len() as libc::c_int) > 0);
+        let display = CStr::from_ptr(display.as_ptr()).to_str().unwrap();
+
+        // The variant and its `ohttp::Error` source, which the message leaves out.
+        assert!(debug.starts_with("ResponseAuthFailed("), "{debug}");
+        assert!(!display.contains("ResponseAuthFailed("), "{display}");
+
+        // last_error_message took the error.
+        let mut empty = [0; 1];
+        assert_eq!(last_error_debug_ffi(empty.as_mut_ptr(), empty.len()), 0);
+    }
+}
+
+#[test]
+fn errors_of_other_types_keep_their_message() {
+    update_last_error(ClientError::ContextEmpty);
+    assert_eq!(last_error_code(), ClientErrorKind::ContextEmpty as i32);
+
+    update_last_error(std::io::Error::other("embedder failure"));
+    assert_eq!(last_error_code(), -1);
+    let err = take_last_error().unwrap();
+    assert_eq!(err.to_string(), "embedder failure");
+    assert!(take_last_error().is_none());
+    assert_eq!(last_error_code(), 0);
+}
@@ -0,0 +1,72 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Fingerprints of encapsulated requests through the Rust API and
+//! `request_context_fingerprint_ffi`.
+
+use apprelay::{
+    encapsulate_request_ffi, request_context_fingerprint_ffi, request_context_message_drop_ffi,
+    RequestContext, REQUEST_FINGERPRINT_LEN,
+};
+
+mod common;
+use common::config;
+
+#[test]
+fn fingerprint_is_the_sha256_of_the_request() {
+    let config = config();
+    let (_, decapsulator) = RequestContext::new(&config, b"GET / HTTP/1.1")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    // Key id 1, X25519, HKDF-SHA256 and AES-128-GCM, then a zero `enc` and "abc", so the
+    // digest does not depend on the randomness of encapsulation.
+    let mut header = vec![1, 0x00, 0x20, 0x00, 0x01, 0x00, 0x01];
+    header.extend_from_slice(&[0; 32]);
+    let context = RequestContext::from_parts(&config, &header, b"abc", decapsulator).unwrap();
+
+    let expected = "dc0cc7c9d1a9ad757cfbcf0af3900b24cb822e1c57fa64418972a27c221d8289";
+    let hex: String = context
+        .fingerprint()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    assert_eq!(hex, expected);
+}
+
+#[test]
+fn fingerprint_is_stable_across_calls() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+
+        let mut first = [0; REQUEST_FINGERPRINT_LEN];
+        let mut second = [0; REQUEST_FINGERPRINT_LEN];
+        assert_eq!(
+            request_context_fingerprint_ffi(context, first.as_mut_ptr(), first.len()),
+            REQUEST_FINGERPRINT_LEN as libc::ssize_t
+        );
+        assert_eq!(
+            request_context_fingerprint_ffi(context, second.as_mut_ptr(), second.len()),
+            REQUEST_FINGERPRINT_LEN as libc::ssize_t
+        );
+        assert_eq!(first, second);
+        assert_eq!(first, (*context).fingerprint());
+
+        let mut short = [0; REQUEST_FINGERPRINT_LEN - 1];
+        assert_eq!(
+            request_context_fingerprint_ffi(context, short.as_mut_ptr(), short.len()),
+            -1
+        );
+
+        // Encapsulation is randomized, so another request of the same message differs.
+        let other = encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert_ne!((*other).fingerprint(), first);
+
+        request_context_message_drop_ffi(other);
+        request_context_message_drop_ffi(context);
+    }
+}
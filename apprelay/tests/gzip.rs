@@ -0,0 +1,110 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Gzip compressed request bodies from `BhttpRequest::encode_gzip`.
+//! Run with `cargo test --features gzip,server --test gzip`.
+
+use std::io::Read;
+
+use apprelay::bhttp::BhttpRequest;
+use apprelay::server::TestServer;
+use apprelay::{ClientError, RequestContext};
+use flate2::read::GzDecoder;
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+
+/// Reads a QUIC variable-length integer.
+fn read_varint(data: &mut &[u8]) -> usize {
+    let len = 1 << (data[0] >> 6);
+    let mut value = (data[0] & 0x3f) as usize;
+    for b in &data[1..len] {
+        value = (value << 8) | *b as usize;
+    }
+    *data = &data[len..];
+    value
+}
+
+fn read_length_prefixed<'a>(data: &mut &'a [u8]) -> &'a [u8] {
+    let len = read_varint(data);
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    bytes
+}
+
+/// Header fields and content of a known-length BHTTP request.
+fn parse_request(mut data: &[u8]) -> (Vec<(String, String)>, Vec<u8>) {
+    assert_eq!(read_varint(&mut data), 0, "known-length request framing");
+    for _ in 0..4 {
+        read_length_prefixed(&mut data);
+    }
+    let mut section = read_length_prefixed(&mut data);
+    let mut headers = Vec::new();
+    while !section.is_empty() {
+        let name = read_length_prefixed(&mut section);
+        let value = read_length_prefixed(&mut section);
+        headers.push((
+            String::from_utf8(name.to_vec()).unwrap(),
+            String::from_utf8(value.to_vec()).unwrap(),
+        ));
+    }
+    let content = read_length_prefixed(&mut data).to_vec();
+    (headers, content)
+}
+
+#[test]
+fn gateway_receives_a_gzip_body() {
+    let config = KeyConfig::new(
+        1,
+        Kem::X25519Sha256,
+        vec![SymmetricSuite::new(Kdf::HkdfSha256, Aead::Aes128Gcm)],
+    )
+    .unwrap();
+    let encoded_config = config.encode().unwrap();
+    let mut server = TestServer::new(config).unwrap();
+
+    let body =
+        br#"{"events":["open","open","open","open","open","open","open","open"]}"#.repeat(64);
+    let msg = BhttpRequest::new("POST", "https", "example.com", "/events")
+        .header("content-type", "application/json")
+        .encode_gzip(&body)
+        .unwrap();
+    let (request, _) = RequestContext::new(&encoded_config, &msg)
+        .unwrap()
+        .into_parts()
+        .unwrap();
+
+    let mut received = None;
+    server
+        .handle(&request, |request| {
+            received = Some(request.to_vec());
+            Vec::new()
+        })
+        .unwrap();
+
+    let (headers, content) = parse_request(&received.unwrap());
+    assert_eq!(
+        headers,
+        [
+            ("content-type".to_owned(), "application/json".to_owned()),
+            ("content-encoding".to_owned(), "gzip".to_owned()),
+        ]
+    );
+    assert!(content.len() < body.len());
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&content[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, body);
+}
+
+#[test]
+fn existing_content_encoding_is_rejected() {
+    let request =
+        BhttpRequest::new("POST", "https", "example.com", "/").header("Content-Encoding", "br");
+    match request.encode_gzip(b"already compressed") {
+        Err(ClientError::InvalidArgument(reason)) => {
+            assert_eq!(reason, "body already has content-encoding br")
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
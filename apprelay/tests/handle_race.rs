@@ -0,0 +1,116 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Accessors racing with the call consuming their context.
+//!
+//! Meant to run under ThreadSanitizer as well, which needs a nightly toolchain:
+//!
+//! ```sh
+//! RUSTFLAGS=-Zsanitizer=thread cargo +nightly test -Zbuild-std \
+//!     --target x86_64-unknown-linux-gnu --test handle_race
+//! ```
+
+use apprelay::error_ffi::last_error_code;
+use apprelay::{
+    decapsulate_response_ffi, encapsulate_request_ffi, request_context_fingerprint_ffi,
+    request_context_message_drop_ffi, request_context_message_len_ffi, ClientErrorKind,
+    RequestContext,
+};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+mod common;
+use common::config;
+
+const ROUNDS: usize = 200;
+const ACCESSORS: usize = 4;
+const ACCESSES: usize = 500;
+
+fn assert_invalid_handle() {
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidHandle as libc::c_int
+    );
+}
+
+/// Reads the context behind `handle` until it is gone. Every access either sees the whole
+/// request or fails with `InvalidHandle`, and once it failed the handle stays invalid.
+fn access(handle: usize, request_len: usize) {
+    let context = handle as *mut RequestContext;
+    let mut consumed = false;
+    for i in 0..ACCESSES {
+        unsafe {
+            if i % 2 == 0 {
+                match request_context_message_len_ffi(context) {
+                    0 => {
+                        assert_invalid_handle();
+                        consumed = true;
+                    }
+                    len => {
+                        assert!(!consumed, "handle valid again after it was consumed");
+                        assert_eq!(len, request_len);
+                    }
+                }
+            } else {
+                let mut fingerprint = [0; 32];
+                match request_context_fingerprint_ffi(
+                    context,
+                    fingerprint.as_mut_ptr(),
+                    fingerprint.len(),
+                ) {
+                    -1 => {
+                        assert_invalid_handle();
+                        consumed = true;
+                    }
+                    written => {
+                        assert!(!consumed, "handle valid again after it was consumed");
+                        assert_eq!(written, 32);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A single test, a consumed context's address may be handed out again to a context of a
+// parallel test, which would make the handle valid again for the accessors.
+#[test]
+fn accessors_race_with_consumption() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    for round in 0..ROUNDS {
+        let context = unsafe {
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len())
+        };
+        assert!(!context.is_null());
+        let request_len = unsafe { request_context_message_len_ffi(context) };
+        let handle = context as usize;
+
+        let start = Arc::new(Barrier::new(ACCESSORS + 1));
+        let accessors: Vec<_> = (0..ACCESSORS)
+            .map(|_| {
+                let start = start.clone();
+                thread::spawn(move || {
+                    start.wait();
+                    access(handle, request_len);
+                })
+            })
+            .collect();
+
+        start.wait();
+        unsafe {
+            if round % 2 == 0 {
+                request_context_message_drop_ffi(context);
+            } else {
+                // The response need not be valid, decapsulation consumes the context anyway.
+                let response = [0; 64];
+                assert!(
+                    decapsulate_response_ffi(context, response.as_ptr(), response.len()).is_null()
+                );
+            }
+        }
+        for accessor in accessors {
+            accessor.join().unwrap();
+        }
+    }
+}
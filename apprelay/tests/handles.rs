@@ -0,0 +1,67 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Rejection of request context handles that were already freed.
+
+use apprelay::error_ffi::{last_error_code, take_last_error};
+use apprelay::{
+    decapsulate_response_ffi, encapsulate_request_ffi, request_context_message_drop_ffi,
+    request_context_message_ffi, request_context_message_len_ffi, request_context_new_ffi,
+    ClientErrorKind,
+};
+
+mod common;
+use common::config;
+
+fn assert_invalid_handle() {
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidHandle as libc::c_int
+    );
+    take_last_error();
+}
+
+// A single test, a freed context's address may be handed out again to a context of a
+// parallel test, which would make the stale handle valid.
+#[test]
+fn freed_handles_are_rejected() {
+    let config = config();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        // Freeing twice.
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+        request_context_message_drop_ffi(context);
+        assert!(take_last_error().is_none());
+        request_context_message_drop_ffi(context);
+        assert_invalid_handle();
+
+        // Using a handle after it was freed.
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        request_context_message_drop_ffi(context);
+        assert!(request_context_message_ffi(context).is_null());
+        assert_invalid_handle();
+        assert_eq!(request_context_message_len_ffi(context), 0);
+        assert_invalid_handle();
+
+        // Using a handle after decapsulation consumed it, the response need not be valid.
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        let response = [0; 64];
+        assert!(decapsulate_response_ffi(context, response.as_ptr(), response.len()).is_null());
+        take_last_error();
+        assert_eq!(request_context_message_len_ffi(context), 0);
+        assert_invalid_handle();
+        request_context_message_drop_ffi(context);
+        assert_invalid_handle();
+
+        // A pooled context is freed only by dropping it, once.
+        let pooled = request_context_new_ffi();
+        request_context_message_drop_ffi(pooled);
+        assert!(take_last_error().is_none());
+        request_context_message_drop_ffi(pooled);
+        assert_invalid_handle();
+    }
+}
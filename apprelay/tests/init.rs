@@ -0,0 +1,45 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The process lifecycle hooks `apprelay_init_ffi` and `apprelay_shutdown_ffi`.
+
+use apprelay::config::{
+    KeyConfig, SymmetricSuite, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+use apprelay::{apprelay_init_ffi, apprelay_shutdown_ffi, RequestContext};
+
+fn encapsulates() -> bool {
+    let config = KeyConfig::new(
+        1,
+        KEM_X25519_SHA256,
+        vec![1; 32],
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_128_GCM,
+        }],
+    )
+    .unwrap()
+    .encode();
+    RequestContext::new(&config, b"GET / HTTP/1.1").is_ok()
+}
+
+// A single test, initialization happens once per process.
+#[test]
+fn init_is_idempotent() {
+    // The rust-hpke backend works before initialization.
+    assert!(encapsulates());
+
+    assert_eq!(apprelay_init_ffi(), 0);
+    assert_eq!(apprelay_init_ffi(), 0);
+    assert!(encapsulates());
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(apprelay_init_ffi))
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 0);
+    }
+    assert!(encapsulates());
+
+    apprelay_shutdown_ffi();
+}
@@ -0,0 +1,1203 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Full exchanges against an in-process `ohttp::Server` with a freshly generated key pair,
+//! for every suite this build supports. Run with `cargo test --features interop-tests`.
+
+use apprelay::bhttp::{build_bhttp_request_ffi, BhttpRequest};
+use apprelay::config::{
+    build_key_config_ffi, HpkeSuite, AEAD_AES_256_GCM, AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256,
+    KEM_X25519_SHA256,
+};
+use apprelay::error_ffi::last_error_code;
+use apprelay::grpc::{encapsulate_grpc_request_ffi, GRPC_MESSAGE_PREFIX_LEN};
+use apprelay::multihop::{
+    decapsulate_double_response_ffi, double_request_context_message_ffi, encapsulate_double_ffi,
+};
+use apprelay::multiplex::{
+    decapsulate_multiplexed_ffi, response_context_array_drop_ffi, response_context_array_error_ffi,
+    response_context_array_get_ffi, response_context_array_len_ffi,
+};
+use apprelay::server::TestServer;
+use apprelay::split::{
+    apprelay_free_bytes_ffi, encapsulate_split_ffi, request_context_from_parts_ffi,
+    response_decapsulator_decapsulate_ffi,
+};
+use apprelay::{
+    decapsulate_response_cb_ffi, decapsulate_response_ffi, decapsulate_response_framed_ffi,
+    encapsulate_into_context_ffi, encapsulate_request_ffi, encapsulate_request_full_ffi,
+    encapsulate_request_padded_ffi, reencapsulate_ffi, request_context_header_ffi,
+    request_context_message_drop_ffi, request_context_message_ffi, request_context_message_len_ffi,
+    request_context_new_ffi, request_context_post_body_len_ffi, response_context_copy_ffi,
+    response_context_drop_ffi, response_context_message_ffi, response_context_message_len_ffi,
+    response_context_take_bytes_ffi, strip_response_framing, ClientError, ClientErrorKind,
+    OhttpHeader, RequestContext, RESPONSE_CHUNK_LEN, RESPONSE_FRAMING_NONE, RESPONSE_FRAMING_U16,
+    RESPONSE_FRAMING_U32,
+};
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, Server, SymmetricSuite};
+use std::ffi::{c_void, CString};
+use std::mem::MaybeUninit;
+
+const KEY_ID: u8 = 7;
+
+const SUITES: [(Kdf, Aead); 2] = [
+    (Kdf::HkdfSha256, Aead::Aes128Gcm),
+    (Kdf::HkdfSha256, Aead::ChaCha20Poly1305),
+];
+
+fn payloads() -> Vec<Vec<u8>> {
+    vec![
+        Vec::new(),
+        b"GET / HTTP/1.1".to_vec(),
+        (0..=255).collect(),
+        vec![0; 64 * 1024],
+    ]
+}
+
+/// Encoded configuration for the client and the gateway holding its private key.
+fn gateway(kdf: Kdf, aead: Aead) -> (Vec<u8>, TestServer) {
+    let config = KeyConfig::new(
+        KEY_ID,
+        Kem::X25519Sha256,
+        vec![SymmetricSuite::new(kdf, aead)],
+    )
+    .unwrap();
+    let encoded = config.encode().unwrap();
+    (encoded, TestServer::new(config).unwrap())
+}
+
+/// Echoes the request reversed, so a response could not pass for the request.
+fn respond(request: &[u8]) -> Vec<u8> {
+    request.iter().rev().copied().collect()
+}
+
+#[test]
+fn round_trip_every_suite() {
+    for (kdf, aead) in SUITES {
+        let (config, mut server) = gateway(kdf, aead);
+        for payload in payloads() {
+            let (request, decapsulator) = RequestContext::new(&config, &payload)
+                .unwrap()
+                .into_parts()
+                .unwrap();
+
+            let mut received = None;
+            let response = server
+                .handle(&request, |request| {
+                    received = Some(request.to_vec());
+                    respond(request)
+                })
+                .unwrap();
+
+            assert_eq!(received.as_deref(), Some(&payload[..]), "{:?}", (kdf, aead));
+            assert_eq!(
+                decapsulator.decapsulate(&response).unwrap(),
+                respond(&payload),
+                "{:?}",
+                (kdf, aead)
+            );
+        }
+    }
+}
+
+#[test]
+fn round_trip_through_ffi() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    for payload in payloads() {
+        unsafe {
+            let context = encapsulate_request_ffi(
+                config.as_ptr(),
+                config.len(),
+                payload.as_ptr(),
+                payload.len(),
+            );
+            assert!(!context.is_null());
+
+            let request = (*context).encapsulated_request().to_vec();
+            let response = server.handle(&request, respond).unwrap();
+
+            let response_context =
+                decapsulate_response_ffi(context, response.as_ptr(), response.len());
+            assert!(!response_context.is_null());
+            let len = response_context_message_len_ffi(response_context);
+            let message =
+                std::slice::from_raw_parts(response_context_message_ffi(response_context), len);
+            assert_eq!(message, &respond(&payload)[..]);
+            response_context_drop_ffi(response_context);
+        }
+    }
+}
+
+#[test]
+fn header_matches_the_config_and_enc() {
+    for (kdf, aead) in SUITES {
+        let (config, mut server) = gateway(kdf, aead);
+        unsafe {
+            let context = encapsulate_request_ffi(config.as_ptr(), config.len(), b"x".as_ptr(), 1);
+            assert!(!context.is_null());
+            let mut header = MaybeUninit::<OhttpHeader>::uninit();
+            assert_eq!(request_context_header_ffi(context, header.as_mut_ptr()), 0);
+            let header = header.assume_init();
+
+            assert_eq!(header.key_id, KEY_ID);
+            assert_eq!(header.kem, Kem::X25519Sha256 as u16);
+            assert_eq!(header.kdf, kdf as u16);
+            assert_eq!(header.aead, aead as u16);
+
+            // The enc is the key share following the 7 byte header, which the gateway
+            // must accept for the request to open.
+            let request = std::slice::from_raw_parts(
+                request_context_message_ffi(context),
+                request_context_message_len_ffi(context),
+            );
+            let enc = std::slice::from_raw_parts(header.enc_ptr, header.enc_len);
+            assert_eq!(enc.len(), 32);
+            assert_eq!(enc, &request[7..7 + 32]);
+            assert!(server.handle(request, respond).is_ok(), "{:?}", (kdf, aead));
+
+            request_context_message_drop_ffi(context);
+        }
+    }
+
+    let empty = request_context_new_ffi();
+    let mut header = MaybeUninit::<OhttpHeader>::uninit();
+    unsafe {
+        assert_eq!(request_context_header_ffi(empty, header.as_mut_ptr()), -1);
+        request_context_message_drop_ffi(empty);
+    }
+}
+
+#[test]
+fn decapsulated_response_is_copied_out_before_the_drop() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = b"GET / HTTP/1.1";
+    unsafe {
+        let context = encapsulate_request_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        assert!(!context.is_null());
+        let request = (*context).encapsulated_request().to_vec();
+        let response = server.handle(&request, respond).unwrap();
+        let response_context = decapsulate_response_ffi(context, response.as_ptr(), response.len());
+        assert!(!response_context.is_null());
+
+        // Query the length, allocate exactly that much and copy, as a C caller does.
+        let len = response_context_message_len_ffi(response_context);
+        assert_eq!(len, payload.len());
+        let mut copy = vec![0; len];
+        assert_eq!(
+            response_context_copy_ffi(response_context, copy.as_mut_ptr(), len - 1),
+            -1
+        );
+        assert_eq!(last_error_code(), ClientErrorKind::BufferTooSmall as i32);
+        assert_eq!(
+            response_context_copy_ffi(response_context, copy.as_mut_ptr(), len),
+            len as isize
+        );
+        assert_eq!(copy, respond(payload));
+
+        // Neither call consumed the context, it is still readable and freed exactly once.
+        assert_eq!(response_context_message_len_ffi(response_context), len);
+        response_context_drop_ffi(response_context);
+    }
+}
+
+#[test]
+fn response_for_another_request_is_rejected() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let (_, decapsulator) = RequestContext::new(&config, b"first")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    let (other, _) = RequestContext::new(&config, b"second")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+
+    let response = server.handle(&other, respond).unwrap();
+    assert!(matches!(
+        decapsulator.decapsulate(&response),
+        Err(ClientError::ResponseAuthFailed(_))
+    ));
+}
+
+#[test]
+fn altered_response_fails_authentication() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let (request, decapsulator) = RequestContext::new(&config, b"GET / HTTP/1.1")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+
+    let mut response = server.handle(&request, respond).unwrap();
+    let last = response.len() - 1;
+    response[last] ^= 1;
+    assert!(matches!(
+        decapsulator.decapsulate(&response),
+        Err(ClientError::ResponseAuthFailed(_))
+    ));
+}
+
+#[test]
+fn responses_at_and_below_the_minimum_length() {
+    for (kdf, aead) in SUITES {
+        let (config, mut server) = gateway(kdf, aead);
+        let context = RequestContext::new(&config, b"GET / HTTP/1.1").unwrap();
+        let minimum = context.suite().unwrap().min_response_len().unwrap();
+        let (request, decapsulator) = context.into_parts().unwrap();
+
+        // An empty response is exactly the response nonce and the AEAD tag.
+        let response = server.handle(&request, |_| Vec::new()).unwrap();
+        assert_eq!(response.len(), minimum);
+        match decapsulator.decapsulate(&response[..minimum - 1]) {
+            Err(ClientError::ResponseTruncated { expected, actual }) => {
+                assert_eq!((expected, actual), (minimum, minimum - 1));
+            }
+            other => panic!("expected ResponseTruncated, got {:?}", other.err()),
+        }
+
+        let (request, decapsulator) = RequestContext::new(&config, b"GET / HTTP/1.1")
+            .unwrap()
+            .into_parts()
+            .unwrap();
+        let response = server.handle(&request, |_| Vec::new()).unwrap();
+        assert_eq!(decapsulator.decapsulate(&response).unwrap(), b"");
+    }
+}
+
+#[test]
+fn padded_requests_land_on_bucket_boundaries() {
+    const BUCKET: usize = 64;
+    // Header, X25519 enc and AES-128-GCM tag.
+    const OVERHEAD: usize = 7 + 32 + 16;
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let request = BhttpRequest::new("POST", "https", "example.com", "/query");
+    for body_len in [0, 1, 40, 41, 42, 100, 105] {
+        let message = request.encode(&vec![b'a'; body_len]);
+        let context = unsafe {
+            encapsulate_request_padded_ffi(
+                config.as_ptr(),
+                config.len(),
+                message.as_ptr(),
+                message.len(),
+                BUCKET,
+            )
+        };
+        assert!(!context.is_null());
+        let encapsulated = unsafe {
+            std::slice::from_raw_parts(
+                request_context_message_ffi(context),
+                request_context_message_len_ffi(context),
+            )
+        };
+        let padded_len = message.len().div_ceil(BUCKET) * BUCKET;
+        assert_eq!(encapsulated.len(), OVERHEAD + padded_len);
+
+        // The gateway sees the message followed by nothing but zeros.
+        let mut received = Vec::new();
+        server
+            .handle(encapsulated, |request| {
+                received = request.to_vec();
+                Vec::new()
+            })
+            .unwrap();
+        assert_eq!(received.len(), padded_len);
+        assert_eq!(&received[..message.len()], &message[..]);
+        assert!(received[message.len()..].iter().all(|&byte| byte == 0));
+        unsafe { request_context_message_drop_ffi(context) };
+    }
+
+    let message = request.encode(b"");
+    let context = unsafe {
+        encapsulate_request_padded_ffi(
+            config.as_ptr(),
+            config.len(),
+            message.as_ptr(),
+            message.len(),
+            0,
+        )
+    };
+    assert!(context.is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+}
+
+#[test]
+fn config_built_from_the_raw_public_key_round_trips() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let public_key = &config[3..35];
+
+    let mut built = [0; 64];
+    let written = unsafe {
+        build_key_config_ffi(
+            KEY_ID,
+            KEM_X25519_SHA256,
+            public_key.as_ptr(),
+            public_key.len(),
+            KDF_HKDF_SHA256,
+            AEAD_CHACHA20_POLY1305,
+            built.as_mut_ptr(),
+            built.len(),
+        )
+    };
+    assert_eq!(written, config.len() as libc::ssize_t);
+    let built = &built[..config.len()];
+    assert_eq!(built, &config[..]);
+
+    let parsed = apprelay::config::KeyConfig::decode(built).unwrap();
+    assert_eq!(parsed.key_id, KEY_ID);
+    assert_eq!(parsed.public_key, public_key);
+
+    let mut context = RequestContext::new(built, b"GET / HTTP/1.1").unwrap();
+    let response = server
+        .handle(context.encapsulated_request(), respond)
+        .unwrap();
+    assert_eq!(
+        context.decapsulate(&response).unwrap(),
+        respond(b"GET / HTTP/1.1")
+    );
+}
+
+#[test]
+fn empty_response_leaves_the_context_for_a_retry() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = b"GET / HTTP/1.1";
+    unsafe {
+        let context = encapsulate_request_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        assert!(!context.is_null());
+        let request_len = request_context_message_len_ffi(context);
+
+        let empty: [u8; 0] = [];
+        for response_ptr in [empty.as_ptr(), std::ptr::null()] {
+            assert!(decapsulate_response_ffi(context, response_ptr, 0).is_null());
+            assert_eq!(
+                last_error_code(),
+                ClientErrorKind::ResponseEmpty as libc::c_int
+            );
+            assert_eq!(request_context_message_len_ffi(context), request_len);
+        }
+
+        let request = std::slice::from_raw_parts(request_context_message_ffi(context), request_len);
+        let response = server.handle(request, respond).unwrap();
+        let response = decapsulate_response_ffi(context, response.as_ptr(), response.len());
+        assert!(!response.is_null());
+        assert_eq!(
+            std::slice::from_raw_parts(
+                response_context_message_ffi(response),
+                response_context_message_len_ffi(response)
+            ),
+            &respond(payload)[..]
+        );
+        response_context_drop_ffi(response);
+    }
+}
+
+#[test]
+fn full_result_round_trip() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = b"GET / HTTP/1.1";
+    unsafe {
+        let result = encapsulate_request_full_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        assert!(!result.context.is_null());
+        assert_eq!(result.error_code, 0);
+        let request = std::slice::from_raw_parts(result.message, result.message_len);
+        assert_eq!(request, (*result.context).encapsulated_request());
+
+        let response = server.handle(request, respond).unwrap();
+        let response = decapsulate_response_ffi(result.context, response.as_ptr(), response.len());
+        assert!(!response.is_null());
+        assert_eq!(
+            std::slice::from_raw_parts(
+                response_context_message_ffi(response),
+                response_context_message_len_ffi(response)
+            ),
+            &respond(payload)[..]
+        );
+        response_context_drop_ffi(response);
+    }
+}
+
+#[test]
+fn later_supported_suite_is_used_when_the_first_is_not() {
+    let (gateway_config, mut server) = gateway(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    // The gateway's key, advertised with AES-256-GCM, which this build can not seal with, first.
+    let mut config = apprelay::config::KeyConfig::decode(&gateway_config).unwrap();
+    config.symmetric.insert(
+        0,
+        apprelay::config::SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_256_GCM,
+        },
+    );
+    let config = config.encode();
+
+    let mut context = RequestContext::new(&config, b"GET / HTTP/1.1").unwrap();
+    assert_eq!(context.suite().unwrap().aead, AEAD_CHACHA20_POLY1305);
+    let response = server
+        .handle(context.encapsulated_request(), respond)
+        .unwrap();
+    assert_eq!(
+        context.decapsulate(&response).unwrap(),
+        respond(b"GET / HTTP/1.1")
+    );
+
+    let mut only_unsupported = apprelay::config::KeyConfig::decode(&config).unwrap();
+    only_unsupported.symmetric.truncate(1);
+    assert!(matches!(
+        RequestContext::new(&only_unsupported.encode(), b"GET / HTTP/1.1"),
+        Err(ClientError::UnsupportedSuite)
+    ));
+}
+
+fn framed(response: &[u8], framing: libc::c_int, declared: usize) -> Vec<u8> {
+    let prefix = match framing {
+        RESPONSE_FRAMING_U16 => (declared as u16).to_be_bytes().to_vec(),
+        RESPONSE_FRAMING_U32 => (declared as u32).to_be_bytes().to_vec(),
+        _ => Vec::new(),
+    };
+    [&prefix[..], response].concat()
+}
+
+#[test]
+fn length_prefixed_responses_are_unframed() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = b"GET / HTTP/1.1";
+    for framing in [
+        RESPONSE_FRAMING_NONE,
+        RESPONSE_FRAMING_U16,
+        RESPONSE_FRAMING_U32,
+    ] {
+        unsafe {
+            let context = encapsulate_request_ffi(
+                config.as_ptr(),
+                config.len(),
+                payload.as_ptr(),
+                payload.len(),
+            );
+            let response = server
+                .handle((*context).encapsulated_request(), respond)
+                .unwrap();
+
+            if framing != RESPONSE_FRAMING_NONE {
+                // A prefix declaring one byte more than follows leaves the context for a retry.
+                let mismatched = framed(&response, framing, response.len() + 1);
+                assert!(decapsulate_response_framed_ffi(
+                    context,
+                    mismatched.as_ptr(),
+                    mismatched.len(),
+                    framing
+                )
+                .is_null());
+                assert_eq!(
+                    last_error_code(),
+                    ClientErrorKind::ResponseFramingMismatch as libc::c_int
+                );
+            }
+
+            let correct = framed(&response, framing, response.len());
+            let response =
+                decapsulate_response_framed_ffi(context, correct.as_ptr(), correct.len(), framing);
+            assert!(!response.is_null());
+            assert_eq!(
+                std::slice::from_raw_parts(
+                    response_context_message_ffi(response),
+                    response_context_message_len_ffi(response)
+                ),
+                &respond(payload)[..]
+            );
+            response_context_drop_ffi(response);
+        }
+    }
+
+    assert!(matches!(
+        strip_response_framing(&[0x00], RESPONSE_FRAMING_U16),
+        Err(ClientError::ResponseTruncated {
+            expected: 2,
+            actual: 1
+        })
+    ));
+    assert!(matches!(
+        strip_response_framing(&[0x00, 0x00, 0x00, 0x02, 0xaa], RESPONSE_FRAMING_U32),
+        Err(ClientError::ResponseFramingMismatch {
+            declared: 2,
+            actual: 1
+        })
+    ));
+    assert!(matches!(
+        strip_response_framing(&[0x00], 9),
+        Err(ClientError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn bodiless_get_reaches_gateway_without_body() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let mut message = [0; 64];
+    let message_len = unsafe {
+        build_bhttp_request_ffi(
+            b"GET\0".as_ptr().cast(),
+            b"https\0".as_ptr().cast(),
+            b"example.com\0".as_ptr().cast(),
+            b"/status\0".as_ptr().cast(),
+            std::ptr::null(),
+            std::ptr::null(),
+            0,
+            std::ptr::null(),
+            0,
+            0,
+            message.as_mut_ptr(),
+            message.len(),
+        )
+    };
+    assert!(message_len > 0);
+    let message = &message[..message_len as usize];
+
+    let (request, _) = RequestContext::new(&config, message)
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    let mut received = Vec::new();
+    server
+        .handle(&request, |request| {
+            received = request.to_vec();
+            Vec::new()
+        })
+        .unwrap();
+
+    let mut expected = vec![0, 3];
+    expected.extend_from_slice(b"GET");
+    expected.push(5);
+    expected.extend_from_slice(b"https");
+    expected.push(11);
+    expected.extend_from_slice(b"example.com");
+    expected.push(7);
+    expected.extend_from_slice(b"/status");
+    // Empty header section, zero content length and empty trailer section.
+    expected.extend_from_slice(&[0, 0, 0]);
+    assert_eq!(received, expected);
+}
+
+fn prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    assert!(bytes.len() < 64);
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(bytes);
+}
+
+/// Known-length BHTTP request spelled out byte by byte rather than through the encoder.
+fn expected_bhttp(method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut expected = vec![0];
+    for control in [method, "https", "example.com", path] {
+        prefixed(&mut expected, control.as_bytes());
+    }
+    let mut section = Vec::new();
+    for (name, value) in headers {
+        prefixed(&mut section, name.as_bytes());
+        prefixed(&mut section, value.as_bytes());
+    }
+    prefixed(&mut expected, &section);
+    prefixed(&mut expected, body);
+    expected.push(0);
+    expected
+}
+
+/// Guards against wire format drift: requests produced by this client are stored and
+/// handed to a plain `ohttp::Server`, which has to recover the exact BHTTP message.
+/// Encapsulation is randomized, so the key pair and requests are generated per run.
+#[test]
+fn reference_server_decapsulates_stored_requests() {
+    let config = KeyConfig::new(
+        KEY_ID,
+        Kem::X25519Sha256,
+        vec![SymmetricSuite::new(Kdf::HkdfSha256, Aead::Aes128Gcm)],
+    )
+    .unwrap();
+    let encoded_config = config.encode().unwrap();
+    let mut server = Server::new(config).unwrap();
+
+    let get = BhttpRequest::new("GET", "https", "example.com", "/status").header("accept", "*/*");
+    let post = BhttpRequest::new("POST", "https", "example.com", "/query")
+        .header("content-type", "application/json");
+    let body = br#"{"q":1}"#;
+    let cases = [
+        (
+            get.encode(b""),
+            expected_bhttp("GET", "/status", &[("accept", "*/*")], b""),
+        ),
+        (
+            post.encode(body),
+            expected_bhttp(
+                "POST",
+                "/query",
+                &[("content-type", "application/json")],
+                body,
+            ),
+        ),
+    ];
+
+    let stored: Vec<Vec<u8>> = cases
+        .iter()
+        .map(|(message, _)| {
+            let (request, _) = RequestContext::new(&encoded_config, message)
+                .unwrap()
+                .into_parts()
+                .unwrap();
+            request
+        })
+        .collect();
+    for (request, (_, expected)) in stored.iter().zip(&cases) {
+        let (decapsulated, _) = server.decapsulate(request).unwrap();
+        assert_eq!(&decapsulated, expected);
+    }
+}
+
+#[test]
+fn grpc_request_round_trip() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let path = CString::new("/echo.Echo/Say").unwrap();
+    let message = b"\x0a\x02hi";
+    let context = unsafe {
+        encapsulate_grpc_request_ffi(
+            config.as_ptr(),
+            config.len(),
+            path.as_ptr(),
+            message.as_ptr(),
+            message.len(),
+        )
+    };
+    assert!(!context.is_null());
+    let request = unsafe {
+        std::slice::from_raw_parts(
+            request_context_message_ffi(context),
+            request_context_message_len_ffi(context),
+        )
+    };
+
+    let mut received = None;
+    let response = server
+        .handle(request, |request| {
+            received = Some(request.to_vec());
+            b"grpc-status: 0".to_vec()
+        })
+        .unwrap();
+
+    // POST with an empty authority, the gRPC headers and the framed message.
+    let mut expected = vec![0];
+    for control in ["POST", "https", "", "/echo.Echo/Say"] {
+        prefixed(&mut expected, control.as_bytes());
+    }
+    let mut section = Vec::new();
+    for field in ["content-type", "application/grpc", "te", "trailers"] {
+        prefixed(&mut section, field.as_bytes());
+    }
+    prefixed(&mut expected, &section);
+    let mut frame = vec![0, 0, 0, 0, message.len() as u8];
+    assert_eq!(frame.len(), GRPC_MESSAGE_PREFIX_LEN);
+    frame.extend_from_slice(message);
+    prefixed(&mut expected, &frame);
+    expected.push(0);
+    assert_eq!(received.unwrap(), expected);
+
+    unsafe {
+        let response = decapsulate_response_ffi(context, response.as_ptr(), response.len());
+        assert!(!response.is_null());
+        assert_eq!(
+            std::slice::from_raw_parts(
+                response_context_message_ffi(response),
+                response_context_message_len_ffi(response)
+            ),
+            b"grpc-status: 0"
+        );
+        response_context_drop_ffi(response);
+    }
+
+    let not_absolute = CString::new("echo.Echo/Say").unwrap();
+    let context = unsafe {
+        encapsulate_grpc_request_ffi(
+            config.as_ptr(),
+            config.len(),
+            not_absolute.as_ptr(),
+            message.as_ptr(),
+            message.len(),
+        )
+    };
+    assert!(context.is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+}
+
+#[test]
+fn split_request_and_decapsulator_have_independent_lifetimes() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let payload = b"GET / HTTP/1.1";
+    unsafe {
+        let mut request_ptr = std::ptr::null_mut();
+        let mut request_len = 0;
+        let mut decapsulator = std::ptr::null_mut();
+        let status = encapsulate_split_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+            &mut request_ptr,
+            &mut request_len,
+            &mut decapsulator,
+        );
+        assert_eq!(status, 0);
+
+        let request = std::slice::from_raw_parts(request_ptr, request_len).to_vec();
+        let response = server.handle(&request, respond).unwrap();
+        apprelay_free_bytes_ffi(request_ptr, request_len);
+
+        let response_context =
+            response_decapsulator_decapsulate_ffi(decapsulator, response.as_ptr(), response.len());
+        assert!(!response_context.is_null());
+        let len = response_context_message_len_ffi(response_context);
+        let message =
+            std::slice::from_raw_parts(response_context_message_ffi(response_context), len);
+        assert_eq!(message, &respond(payload)[..]);
+        response_context_drop_ffi(response_context);
+    }
+}
+
+#[test]
+fn split_request_reassembled_from_header_and_ciphertext() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = b"GET / HTTP/1.1";
+    // Key id, KEM, KDF and AEAD followed by the 32 byte X25519 `enc`.
+    let header_len = 7 + 32;
+    unsafe {
+        let mut request_ptr = std::ptr::null_mut();
+        let mut request_len = 0;
+        let mut decapsulator = std::ptr::null_mut();
+        let status = encapsulate_split_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+            &mut request_ptr,
+            &mut request_len,
+            &mut decapsulator,
+        );
+        assert_eq!(status, 0);
+        let request = std::slice::from_raw_parts(request_ptr, request_len).to_vec();
+        apprelay_free_bytes_ffi(request_ptr, request_len);
+        let (header, ciphertext) = request.split_at(header_len);
+
+        // A header cut short of `enc` is rejected without consuming the decapsulator.
+        let truncated = request_context_from_parts_ffi(
+            config.as_ptr(),
+            config.len(),
+            header.as_ptr(),
+            header.len() - 1,
+            ciphertext.as_ptr(),
+            ciphertext.len(),
+            decapsulator,
+        );
+        assert!(truncated.is_null());
+
+        let context = request_context_from_parts_ffi(
+            config.as_ptr(),
+            config.len(),
+            header.as_ptr(),
+            header.len(),
+            ciphertext.as_ptr(),
+            ciphertext.len(),
+            decapsulator,
+        );
+        assert!(!context.is_null());
+        assert_eq!((*context).encapsulated_request(), &request[..]);
+
+        let response = server.handle(&request, respond).unwrap();
+        let response_context = decapsulate_response_ffi(context, response.as_ptr(), response.len());
+        assert!(!response_context.is_null());
+        let len = response_context_message_len_ffi(response_context);
+        let message =
+            std::slice::from_raw_parts(response_context_message_ffi(response_context), len);
+        assert_eq!(message, &respond(payload)[..]);
+        response_context_drop_ffi(response_context);
+    }
+}
+
+#[test]
+fn retry_after_key_rotation() {
+    let (old_config, _) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let (new_config, mut rotated) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = b"GET / HTTP/1.1";
+    unsafe {
+        let stale = encapsulate_request_ffi(
+            old_config.as_ptr(),
+            old_config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        assert!(!stale.is_null());
+        assert!(rotated
+            .handle((*stale).encapsulated_request(), respond)
+            .is_err());
+        request_context_message_drop_ffi(stale);
+
+        let retry = reencapsulate_ffi(
+            new_config.as_ptr(),
+            new_config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        assert!(!retry.is_null());
+        let response = rotated
+            .handle((*retry).encapsulated_request(), respond)
+            .unwrap();
+        let response_context = decapsulate_response_ffi(retry, response.as_ptr(), response.len());
+        assert!(!response_context.is_null());
+        let len = response_context_message_len_ffi(response_context);
+        let message =
+            std::slice::from_raw_parts(response_context_message_ffi(response_context), len);
+        assert_eq!(message, &respond(payload)[..]);
+        response_context_drop_ffi(response_context);
+    }
+}
+
+extern "C" fn collect_chunk(chunk_ptr: *const u8, chunk_len: usize, user: *mut c_void) -> bool {
+    let chunks = unsafe { &mut *(user as *mut Vec<Vec<u8>>) };
+    chunks.push(unsafe { std::slice::from_raw_parts(chunk_ptr, chunk_len) }.to_vec());
+    true
+}
+
+extern "C" fn abort_after_first_chunk(
+    _chunk_ptr: *const u8,
+    _chunk_len: usize,
+    user: *mut c_void,
+) -> bool {
+    unsafe { *(user as *mut usize) += 1 };
+    false
+}
+
+#[test]
+fn callback_receives_response_in_chunks() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let payload = vec![7; 2 * RESPONSE_CHUNK_LEN + 1];
+    unsafe {
+        let context = encapsulate_request_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        let response = server
+            .handle((*context).encapsulated_request(), respond)
+            .unwrap();
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        let status = decapsulate_response_cb_ffi(
+            context,
+            response.as_ptr(),
+            response.len(),
+            Some(collect_chunk),
+            &mut chunks as *mut _ as *mut c_void,
+        );
+        assert_eq!(status, 0);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= RESPONSE_CHUNK_LEN));
+        assert_eq!(chunks.concat(), respond(&payload));
+
+        let context = encapsulate_request_ffi(
+            config.as_ptr(),
+            config.len(),
+            payload.as_ptr(),
+            payload.len(),
+        );
+        let response = server
+            .handle((*context).encapsulated_request(), respond)
+            .unwrap();
+        let mut calls = 0usize;
+        let status = decapsulate_response_cb_ffi(
+            context,
+            response.as_ptr(),
+            response.len(),
+            Some(abort_after_first_chunk),
+            &mut calls as *mut _ as *mut c_void,
+        );
+        assert_eq!(status, -1);
+        assert_eq!(calls, 1);
+        assert_eq!(
+            last_error_code(),
+            ClientErrorKind::CallbackAborted as libc::c_int
+        );
+    }
+}
+
+#[test]
+fn multiplexed_responses_open_with_their_contexts() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let payloads: [&[u8]; 3] = [b"first", b"second", b"third"];
+    let mut body = Vec::new();
+    let contexts: Vec<*mut RequestContext> = payloads
+        .iter()
+        .map(|payload| unsafe {
+            let context = encapsulate_request_ffi(
+                config.as_ptr(),
+                config.len(),
+                payload.as_ptr(),
+                payload.len(),
+            );
+            assert!(!context.is_null());
+            let response = server
+                .handle((*context).encapsulated_request(), respond)
+                .unwrap();
+            body.extend_from_slice(&(response.len() as u32).to_be_bytes());
+            body.extend_from_slice(&response);
+            context
+        })
+        .collect();
+    // The relay is still sending the third response.
+    body.truncate(body.len() - 1);
+
+    unsafe {
+        let array = decapsulate_multiplexed_ffi(
+            contexts.as_ptr(),
+            contexts.len(),
+            body.as_ptr(),
+            body.len(),
+        );
+        assert!(!array.is_null());
+        assert_eq!(response_context_array_len_ffi(array), 3);
+        for (index, payload) in payloads[..2].iter().enumerate() {
+            assert_eq!(response_context_array_error_ffi(array, index), 0);
+            let response = response_context_array_get_ffi(array, index);
+            assert!(!response.is_null());
+            let message = std::slice::from_raw_parts(
+                response_context_message_ffi(response),
+                response_context_message_len_ffi(response),
+            );
+            assert_eq!(message, &respond(payload)[..]);
+        }
+        assert_eq!(
+            response_context_array_error_ffi(array, 2),
+            ClientErrorKind::ResponseTruncated as libc::c_int
+        );
+        assert!(response_context_array_get_ffi(array, 2).is_null());
+        assert_eq!(response_context_array_error_ffi(array, 3), -1);
+        response_context_array_drop_ffi(array);
+
+        // The context of the truncated response is left for the complete one.
+        let response = server
+            .handle((*contexts[2]).encapsulated_request(), respond)
+            .unwrap();
+        let response_context =
+            decapsulate_response_ffi(contexts[2], response.as_ptr(), response.len());
+        assert!(!response_context.is_null());
+        response_context_drop_ffi(response_context);
+    }
+}
+
+#[test]
+fn post_body_len_covers_the_whole_request() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    for payload in payloads() {
+        unsafe {
+            let context = encapsulate_request_ffi(
+                config.as_ptr(),
+                config.len(),
+                payload.as_ptr(),
+                payload.len(),
+            );
+            let content_length = request_context_post_body_len_ffi(context);
+            assert_eq!(content_length, request_context_message_len_ffi(context));
+
+            let body =
+                std::slice::from_raw_parts(request_context_message_ffi(context), content_length);
+            let response = server.handle(body, respond).unwrap();
+            let response_context =
+                decapsulate_response_ffi(context, response.as_ptr(), response.len());
+            assert!(!response_context.is_null());
+            response_context_drop_ffi(response_context);
+        }
+    }
+}
+
+#[test]
+fn response_bytes_taken_without_copy() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    for payload in payloads() {
+        unsafe {
+            let context = encapsulate_request_ffi(
+                config.as_ptr(),
+                config.len(),
+                payload.as_ptr(),
+                payload.len(),
+            );
+            let response = server
+                .handle((*context).encapsulated_request(), respond)
+                .unwrap();
+            let response_context =
+                decapsulate_response_ffi(context, response.as_ptr(), response.len());
+            assert!(!response_context.is_null());
+
+            let mut len = 0;
+            let bytes = response_context_take_bytes_ffi(response_context, &mut len);
+            assert!(!bytes.is_null());
+            assert_eq!(
+                std::slice::from_raw_parts(bytes, len),
+                &respond(&payload)[..]
+            );
+            apprelay_free_bytes_ffi(bytes, len);
+        }
+    }
+}
+
+#[test]
+fn one_pooled_context_encapsulates_ten_thousand_requests() {
+    let (config, mut server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    unsafe {
+        let context = request_context_new_ffi();
+        for i in 0..10_000u32 {
+            let payload = i.to_be_bytes();
+            assert_eq!(
+                encapsulate_into_context_ffi(
+                    context,
+                    config.as_ptr(),
+                    config.len(),
+                    payload.as_ptr(),
+                    payload.len(),
+                ),
+                0
+            );
+            if i % 1000 == 999 {
+                let mut received = None;
+                server
+                    .handle((*context).encapsulated_request(), |request| {
+                        received = Some(request.to_vec());
+                        respond(request)
+                    })
+                    .unwrap();
+                assert_eq!(received.as_deref(), Some(&payload[..]));
+            }
+        }
+        request_context_message_drop_ffi(context);
+    }
+}
+
+#[test]
+fn double_encapsulation_round_trip() {
+    let (inner_config, mut gateway_server) = gateway(Kdf::HkdfSha256, Aead::Aes128Gcm);
+    let (outer_config, mut relay_server) = gateway(Kdf::HkdfSha256, Aead::ChaCha20Poly1305);
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context = encapsulate_double_ffi(
+            inner_config.as_ptr(),
+            inner_config.len(),
+            outer_config.as_ptr(),
+            outer_config.len(),
+            msg.as_ptr(),
+            msg.len(),
+        );
+        assert!(!context.is_null());
+        let request = double_request_context_message_ffi(context);
+        let request = std::slice::from_raw_parts(request.ptr, request.len);
+
+        let mut received = None;
+        let response = relay_server
+            .handle(request, |inner_request| {
+                gateway_server
+                    .handle(inner_request, |request| {
+                        received = Some(request.to_vec());
+                        respond(request)
+                    })
+                    .unwrap()
+            })
+            .unwrap();
+        assert_eq!(received.as_deref(), Some(&msg[..]));
+
+        // A NULL response leaves the context to the caller.
+        assert!(
+            decapsulate_double_response_ffi(context, std::ptr::null(), response.len()).is_null()
+        );
+
+        let response_context =
+            decapsulate_double_response_ffi(context, response.as_ptr(), response.len());
+        assert!(!response_context.is_null());
+        let len = response_context_message_len_ffi(response_context);
+        let plaintext =
+            std::slice::from_raw_parts(response_context_message_ffi(response_context), len);
+        assert_eq!(plaintext, &respond(msg)[..]);
+        response_context_drop_ffi(response_context);
+    }
+}
+
+#[test]
+fn predicted_sizes_match_every_suite() {
+    for (kdf, aead) in SUITES {
+        let (config, mut server) = gateway(kdf, aead);
+        for payload in payloads() {
+            let context = RequestContext::new(&config, &payload).unwrap();
+            let suite = context.suite().unwrap();
+            assert_eq!(
+                suite.encapsulated_request_len(payload.len()),
+                Some(context.encapsulated_request().len()),
+                "{:?}",
+                (kdf, aead)
+            );
+
+            let (request, decapsulator) = context.into_parts().unwrap();
+            let response = server.handle(&request, respond).unwrap();
+            assert_eq!(
+                suite.encapsulated_response_len(payload.len()),
+                Some(response.len()),
+                "{:?}",
+                (kdf, aead)
+            );
+            assert!(suite.min_response_len().unwrap() <= response.len());
+            assert_eq!(
+                decapsulator.decapsulate(&response).unwrap(),
+                respond(&payload)
+            );
+        }
+    }
+}
+
+#[test]
+fn aes_256_gcm_sizes_are_predicted_but_not_encapsulated() {
+    let suite = HpkeSuite {
+        kem: KEM_X25519_SHA256,
+        kdf: KDF_HKDF_SHA256,
+        aead: AEAD_AES_256_GCM,
+    };
+    assert!(!suite.is_supported());
+    // Header, X25519 enc and tag, and a response nonce as long as the 32 byte key.
+    assert_eq!(suite.encapsulated_request_len(100), Some(7 + 32 + 100 + 16));
+    assert_eq!(suite.encapsulated_response_len(100), Some(32 + 100 + 16));
+
+    let config = apprelay::config::KeyConfig::new(
+        KEY_ID,
+        KEM_X25519_SHA256,
+        vec![1; 32],
+        vec![apprelay::config::SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_256_GCM,
+        }],
+    )
+    .unwrap()
+    .encode();
+    assert_eq!(
+        RequestContext::new(&config, b"GET / HTTP/1.1")
+            .err()
+            .map(|err| err.kind()),
+        Some(ClientErrorKind::UnsupportedSuite)
+    );
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Random delay of decapsulated responses returned over FFI.
+//! Run with `cargo test --features interop-tests --test jitter`.
+
+use std::time::{Duration, Instant};
+
+use apprelay::android::decapsulate_context;
+use apprelay::error_ffi::last_error_code;
+use apprelay::jitter::{next_response_jitter, set_response_jitter_ffi};
+use apprelay::server::TestServer;
+use apprelay::{
+    decapsulate_response_ffi, encapsulate_request_ffi, response_context_drop_ffi, ClientErrorKind,
+};
+use ohttp::hpke::{Aead, Kdf, Kem};
+use ohttp::{KeyConfig, SymmetricSuite};
+
+// A single test, the setting is process wide and parallel tests would disturb it.
+#[test]
+fn delay_stays_within_bounds() {
+    assert_eq!(next_response_jitter(), Duration::ZERO);
+    assert_eq!(set_response_jitter_ffi(50, 10), -1);
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+
+    assert_eq!(set_response_jitter_ffi(20, 40), 0);
+    for _ in 0..1000 {
+        let delay = next_response_jitter();
+        assert!(delay >= Duration::from_millis(20) && delay <= Duration::from_millis(40));
+    }
+
+    let config = KeyConfig::new(
+        2,
+        Kem::X25519Sha256,
+        vec![SymmetricSuite::new(Kdf::HkdfSha256, Aead::Aes128Gcm)],
+    )
+    .unwrap();
+    let encoded = config.encode().unwrap();
+    let mut server = TestServer::new(config).unwrap();
+    let msg = b"ping";
+    unsafe {
+        let context = encapsulate_request_ffi(encoded.as_ptr(), encoded.len(), msg.as_ptr(), 4);
+        let response = server
+            .handle((*context).encapsulated_request(), |_| b"pong".to_vec())
+            .unwrap();
+        let started = Instant::now();
+        let response_context = decapsulate_response_ffi(context, response.as_ptr(), response.len());
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert!(!response_context.is_null());
+        response_context_drop_ffi(response_context);
+
+        // The JNI entry points share this core with each other.
+        let context = encapsulate_request_ffi(encoded.as_ptr(), encoded.len(), msg.as_ptr(), 4);
+        let response = server
+            .handle((*context).encapsulated_request(), |_| b"pong".to_vec())
+            .unwrap();
+        let started = Instant::now();
+        let response = decapsulate_context(context as i64, &response).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(response, b"pong");
+    }
+
+    assert_eq!(set_response_jitter_ffi(0, 0), 0);
+    assert_eq!(next_response_jitter(), Duration::ZERO);
+}
@@ -0,0 +1,48 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Key configurations assembled from JSON Web Keys.
+//! Run with `cargo test --features jwk --test jwk`.
+
+use apprelay::config::{KeyConfig, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256};
+use apprelay::jwk::key_config_from_jwk_ffi;
+use apprelay::RequestContext;
+
+/// X25519 public key of RFC 8037, appendix A.6.
+const X25519_JWK: &[u8] =
+    b"{\"kty\":\"OKP\",\"crv\":\"X25519\",\"x\":\"3p7bfXt9wbTTW2HC7OQ1Nz-DQ8hbeGdNrfx-FG-IK08\"}\0";
+
+unsafe fn from_jwk(jwk: &[u8], out: &mut [u8]) -> isize {
+    key_config_from_jwk_ffi(
+        jwk.as_ptr().cast(),
+        5,
+        KDF_HKDF_SHA256,
+        AEAD_AES_128_GCM,
+        out.as_mut_ptr(),
+        out.len(),
+    )
+}
+
+#[test]
+fn x25519_jwk_config_encapsulates() {
+    let mut out = [0; 64];
+    let written = unsafe { from_jwk(X25519_JWK, &mut out) };
+    assert!(written > 0);
+    let encoded = &out[..written as usize];
+
+    let config = KeyConfig::decode(encoded).unwrap();
+    assert_eq!(config.key_id, 5);
+    assert_eq!(config.kem, KEM_X25519_SHA256);
+    assert!(RequestContext::new(encoded, b"hello").is_ok());
+}
+
+#[test]
+fn mismatched_curve_and_private_keys_are_rejected() {
+    let mut out = [0; 64];
+    let wrong_curve =
+        b"{\"kty\":\"EC\",\"crv\":\"X25519\",\"x\":\"3p7bfXt9wbTTW2HC7OQ1Nz-DQ8hbeGdNrfx-FG-IK08\"}\0";
+    assert_eq!(unsafe { from_jwk(wrong_curve, &mut out) }, -1);
+
+    let private = b"{\"kty\":\"OKP\",\"crv\":\"X25519\",\"x\":\"3p7bfXt9wbTTW2HC7OQ1Nz-DQ8hbeGdNrfx-FG-IK08\",\"d\":\"dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo\"}\0";
+    assert_eq!(unsafe { from_jwk(private, &mut out) }, -1);
+}
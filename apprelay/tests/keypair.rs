@@ -0,0 +1,63 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Key pairs generated over FFI for end-to-end tests of language bindings.
+//! Run with `cargo test --features server,testutil --test keypair`.
+
+use apprelay::config::{
+    HpkeSuite, AEAD_AES_256_GCM, AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+use apprelay::server::{generate_test_keypair_ffi, TestServer, TEST_PRIVATE_KEY_LEN};
+use apprelay::RequestContext;
+
+#[test]
+fn generated_keypair_round_trips() {
+    let suite = HpkeSuite {
+        kem: KEM_X25519_SHA256,
+        kdf: KDF_HKDF_SHA256,
+        aead: AEAD_CHACHA20_POLY1305,
+    };
+    let mut config = [0; 64];
+    let mut private = [0; TEST_PRIVATE_KEY_LEN];
+    let written = unsafe {
+        generate_test_keypair_ffi(
+            suite.kem,
+            suite.kdf,
+            suite.aead,
+            3,
+            config.as_mut_ptr(),
+            config.len(),
+            private.as_mut_ptr(),
+            private.len(),
+        )
+    };
+    assert!(written > 0);
+    let config = &config[..written as usize];
+
+    let (request, decapsulator) = RequestContext::new(config, b"ping")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    let mut server = TestServer::from_seed(3, suite, &private).unwrap();
+    let response = server
+        .handle(&request, |request| {
+            assert_eq!(request, b"ping");
+            b"pong".to_vec()
+        })
+        .unwrap();
+    assert_eq!(decapsulator.decapsulate(&response).unwrap(), b"pong");
+
+    let unsupported = unsafe {
+        generate_test_keypair_ffi(
+            suite.kem,
+            suite.kdf,
+            AEAD_AES_256_GCM,
+            3,
+            [0; 64].as_mut_ptr(),
+            64,
+            private.as_mut_ptr(),
+            private.len(),
+        )
+    };
+    assert_eq!(unsupported, -1);
+}
@@ -0,0 +1,41 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Layout of the FFI structs as reported by `apprelay_struct_sizes_ffi`.
+
+use apprelay::layout::{apprelay_struct_sizes_ffi, StructSizes};
+
+/// The layout a C compiler gives these structs on this target.
+#[cfg(target_pointer_width = "64")]
+const EXPECTED: StructSizes = StructSizes {
+    struct_sizes_size: 56,
+    bytes_view_size: 16,
+    bytes_view_align: 8,
+    bytes_view_ptr_offset: 0,
+    bytes_view_len_offset: 8,
+    // Two pointers, a `size_t` and an `int` padded to the pointer alignment.
+    encapsulate_result_size: 32,
+    encapsulate_result_align: 8,
+};
+#[cfg(target_pointer_width = "32")]
+const EXPECTED: StructSizes = StructSizes {
+    struct_sizes_size: 28,
+    bytes_view_size: 8,
+    bytes_view_align: 4,
+    bytes_view_ptr_offset: 0,
+    bytes_view_len_offset: 4,
+    encapsulate_result_size: 16,
+    encapsulate_result_align: 4,
+};
+
+#[test]
+fn sizes_and_offsets_match_the_c_layout() {
+    assert_eq!(StructSizes::current(), EXPECTED);
+
+    let mut sizes = StructSizes::default();
+    unsafe { apprelay_struct_sizes_ffi(&mut sizes) };
+    assert_eq!(sizes, EXPECTED);
+
+    // A NULL output is ignored.
+    unsafe { apprelay_struct_sizes_ffi(std::ptr::null_mut()) };
+}
@@ -0,0 +1,54 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Enumeration of the live request context handles.
+//! Run with `cargo test --features diagnostics --test live_handles`.
+
+use apprelay::diagnostics::{
+    apprelay_debug_live_handle_count_ffi, apprelay_debug_live_handles_ffi,
+};
+use apprelay::error_ffi::last_error_code;
+use apprelay::{request_context_message_drop_ffi, request_context_new_ffi, ClientErrorKind};
+
+fn live_handles() -> Vec<u64> {
+    let mut out = vec![0; apprelay_debug_live_handle_count_ffi()];
+    let written = unsafe { apprelay_debug_live_handles_ffi(out.as_mut_ptr(), out.len()) };
+    assert_eq!(written, out.len() as libc::ssize_t);
+    out
+}
+
+// A single test, the registry is process wide and parallel tests would disturb it.
+#[test]
+fn live_handles_track_frees() {
+    assert!(live_handles().is_empty());
+
+    let contexts: Vec<_> = (0..4).map(|_| request_context_new_ffi()).collect();
+    let mut expected: Vec<u64> = contexts.iter().map(|context| *context as u64).collect();
+    expected.sort_unstable();
+    assert_eq!(live_handles(), expected);
+
+    unsafe {
+        request_context_message_drop_ffi(contexts[0]);
+        request_context_message_drop_ffi(contexts[2]);
+    }
+    let mut expected = vec![contexts[1] as u64, contexts[3] as u64];
+    expected.sort_unstable();
+    assert_eq!(apprelay_debug_live_handle_count_ffi(), 2);
+    assert_eq!(live_handles(), expected);
+
+    let mut short = [0u64; 1];
+    assert_eq!(
+        unsafe { apprelay_debug_live_handles_ffi(short.as_mut_ptr(), short.len()) },
+        -1
+    );
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::BufferTooSmall as libc::c_int
+    );
+
+    unsafe {
+        request_context_message_drop_ffi(contexts[1]);
+        request_context_message_drop_ffi(contexts[3]);
+    }
+    assert!(live_handles().is_empty());
+}
@@ -0,0 +1,63 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! JSON log records of requests.
+//! Run with `cargo test --features serde --test log_record`.
+
+use std::ffi::CStr;
+
+use apprelay::config::{
+    KeyConfig, SymmetricSuite, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+use apprelay::log_record::request_context_log_record_ffi;
+use apprelay::{encapsulate_request_ffi, request_context_message_drop_ffi};
+use serde_json::Value;
+
+#[test]
+fn log_record_fields() {
+    let config = KeyConfig::new(
+        6,
+        KEM_X25519_SHA256,
+        vec![6; 32],
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_128_GCM,
+        }],
+    )
+    .unwrap()
+    .encode();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+
+        let mut out = vec![0; 512];
+        assert_eq!(
+            request_context_log_record_ffi(context, out.as_mut_ptr(), 8),
+            -1
+        );
+        let written = request_context_log_record_ffi(context, out.as_mut_ptr(), out.len());
+        assert!(written > 0);
+        let record = CStr::from_ptr(out.as_ptr()).to_str().unwrap();
+        assert_eq!(record.len(), written as usize);
+
+        let record: Value = serde_json::from_str(record).unwrap();
+        assert_eq!(record["key_id"], 6);
+        assert_eq!(record["kem"], KEM_X25519_SHA256);
+        assert_eq!(record["kdf"], KDF_HKDF_SHA256);
+        assert_eq!(record["aead"], AEAD_AES_128_GCM);
+        assert_eq!(record["enc_len"], 32);
+        // Header, enc, message and AES-GCM tag.
+        assert_eq!(record["encapsulated_len"], 7 + 32 + msg.len() + 16);
+        let expected: String = (*context)
+            .fingerprint()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        assert_eq!(record["fingerprint"], expected);
+        assert_eq!(record.as_object().unwrap().len(), 7);
+
+        request_context_message_drop_ffi(context);
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Telling encapsulated responses apart from errors of the relay, and classifying blobs.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use apprelay::bhttp::BhttpRequest;
+use apprelay::config::{
+    KeyConfig, SymmetricSuite, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+use apprelay::error_ffi::last_error_code;
+use apprelay::media_type::{
+    check_response_content_type_ffi, classify_message, classify_message_ffi,
+    expected_content_type_for_mode_ffi, parse_media_type, should_decapsulate,
+    should_decapsulate_ffi, MessageKind, OhttpMediaType, MESSAGE_BHTTP,
+    MESSAGE_ENCAPSULATED_REQUEST, MESSAGE_UNKNOWN, OHTTP_MODE_CHUNKED, OHTTP_MODE_ONE_SHOT,
+};
+use apprelay::{ClientErrorKind, RequestContext};
+
+fn should_decapsulate_c(status: u16, content_type: &str) -> libc::c_int {
+    let content_type = CString::new(content_type).unwrap();
+    unsafe { should_decapsulate_ffi(status, content_type.as_ptr()) }
+}
+
+#[test]
+fn only_ohttp_responses_with_status_200_are_decapsulated() {
+    assert_eq!(should_decapsulate_c(200, "message/ohttp-res"), 1);
+    assert_eq!(
+        should_decapsulate_c(200, "Message/OHTTP-res; charset=binary"),
+        1
+    );
+
+    assert_eq!(should_decapsulate_c(200, "text/html"), 0);
+    assert_eq!(should_decapsulate_c(200, "message/ohttp-req"), 0);
+    assert_eq!(should_decapsulate_c(502, "message/ohttp-res"), 0);
+    assert_eq!(should_decapsulate_c(502, "text/plain"), 0);
+    assert_eq!(unsafe { should_decapsulate_ffi(200, ptr::null()) }, 0);
+
+    assert!(should_decapsulate(200, Some("message/ohttp-res")));
+    assert!(!should_decapsulate(200, None));
+}
+
+#[test]
+fn blobs_are_classified() {
+    let bhttp = BhttpRequest::new("GET", "https", "example.com", "/").encode(b"");
+    let config = KeyConfig::new(
+        1,
+        KEM_X25519_SHA256,
+        vec![9; 32],
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_AES_128_GCM,
+        }],
+    )
+    .unwrap()
+    .encode();
+    let context = RequestContext::new(&config, &bhttp).unwrap();
+    let encapsulated = context.encapsulated_request();
+    let noise: Vec<u8> = (0..64u32).map(|i| (i * 37 + 11) as u8).collect();
+    let response = [0x01, 0x40, 0xc8, 0x00, 0x00];
+
+    let classify = |data: &[u8]| unsafe { classify_message_ffi(data.as_ptr(), data.len()) };
+    assert_eq!(classify(encapsulated), MESSAGE_ENCAPSULATED_REQUEST);
+    assert_eq!(classify(&bhttp), MESSAGE_BHTTP);
+    assert_eq!(classify(&response), MESSAGE_BHTTP);
+    assert_eq!(classify(&noise), MESSAGE_UNKNOWN);
+    assert_eq!(classify(b""), MESSAGE_UNKNOWN);
+
+    // A header alone is too short to hold the encapsulated key and a tag.
+    assert_eq!(classify_message(&encapsulated[..7]), MessageKind::Unknown);
+}
+
+#[test]
+fn media_types_are_parsed() {
+    assert_eq!(
+        parse_media_type("message/ohttp-res"),
+        OhttpMediaType::Response
+    );
+    assert_eq!(
+        parse_media_type(" Message/OHTTP-Res ; charset=binary"),
+        OhttpMediaType::Response
+    );
+    assert_eq!(
+        parse_media_type("message/ohttp-chunked-res"),
+        OhttpMediaType::ChunkedResponse
+    );
+    assert_eq!(
+        parse_media_type("Text/HTML; charset=utf-8"),
+        OhttpMediaType::Other("text/html".to_owned())
+    );
+    assert_eq!(parse_media_type(""), OhttpMediaType::Other(String::new()));
+}
+
+fn check_content_type_c(header_value: &str, mode: libc::c_int) -> bool {
+    let header_value = CString::new(header_value).unwrap();
+    unsafe { check_response_content_type_ffi(header_value.as_ptr(), mode) }
+}
+
+#[test]
+fn content_types_follow_the_mode() {
+    let modes = [
+        (
+            OHTTP_MODE_ONE_SHOT,
+            "message/ohttp-req",
+            "message/ohttp-res",
+        ),
+        (
+            OHTTP_MODE_CHUNKED,
+            "message/ohttp-chunked-req",
+            "message/ohttp-chunked-res",
+        ),
+    ];
+    for (mode, request, response) in modes {
+        let expected = expected_content_type_for_mode_ffi(mode);
+        assert!(!expected.is_null());
+        assert_eq!(
+            unsafe { CStr::from_ptr(expected) }.to_str().unwrap(),
+            request
+        );
+
+        assert!(check_content_type_c(response, mode));
+        assert!(check_content_type_c(
+            &format!(" {}; charset=binary", response.to_ascii_uppercase()),
+            mode
+        ));
+        assert!(!check_content_type_c(request, mode));
+        assert!(!check_content_type_c("text/html", mode));
+    }
+    assert!(!check_content_type_c(
+        "message/ohttp-res",
+        OHTTP_MODE_CHUNKED
+    ));
+    assert!(!check_content_type_c(
+        "message/ohttp-chunked-res",
+        OHTTP_MODE_ONE_SHOT
+    ));
+
+    assert!(expected_content_type_for_mode_ffi(7).is_null());
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+    assert!(!check_content_type_c("message/ohttp-res", 7));
+    assert_eq!(
+        last_error_code(),
+        ClientErrorKind::InvalidArgument as libc::c_int
+    );
+    assert!(!unsafe { check_response_content_type_ffi(ptr::null(), OHTTP_MODE_ONE_SHOT) });
+}
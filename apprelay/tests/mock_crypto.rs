@@ -0,0 +1,259 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Context lifecycle and error mapping driven through `MockCryptoProvider`, without HPKE.
+//! Run with `cargo test --features mock-crypto --test mock_crypto`.
+
+use apprelay::config::{KeyConfig, SymmetricSuite, AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256};
+use apprelay::crypto::{CryptoProvider, MockCryptoProvider, ResponseOpener};
+use apprelay::error_ffi::last_error_code;
+use apprelay::{
+    encapsulate_into_context_with_provider, request_context_message_drop_ffi,
+    request_context_message_len_ffi, request_context_new_ffi, ClientError, ClientErrorKind,
+    RequestContext,
+};
+
+mod common;
+
+const KEY_ID: u8 = 3;
+
+fn config() -> Vec<u8> {
+    common::config_with_key_id(KEY_ID)
+}
+
+fn error_kind<T>(result: Result<T, ClientError>) -> ClientErrorKind {
+    match result {
+        Ok(_) => panic!("expected an error"),
+        Err(err) => err.kind(),
+    }
+}
+
+#[test]
+fn mock_round_trip() {
+    let context =
+        RequestContext::with_provider(&MockCryptoProvider::default(), &config(), b"hello").unwrap();
+    assert_eq!(context.key_id(), Some(KEY_ID));
+    assert_eq!(context.enc(), Some(&[0; 32][..]));
+    assert!(context.encapsulated_request().ends_with(b"hello"));
+
+    let (_, decapsulator) = context.into_parts().unwrap();
+    let response = [7; 48];
+    assert_eq!(decapsulator.decapsulate(&response).unwrap(), response);
+}
+
+#[test]
+fn second_decapsulation_is_rejected() {
+    let mut context =
+        RequestContext::with_provider(&MockCryptoProvider::default(), &config(), b"hello").unwrap();
+    let response = [7; 48];
+    assert_eq!(context.decapsulate(&response).unwrap(), response);
+    assert!(context.encapsulated_request().is_empty());
+
+    match context.decapsulate(&response) {
+        Err(ClientError::InvalidArgument(reason)) => {
+            assert_eq!(reason, "response already decapsulated")
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // A failed decapsulation consumes the HPKE state as well.
+    let provider = MockCryptoProvider {
+        fail_open: true,
+        ..Default::default()
+    };
+    let mut context = RequestContext::with_provider(&provider, &config(), b"hello").unwrap();
+    assert_eq!(
+        error_kind(context.decapsulate(&response)),
+        ClientErrorKind::ResponseAuthFailed
+    );
+    assert_eq!(
+        error_kind(context.decapsulate(&response)),
+        ClientErrorKind::InvalidArgument
+    );
+}
+
+#[test]
+fn encapsulation_failure_is_reported() {
+    let provider = MockCryptoProvider {
+        fail_encapsulate: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        error_kind(RequestContext::with_provider(
+            &provider,
+            &config(),
+            b"hello"
+        )),
+        ClientErrorKind::EncapsulationFailed
+    );
+}
+
+#[test]
+fn malformed_config_fails_before_the_provider() {
+    let provider = MockCryptoProvider {
+        fail_encapsulate: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        error_kind(RequestContext::with_provider(
+            &provider,
+            &config()[1..],
+            b"hello"
+        )),
+        ClientErrorKind::MalformedConfig
+    );
+}
+
+#[test]
+fn short_response_is_truncated_before_opening() {
+    let provider = MockCryptoProvider {
+        fail_open: true,
+        ..Default::default()
+    };
+    let (_, decapsulator) = RequestContext::with_provider(&provider, &config(), b"hello")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    assert_eq!(
+        error_kind(decapsulator.decapsulate(&[0; 8])),
+        ClientErrorKind::ResponseTruncated
+    );
+}
+
+#[test]
+fn open_failure_is_an_authentication_failure() {
+    let provider = MockCryptoProvider {
+        fail_open: true,
+        ..Default::default()
+    };
+    let (_, decapsulator) = RequestContext::with_provider(&provider, &config(), b"hello")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    assert_eq!(
+        error_kind(decapsulator.decapsulate(&[0; 48])),
+        ClientErrorKind::ResponseAuthFailed
+    );
+}
+
+#[test]
+fn unparsable_response_is_a_decapsulation_failure() {
+    let provider = MockCryptoProvider {
+        fail_open_malformed: true,
+        ..Default::default()
+    };
+    let (_, decapsulator) = RequestContext::with_provider(&provider, &config(), b"hello")
+        .unwrap()
+        .into_parts()
+        .unwrap();
+    assert_eq!(
+        error_kind(decapsulator.decapsulate(&[0; 48])),
+        ClientErrorKind::DecapsulationFailed
+    );
+}
+
+#[test]
+fn unavailable_rng_fails_encapsulation() {
+    let provider = MockCryptoProvider {
+        fail_rng: true,
+        ..Default::default()
+    };
+    match RequestContext::with_provider(&provider, &config(), b"hello") {
+        Err(err @ ClientError::RngUnavailable(_)) => {
+            assert_eq!(err.kind(), ClientErrorKind::RngUnavailable);
+            let source = std::error::Error::source(&err).expect("the getrandom error");
+            assert_eq!(
+                source.downcast_ref::<getrandom::Error>(),
+                Some(&getrandom::Error::UNSUPPORTED)
+            );
+        }
+        other => panic!("expected RngUnavailable, got {:?}", other.err()),
+    }
+}
+
+/// Encapsulates with a stale parse offering another AEAD than the selected suite, as a
+/// configuration cache returning an outdated entry would.
+struct StaleProvider;
+
+impl CryptoProvider for StaleProvider {
+    fn encapsulate(
+        &self,
+        config: &KeyConfig,
+        msg: &[u8],
+    ) -> Result<(Vec<u8>, Box<dyn ResponseOpener>), ClientError> {
+        let mut stale = config.clone();
+        stale.symmetric = vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_CHACHA20_POLY1305,
+        }];
+        MockCryptoProvider::default().encapsulate(&stale, msg)
+    }
+}
+
+#[cfg(feature = "suite-checks")]
+#[test]
+fn stale_suite_is_rejected() {
+    assert_eq!(
+        error_kind(RequestContext::with_provider(
+            &StaleProvider,
+            &config(),
+            b"hello"
+        )),
+        ClientErrorKind::SuiteMismatch
+    );
+}
+
+#[cfg(all(not(feature = "suite-checks"), debug_assertions))]
+#[test]
+#[should_panic(expected = "does not match the selected suite")]
+fn stale_suite_asserts_in_debug_builds() {
+    let _ = RequestContext::with_provider(&StaleProvider, &config(), b"hello");
+}
+
+/// Encapsulates `b"hello"` into a pooled context with `provider`, returning the status and
+/// the error code it left behind.
+fn encapsulate_into_pooled(provider: &dyn CryptoProvider) -> (libc::c_int, libc::c_int) {
+    let config = config();
+    unsafe {
+        let context = request_context_new_ffi();
+        let status = encapsulate_into_context_with_provider(
+            provider,
+            context,
+            config.as_ptr(),
+            config.len(),
+            b"hello".as_ptr(),
+            5,
+        );
+        let code = last_error_code();
+        // The context stays live and empty whatever happened.
+        assert_eq!(request_context_message_len_ffi(context), 0);
+        request_context_message_drop_ffi(context);
+        (status, code)
+    }
+}
+
+#[test]
+fn failed_refill_of_a_pooled_context_returns_an_error() {
+    let provider = MockCryptoProvider {
+        fail_encapsulate: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        encapsulate_into_pooled(&provider),
+        (-1, ClientErrorKind::EncapsulationFailed as libc::c_int)
+    );
+
+    // Debug builds without `suite-checks` panic on a stale suite, which must not unwind
+    // into the C caller.
+    let expected = if cfg!(feature = "suite-checks") {
+        ClientErrorKind::SuiteMismatch
+    } else if cfg!(debug_assertions) {
+        ClientErrorKind::SafePanic
+    } else {
+        return;
+    };
+    assert_eq!(
+        encapsulate_into_pooled(&StaleProvider),
+        (-1, expected as libc::c_int)
+    );
+}
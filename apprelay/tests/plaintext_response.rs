@@ -0,0 +1,63 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Parsing a BHTTP response wrapped by `response_context_from_plaintext_ffi`.
+//! Run with `cargo test --features testutil --test plaintext_response`.
+
+use apprelay::bhttp::{
+    bhttp_response_body_ffi, bhttp_response_drop_ffi, bhttp_response_header_count_ffi,
+    bhttp_response_header_name_ffi, bhttp_response_header_value_ffi, bhttp_response_status_ffi,
+    bhttp_response_trailer_count_ffi, parse_bhttp_response_ffi,
+};
+use apprelay::{
+    response_context_drop_ffi, response_context_from_plaintext_ffi, response_context_message_ffi,
+    response_context_message_len_ffi, BytesView,
+};
+
+/// A known-length `200 OK` with two header fields, a 5 byte body and no trailers.
+const RESPONSE: &[u8] = b"\x01\x40\xc8\
+    \x24\x0ccontent-type\x0atext/plain\x07x-trace\x03abc\
+    \x05hello\
+    \x00";
+
+unsafe fn view<'a>(view: BytesView) -> &'a [u8] {
+    std::slice::from_raw_parts(view.ptr, view.len)
+}
+
+#[test]
+fn known_response_parses_from_the_context() {
+    unsafe {
+        let context = response_context_from_plaintext_ffi(RESPONSE.as_ptr(), RESPONSE.len());
+        assert!(!context.is_null());
+        let plaintext = std::slice::from_raw_parts(
+            response_context_message_ffi(context),
+            response_context_message_len_ffi(context),
+        );
+        assert_eq!(plaintext, RESPONSE);
+
+        let response = parse_bhttp_response_ffi(plaintext.as_ptr(), plaintext.len());
+        assert!(!response.is_null());
+        assert_eq!(bhttp_response_status_ffi(response), 200);
+        assert_eq!(bhttp_response_header_count_ffi(response), 2);
+        let headers: Vec<(&[u8], &[u8])> = (0..2)
+            .map(|i| {
+                (
+                    view(bhttp_response_header_name_ffi(response, i)),
+                    view(bhttp_response_header_value_ffi(response, i)),
+                )
+            })
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                (&b"content-type"[..], &b"text/plain"[..]),
+                (&b"x-trace"[..], &b"abc"[..]),
+            ]
+        );
+        assert_eq!(view(bhttp_response_body_ffi(response)), b"hello");
+        assert_eq!(bhttp_response_trailer_count_ffi(response), 0);
+
+        bhttp_response_drop_ffi(response);
+        response_context_drop_ffi(context);
+    }
+}
@@ -0,0 +1,35 @@
+// Copyright (c) 2022 Cloudflare, Inc. All rights reserved.
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Warming up the crypto backend before the first request.
+
+use apprelay::config::{
+    KeyConfig, SymmetricSuite, AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256, KEM_X25519_SHA256,
+};
+use apprelay::{apprelay_warmup_ffi, encapsulate_request_ffi, request_context_message_drop_ffi};
+
+#[test]
+fn warmup_is_idempotent_and_encapsulation_follows() {
+    assert_eq!(apprelay_warmup_ffi(), 0);
+    assert_eq!(apprelay_warmup_ffi(), 0);
+
+    let config = KeyConfig::new(
+        4,
+        KEM_X25519_SHA256,
+        vec![4; 32],
+        vec![SymmetricSuite {
+            kdf: KDF_HKDF_SHA256,
+            aead: AEAD_CHACHA20_POLY1305,
+        }],
+    )
+    .unwrap()
+    .encode();
+    let msg = b"GET / HTTP/1.1";
+    unsafe {
+        let context =
+            encapsulate_request_ffi(config.as_ptr(), config.len(), msg.as_ptr(), msg.len());
+        assert!(!context.is_null());
+        assert_eq!((*context).key_id(), Some(4));
+        request_context_message_drop_ffi(context);
+    }
+}